@@ -0,0 +1,52 @@
+use std::sync;
+use std::task;
+
+use charm::model::datapath;
+use charm::model::datapath::DataPathExt;
+use charm::model::space;
+
+use criterion::criterion_group;
+use criterion::criterion_main;
+
+const SPACE_SIZE: u64 = 0x100000;
+
+/// A file with `depth` small edits scattered all over it, like after a long editing session.
+fn edited(depth: u64) -> datapath::DataPath {
+    let space = sync::Arc::new(space::AddressSpace::from(space::buffer::BufferAddressSpace::new((0..SPACE_SIZE).map(|i| i as u8).collect(), "bench")));
+
+    let mut datapath: datapath::DataPath = imbl::vector![
+        datapath::LoadSpaceFilter::new_complete(space, 0, 0, None, 0x1000, std::num::NonZeroUsize::new(64).unwrap()).to_filter(),
+    ].into();
+
+    for i in 0..depth {
+        datapath.push_back(datapath::OverwriteFilter {
+            offset: (i * 0x1337) % SPACE_SIZE,
+            bytes: vec![0xaa; 4],
+        }.to_filter());
+    }
+
+    datapath
+}
+
+/// Fetches as much as a wide hexdump shows at once.
+fn fetch(c: &mut criterion::Criterion) {
+    let mut group = c.benchmark_group("fetch");
+
+    for depth in [0, 16, 256, 4096] {
+        let datapath = edited(depth);
+        let mut records = vec![datapath::ByteRecord::default(); 0x2000];
+        let mut cx = task::Context::from_waker(futures::task::noop_waker_ref());
+
+        /* get everything into the cache and the datapath's index built first, so we're only measuring the fetch */
+        datapath.fetch(datapath::ByteRecordRange::new(0x8000, &mut records), &mut cx);
+
+        group.bench_with_input(criterion::BenchmarkId::from_parameter(depth), &datapath, |b, datapath| b.iter(|| {
+            datapath.fetch(datapath::ByteRecordRange::new(0x8000, &mut records), &mut cx);
+        }));
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, fetch);
+criterion_main!(benches);
@@ -459,6 +459,22 @@ impl Tokenizer {
         match &change.ty {
             change::ChangeType::AlterNode { .. } => {},
             change::ChangeType::AlterNodesBulk { .. } => {},
+            change::ChangeType::StackFilter { .. } => {},
+            change::ChangeType::ReplaceTopFilter { .. } => {},
+            change::ChangeType::AddFilter { .. } => {},
+            change::ChangeType::RemoveFilter { .. } => {},
+            change::ChangeType::ReplaceFilter { .. } => {},
+            change::ChangeType::ReorderFilter { .. } => {},
+            change::ChangeType::ReplaceDatapath { .. } => {},
+            change::ChangeType::RewriteDatapath { .. } => {},
+
+            /* Data was inserted or removed, possibly shifting where we were. */
+            change::ChangeType::InsertData { .. }
+                | change::ChangeType::RemoveInsertedData { .. }
+                | change::ChangeType::DeleteData { .. }
+                | change::ChangeType::RestoreDeletedData { .. } => if let Some(offset) = offset.as_mut() {
+                change.update_offset(new_root, &stack_state.current_path, offset);
+            },
             
             change::ChangeType::InsertNode { parent: affected_path, index: affected_index, child: new_childhood } if affected_path == &stack_state.current_path => {
                 /* A new child was added to the node we're on. */
@@ -597,6 +613,18 @@ impl Tokenizer {
         match &change.ty {
             change::ChangeType::AlterNode { .. } => state.push(child_index),
             change::ChangeType::AlterNodesBulk { .. } => state.push(child_index),
+            change::ChangeType::StackFilter { .. } => state.push(child_index),
+            change::ChangeType::ReplaceTopFilter { .. } => state.push(child_index),
+            change::ChangeType::InsertData { .. } => state.push(child_index),
+            change::ChangeType::RemoveInsertedData { .. } => state.push(child_index),
+            change::ChangeType::DeleteData { .. } => state.push(child_index),
+            change::ChangeType::RestoreDeletedData { .. } => state.push(child_index),
+            change::ChangeType::AddFilter { .. } => state.push(child_index),
+            change::ChangeType::RemoveFilter { .. } => state.push(child_index),
+            change::ChangeType::ReplaceFilter { .. } => state.push(child_index),
+            change::ChangeType::ReorderFilter { .. } => state.push(child_index),
+            change::ChangeType::ReplaceDatapath { .. } => state.push(child_index),
+            change::ChangeType::RewriteDatapath { .. } => state.push(child_index),
             change::ChangeType::InsertNode { parent: path, index: after_child, child: _ } => {
                 if path == &state.current_path && child_index >= *after_child {
                     state.push(child_index + 1);
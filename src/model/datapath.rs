@@ -1,4 +1,14 @@
+pub mod compact;
+pub mod decompress;
+pub mod export;
+pub mod index;
+pub mod patch;
+pub mod provenance;
+pub mod write_back;
+
+use std::fmt;
 use std::iter;
+use std::ops;
 use std::string;
 use std::sync;
 use std::task;
@@ -20,8 +30,27 @@ pub struct ByteRecord {
     pub loaded: bool, /* Touched by a LoadSpaceEdit */
     pub overwritten: bool, /* Touched by an OverwriteEdit */
     pub inserted: bool, /* Touched by an InsertEdit */
-    pub moved: bool, /* Touched by a MoveEdit or trailing end of an InsertEdit */
-    pub error: bool, /* Some kind of error was encountered on the datapath. Probably an I/O error. */
+    pub moved: bool, /* Touched by a MoveEdit or trailing end of an InsertEdit or DeleteEdit */
+    pub transformed: bool, /* Touched by a TransformEdit */
+    pub decompressed: bool, /* Produced by a DecompressFilter */
+    pub error: Option<ByteError>, /* Why this byte couldn't be read, if it couldn't. */
+}
+
+/// Why a byte couldn't be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteError {
+    /// The address space tried to read it and failed. We keep the OS error code around, if there was one, so we can
+    /// describe the error properly later.
+    Io {
+        kind: std::io::ErrorKind,
+        os_error: Option<i32>,
+    },
+    /// The address space doesn't have anything at this address, like an unmapped page in a process.
+    Unmapped,
+    /// The address is past the end of the address space.
+    EndOfSpace,
+    /// A [DecompressFilter] couldn't decompress any further than this.
+    Decompression(decompress::DecompressionError),
 }
 
 impl ByteRecord {
@@ -42,21 +71,62 @@ impl ByteRecord {
     }
 }
 
+impl ByteError {
+    pub fn from_io_error(e: &std::io::Error) -> ByteError {
+        ByteError::Io {
+            kind: e.kind(),
+            os_error: e.raw_os_error(),
+        }
+    }
+
+    pub fn describe(&self) -> string::String {
+        match self {
+            ByteError::Io { os_error: Some(code), .. } => format!("I/O error: {}", std::io::Error::from_raw_os_error(*code)),
+            ByteError::Io { kind, .. } => format!("I/O error: {}", kind),
+            ByteError::Unmapped => "Nothing is mapped at this address.".to_string(),
+            ByteError::EndOfSpace => "This address is past the end of the address space.".to_string(),
+            ByteError::Decompression(e) => e.describe().to_string(),
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct ByteRecordRange<'a> {
     addr: u64,
     out: &'a mut [ByteRecord]
 }
 
+/// Tells the datapath which part of it is being looked at and which way it's moving, so that slow address spaces can
+/// start fetching what's about to be looked at next.
+#[derive(Debug, Clone, Copy)]
+pub struct PrefetchHint {
+    pub addr: u64,
+    pub size: u64,
+    pub direction: space::cache::Direction,
+    /// How far past the edge of the range to read ahead, in bytes.
+    pub distance: u64,
+}
+
 #[derive(Clone, Debug)]
 pub enum Filter {
     LoadSpace(LoadSpaceFilter),
     Overwrite(OverwriteFilter),
     Move(MoveFilter),
     Insert(InsertFilter),
+    Transform(TransformFilter),
+    Decompress(DecompressFilter),
+    Delete(DeleteFilter),
+    Disabled(DisabledFilter),
 }
 
-pub type DataPath = imbl::Vector<Filter>;
+/// The filters that data goes through on its way into the document, from the bottom up. This works like the list of
+/// filters itself, except that it keeps an [index::Index] of them around for fetches to use, and throws it out
+/// whenever the filters might change.
+#[derive(Clone, Default)]
+pub struct DataPath {
+    filters: imbl::Vector<Filter>,
+    index: sync::OnceLock<sync::Arc<index::Index>>,
+}
 
 impl Filter {
     pub fn stack(a: &Filter, b: &Filter) -> Option<Filter> {
@@ -66,27 +136,141 @@ impl Filter {
             (Filter::Move(ai), Filter::Move(bi)) => MoveFilter::stack(ai, bi).map(Filter::Move),
             (Filter::Insert(ai), Filter::Insert(bi)) => InsertFilter::stack(ai, bi).map(Filter::Insert),
             (Filter::Insert(ai), Filter::Overwrite(bi)) => InsertFilter::stack_overwrite(ai, bi).map(Filter::Insert),
+            (Filter::Transform(ai), Filter::Transform(bi)) => TransformFilter::stack(ai, bi).map(Filter::Transform),
+            (Filter::Delete(ai), Filter::Delete(bi)) => DeleteFilter::stack(ai, bi).map(Filter::Delete),
             _ => None,
         }
     }
     
-    fn fetch_next<'a, 'b, 'c>(mut iter: impl iter::Iterator<Item = &'a Filter> + Clone, range: &'c mut ByteRecordRange<'b>, cx: &mut task::Context) {
-        if let Some(filter) = iter.next() {
+    fn fetch_next<'a, 'b, 'c>(mut iter: index::Below<'a>, range: &'c mut ByteRecordRange<'b>, cx: &mut task::Context) {
+        /* Filters that can't affect the range would just pass it along, so the index takes us straight past them.
+         * This keeps long stacks of small edits from costing anything away from where they are, and keeps the
+         * recursion shallow. */
+        let (begin, end) = (range.addr, range.addr + range.out.len() as u64);
+
+        if let Some(filter) = iter.next_affecting(begin, end) {
             filter.fetch(iter, range, cx)
         }
     }
 
+    /// The offset and size of the addresses where fetching through this filter could turn out differently than
+    /// fetching from underneath it directly, or None if there aren't any. Filters that shift addresses affect
+    /// everything after them, so they don't have a size.
+    fn affected(&self) -> Option<(u64, Option<u64>)> {
+        match self {
+            Filter::LoadSpace(f) => Some((f.load_offset, f.size)),
+            Filter::Overwrite(f) => Some((f.offset, Some(f.bytes.len() as u64))),
+            /* can't fetch through these yet, so don't hide that */
+            Filter::Move(_) => Some((0, None)),
+            Filter::Insert(f) => Some((f.offset, None)),
+            Filter::Transform(f) => Some((f.offset, Some(f.size))),
+            /* we might not know how big the output is yet */
+            Filter::Decompress(f) => Some((f.offset, None)),
+            Filter::Delete(f) => Some((f.offset, None)),
+            Filter::Disabled(_) => None,
+        }
+    }
+
     /* Before you enter a fetch chain, you should clear the result array. */
-    fn fetch<'a, 'b, 'c>(&self, iter: impl iter::Iterator<Item = &'a Filter> + Clone, range: &'c mut ByteRecordRange<'b>, cx: &mut task::Context) {
+    fn fetch<'a, 'b, 'c>(&self, iter: index::Below<'a>, range: &'c mut ByteRecordRange<'b>, cx: &mut task::Context) {
         match self {
             Filter::LoadSpace(f) => f.fetch(iter, range, cx),
             Filter::Overwrite(f) => f.fetch(iter, range, cx),
             Filter::Move(f) => f.fetch(iter, range, cx),
             Filter::Insert(f) => f.fetch(iter, range, cx),
+            Filter::Transform(f) => f.fetch(iter, range, cx),
+            Filter::Decompress(f) => f.fetch(iter, range, cx),
+            Filter::Delete(f) => f.fetch(iter, range, cx),
+            Filter::Disabled(_) => Filter::fetch_next(iter, range, cx),
         }
     }
 
-    pub fn fetch_chain<'a>(iter: impl iter::Iterator<Item = &'a Filter> + Clone, mut range: ByteRecordRange, cx: &mut task::Context) {
+    /// Looks through a disabled filter to the one it's holding onto.
+    pub fn inner(&self) -> &Filter {
+        match self {
+            Filter::Disabled(f) => &f.filter,
+            f => f,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !matches!(self, Filter::Disabled(_))
+    }
+
+    /// Disables an enabled filter, or enables a disabled one.
+    pub fn toggled(&self) -> Filter {
+        match self {
+            Filter::Disabled(f) => (*f.filter).clone(),
+            f => DisabledFilter { filter: Box::new(f.clone()) }.to_filter(),
+        }
+    }
+
+    /// Whether this filter changes the addresses of bytes that it didn't otherwise touch. Filters stacked on top of
+    /// one of these were placed with its effect in mind, so they can't be moved past it. Disabled filters count too,
+    /// since they can be enabled again.
+    pub fn shifts_addresses(&self) -> bool {
+        matches!(self.inner(), Filter::Move(_) | Filter::Insert(_) | Filter::Delete(_))
+    }
+
+    /// Whether this filter was made along with a change to the size of the structure, so it can't be taken out of the
+    /// datapath or put back in on its own.
+    pub fn resizes_structure(&self) -> bool {
+        matches!(self.inner(), Filter::Insert(_) | Filter::Delete(_))
+    }
+
+    /// Whether `other` inserts or deletes the same bytes as this filter, so that either one fits the structure the
+    /// same way.
+    pub fn resizes_like(&self, other: &Filter) -> bool {
+        match (self, other) {
+            (Filter::Insert(a), Filter::Insert(b)) => a.offset == b.offset && a.bytes.len() == b.bytes.len(),
+            (Filter::Delete(a), Filter::Delete(b)) => a.offset == b.offset && a.size == b.size,
+            _ => false,
+        }
+    }
+
+    /// A copy of this filter that affects the bytes at `addr` instead, or None if it can't be moved on its own. Filters
+    /// that shift addresses were made along with a change to the structure, so moving them would leave the two out of
+    /// step.
+    pub fn relocated(&self, addr: u64) -> Option<Filter> {
+        let mut filter = self.clone();
+
+        match &mut filter {
+            Filter::LoadSpace(f) => f.load_offset = addr,
+            Filter::Overwrite(f) => f.offset = addr,
+            Filter::Transform(f) => f.offset = addr,
+            Filter::Decompress(f) => f.offset = addr,
+            Filter::Move(_) | Filter::Insert(_) | Filter::Delete(_) => return None,
+            Filter::Disabled(f) => *f.filter = f.filter.relocated(addr)?,
+        }
+
+        Some(filter)
+    }
+
+    /// The generation of the address space that this filter loads from, if it loads from one.
+    fn space_generation(&self) -> Option<u64> {
+        match self {
+            Filter::LoadSpace(f) => Some(f.cache.space.generation()),
+            _ => None,
+        }
+    }
+
+    /// Whether two filters would do the same thing to the data underneath them. Filters that keep a cache are only the
+    /// same if they share it.
+    fn same_as(&self, other: &Filter) -> bool {
+        match (self, other) {
+            (Filter::LoadSpace(a), Filter::LoadSpace(b)) => sync::Arc::ptr_eq(&a.cache, &b.cache) && a.load_offset == b.load_offset && a.space_offset == b.space_offset && a.size == b.size,
+            (Filter::Overwrite(a), Filter::Overwrite(b)) => a.offset == b.offset && a.bytes == b.bytes,
+            (Filter::Move(a), Filter::Move(b)) => a.from == b.from && a.to == b.to && a.size == b.size,
+            (Filter::Insert(a), Filter::Insert(b)) => a.offset == b.offset && a.bytes == b.bytes,
+            (Filter::Transform(a), Filter::Transform(b)) => a.offset == b.offset && a.size == b.size && a.op == b.op && a.key == b.key,
+            (Filter::Decompress(a), Filter::Decompress(b)) => sync::Arc::ptr_eq(&a.cache, &b.cache) && a.offset == b.offset && a.source_offset == b.source_offset && a.source_size == b.source_size && a.format == b.format,
+            (Filter::Delete(a), Filter::Delete(b)) => a.offset == b.offset && a.size == b.size,
+            (Filter::Disabled(a), Filter::Disabled(b)) => a.filter.same_as(&b.filter),
+            _ => false,
+        }
+    }
+
+    pub fn fetch_chain(iter: index::Below, mut range: ByteRecordRange, cx: &mut task::Context) {
         range.out.fill(ByteRecord::default());
         Filter::fetch_next(iter, &mut range, cx)
     }
@@ -98,12 +282,23 @@ impl Filter {
         }
     }
 
+    fn prefetch(&self, hint: &PrefetchHint, cx: &mut task::Context) {
+        match self {
+            Filter::LoadSpace(f) => f.prefetch(hint, cx),
+            _ => {}
+        }
+    }
+
     pub fn human_details(&self) -> string::String {
         match self {
             Filter::LoadSpace(f) => f.human_details(),
             Filter::Overwrite(f) => f.human_details(),
             Filter::Move(f) => f.human_details(),
             Filter::Insert(f) => f.human_details(),
+            Filter::Transform(f) => f.human_details(),
+            Filter::Decompress(f) => f.human_details(),
+            Filter::Delete(f) => f.human_details(),
+            Filter::Disabled(f) => f.filter.human_details(),
         }
     }
 
@@ -113,6 +308,10 @@ impl Filter {
             Filter::Overwrite(f) => f.human_affects_addr(),
             Filter::Move(f) => f.human_affects_addr(),
             Filter::Insert(f) => f.human_affects_addr(),
+            Filter::Transform(f) => f.human_affects_addr(),
+            Filter::Decompress(f) => f.human_affects_addr(),
+            Filter::Delete(f) => f.human_affects_addr(),
+            Filter::Disabled(f) => f.filter.human_affects_addr(),
         }
     }
 
@@ -122,6 +321,10 @@ impl Filter {
             Filter::Overwrite(f) => f.human_affects_size(),
             Filter::Move(f) => f.human_affects_size(),
             Filter::Insert(f) => f.human_affects_size(),
+            Filter::Transform(f) => f.human_affects_size(),
+            Filter::Decompress(f) => f.human_affects_size(),
+            Filter::Delete(f) => f.human_affects_size(),
+            Filter::Disabled(f) => f.filter.human_affects_size(),
         }
     }
 }
@@ -129,6 +332,8 @@ impl Filter {
 pub trait DataPathExt {
     fn poll(&self, cx: &mut task::Context) -> bool;
     fn fetch(&self, range: ByteRecordRange, cx: &mut task::Context);
+    fn prefetch(&self, hint: &PrefetchHint, cx: &mut task::Context);
+    fn provenance(&self, addr: u64) -> vec::Vec<provenance::Contribution>;
 }
 
 impl DataPathExt for DataPath {
@@ -141,7 +346,96 @@ impl DataPathExt for DataPath {
     }
     
     fn fetch(&self, range: ByteRecordRange, cx: &mut task::Context) {
-        Filter::fetch_chain(self.iter().rev(), range, cx)
+        Filter::fetch_chain(index::Below::top(self), range, cx)
+    }
+
+    /* This is only a hint, so we don't bother accounting for filters that move data around on top of the spaces. */
+    fn prefetch(&self, hint: &PrefetchHint, cx: &mut task::Context) {
+        for filter in self.iter() {
+            filter.prefetch(hint, cx);
+        }
+    }
+
+    fn provenance(&self, addr: u64) -> vec::Vec<provenance::Contribution> {
+        provenance::provenance(self, addr)
+    }
+}
+
+impl DataPath {
+    pub fn new() -> DataPath {
+        DataPath::default()
+    }
+
+    fn index(&self) -> &index::Index {
+        self.index.get_or_init(|| sync::Arc::new(index::Index::new(self)))
+    }
+}
+
+impl ops::Deref for DataPath {
+    type Target = imbl::Vector<Filter>;
+
+    fn deref(&self) -> &imbl::Vector<Filter> {
+        &self.filters
+    }
+}
+
+impl ops::DerefMut for DataPath {
+    fn deref_mut(&mut self) -> &mut imbl::Vector<Filter> {
+        /* the filters are about to change */
+        self.index.take();
+        &mut self.filters
+    }
+}
+
+impl From<imbl::Vector<Filter>> for DataPath {
+    fn from(filters: imbl::Vector<Filter>) -> DataPath {
+        DataPath {
+            filters,
+            index: Default::default(),
+        }
+    }
+}
+
+impl iter::FromIterator<Filter> for DataPath {
+    fn from_iter<I: iter::IntoIterator<Item = Filter>>(iter: I) -> DataPath {
+        DataPath::from(iter.into_iter().collect::<imbl::Vector<Filter>>())
+    }
+}
+
+impl<'a> iter::IntoIterator for &'a DataPath {
+    type Item = &'a Filter;
+    type IntoIter = imbl::vector::Iter<'a, Filter>;
+
+    fn into_iter(self) -> imbl::vector::Iter<'a, Filter> {
+        self.filters.iter()
+    }
+}
+
+impl fmt::Debug for DataPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.filters.fmt(f)
+    }
+}
+
+/// Gives decompression filters in `after` a cache of their own if anything underneath them is different than it was in
+/// `before`, instead of letting them keep sharing one with their counterparts in `before`. This should be done whenever
+/// a datapath is derived from another one.
+pub fn refresh_decompression(before: &DataPath, after: &mut DataPath) {
+    if !after.iter().any(|filter| matches!(filter.inner(), Filter::Decompress(_))) {
+        return;
+    }
+
+    /* the first filter that's different might be a decompression filter that came from somewhere else */
+    let unchanged = before.iter().zip(after.iter()).take_while(|(a, b)| a.same_as(b)).count();
+
+    for filter in after.iter_mut().skip(unchanged) {
+        match filter {
+            Filter::Decompress(f) => f.cache = Default::default(),
+            Filter::Disabled(DisabledFilter { filter }) => if let Filter::Decompress(f) = &mut **filter {
+                f.cache = Default::default();
+            },
+            _ => {},
+        }
     }
 }
 
@@ -253,18 +547,63 @@ pub struct InsertFilter {
     pub bytes: vec::Vec<u8>,
 }
 
+/// Decodes bytes in an extent by combining each one with a byte of a repeating key, like the simple obfuscation that a
+/// lot of formats use. The key starts over at the beginning of the extent.
+#[derive(Clone, Debug)]
+pub struct TransformFilter {
+    pub offset: u64,
+    pub size: u64,
+    pub op: TransformOp,
+    pub key: vec::Vec<u8>,
+}
+
+/// Removes an extent, shifting everything after it down to fill the gap.
+#[derive(Clone, Debug)]
+pub struct DeleteFilter {
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Keeps a filter in the datapath without it doing anything, so that it can be turned back on later.
+#[derive(Clone, Debug)]
+pub struct DisabledFilter {
+    pub filter: Box<Filter>,
+}
+
+/// Shows the decompressed contents of a compressed extent at another address, like past the end of the file. The
+/// source is read and decompressed the first time anything at `offset` or after it is fetched, and the result is kept
+/// around until the address spaces or filters underneath change.
+#[derive(Clone, Debug)]
+pub struct DecompressFilter {
+    pub offset: u64,
+    pub source_offset: u64,
+    pub source_size: u64,
+    pub format: decompress::Format,
+    cache: sync::Arc<parking_lot::Mutex<decompress::Cache>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransformOp {
+    Xor,
+    /// Adds the key byte, wrapping around.
+    Add,
+    /// Subtracts the key byte, wrapping around.
+    Subtract,
+    /// Rotates left by the key byte's number of bits, modulo 8.
+    RotateLeft,
+    /// Rotates right by the key byte's number of bits, modulo 8.
+    RotateRight,
+}
+
 impl LoadSpaceFilter {
     pub fn new_defaults(space: sync::Arc<space::AddressSpace>, load_offset: u64, space_offset: u64) -> LoadSpaceFilter {
-        // TODO: make this const when const unwrap gets stabilized
-        //       https://github.com/rust-lang/rust/issues/67441
-        //const block_count: std::num::NonZeroUsize = std::num::NonZeroUsize::new(1024).unwrap();
-        let block_count = std::num::NonZeroUsize::new(1024).unwrap();
+        let (block_size, block_count) = space::cache::default_geometry();
         
         LoadSpaceFilter {
             load_offset,
             space_offset,
-            size: None,
-            cache: sync::Arc::new(space::cache::SpaceCache::new(space, 0x1000, block_count)), // 4 MiB cache is plenty
+            size: space.size().map(|size| size.saturating_sub(space_offset)),
+            cache: sync::Arc::new(space::cache::SpaceCache::new(space, block_size, block_count)),
         }
     }
 
@@ -285,7 +624,7 @@ impl LoadSpaceFilter {
         space - self.space_offset + self.load_offset
     }
     
-    fn fetch<'a, 'b, 'c>(&self, iter: impl iter::Iterator<Item = &'a Filter> + Clone, range: &'c mut ByteRecordRange<'b>, cx: &mut task::Context) {
+    fn fetch<'a, 'b, 'c>(&self, iter: index::Below<'a>, range: &'c mut ByteRecordRange<'b>, cx: &mut task::Context) {
         let (mut before, overlap, mut after) = range.split3((self.load_offset, self.size));
 
         if !before.is_empty() {
@@ -298,31 +637,59 @@ impl LoadSpaceFilter {
 
         if !overlap.is_empty() {
             let mut lru_guard = self.cache.lock();
-            
-            let mut current_block = None;
 
-            // TODO: optimize this
-            for (i, br) in overlap.out.iter_mut().enumerate() {
-                let required_block_addr = ((overlap.addr + i as u64 - self.load_offset + self.space_offset) / self.cache.block_size) * self.cache.block_size;
+            /* Only look up the size of the space if something comes up short, since it can take a syscall. */
+            let mut space_size = None;
+            let mut unreadable = |space_addr: u64| match *space_size.get_or_insert_with(|| self.cache.space.size()) {
+                Some(size) if space_addr >= size => ByteError::EndOfSpace,
+                _ => ByteError::Unmapped,
+            };
+
+            /* Go a block at a time, so we only look each block up once and can copy straight out of it. */
+            let mut space_addr = self.convert_to_space(overlap.addr);
+            let mut out = overlap.out;
+
+            while !out.is_empty() {
+                let block_addr = (space_addr / self.cache.block_size) * self.cache.block_size;
+                let offset_in_block = (space_addr - block_addr) as usize;
+                let len = std::cmp::min(out.len() as u64, block_addr + self.cache.block_size - space_addr) as usize;
+                let (chunk, rest) = std::mem::take(&mut out).split_at_mut(len);
+
+                for br in chunk.iter_mut() {
+                    br.loaded = true;
+                }
 
-                let block = match current_block {
-                    Some((addr, block)) if addr == required_block_addr => block,
-                    _ => self.cache.fetch_block_with_lock(required_block_addr, &mut lru_guard, cx),
-                };
-                
-                br.loaded = true;
-                
-                match &*block {
-                    space::cache::SpaceCacheEntry::Pending(_) => br.pending = true,
-                    space::cache::SpaceCacheEntry::Finished(space::FetchResult::Ok(bytes)) => br.value = bytes[(self.convert_to_space(overlap.addr + i as u64) - required_block_addr) as usize],
-                    space::cache::SpaceCacheEntry::Finished(space::FetchResult::Partial(bytes)) => match bytes.get((self.convert_to_space(overlap.addr + i as u64) - required_block_addr) as usize) {
-                        Some(b) => br.value = *b,
-                        None => br.error = true,
+                /* how many bytes at the start of the chunk the block has data for */
+                let available = match &*self.cache.fetch_block_with_lock(block_addr, &mut lru_guard, cx) {
+                    space::cache::SpaceCacheEntry::Pending(_) => {
+                        for br in chunk.iter_mut() {
+                            br.pending = true;
+                        }
+                        chunk.len()
+                    },
+                    space::cache::SpaceCacheEntry::Finished(space::FetchResult::Ok(bytes) | space::FetchResult::Partial(bytes)) => {
+                        let bytes = bytes.get(offset_in_block..).unwrap_or(&[]);
+                        for (br, b) in chunk.iter_mut().zip(bytes.iter()) {
+                            br.value = *b;
+                        }
+                        std::cmp::min(chunk.len(), bytes.len())
                     },
-                    space::cache::SpaceCacheEntry::Finished(_) => br.error = true,
+                    space::cache::SpaceCacheEntry::Finished(space::FetchResult::Unreadable) => 0,
+                    space::cache::SpaceCacheEntry::Finished(space::FetchResult::IoError(e)) => {
+                        let error = ByteError::from_io_error(e);
+                        for br in chunk.iter_mut() {
+                            br.error = Some(error);
+                        }
+                        chunk.len()
+                    },
+                };
+
+                for (i, br) in chunk.iter_mut().enumerate().skip(available) {
+                    br.error = Some(unreadable(space_addr + i as u64));
                 }
-                
-                current_block = Some((required_block_addr, block));
+
+                space_addr+= chunk.len() as u64;
+                out = rest;
             }
         }
     }
@@ -343,6 +710,26 @@ impl LoadSpaceFilter {
         self.cache.poll_blocks(cx)
     }
 
+    fn prefetch(&self, hint: &PrefetchHint, cx: &mut task::Context) {
+        let begin = self.load_offset;
+        let end = self.size.map_or(u64::MAX, |size| self.load_offset.saturating_add(size));
+
+        /* clips a range of addresses to the part that we load, converted to space addresses */
+        let clip = |(a, b): (u64, u64)| {
+            let (a, b) = (std::cmp::max(a, begin), std::cmp::min(b, end));
+            (a < b).then(|| (self.convert_to_space(a), self.convert_to_space(b)))
+        };
+        
+        let hint_end = hint.addr.saturating_add(hint.size);
+        let ahead = match hint.direction {
+            space::cache::Direction::Forward => (hint_end, hint_end.saturating_add(hint.distance)),
+            space::cache::Direction::Backward => (hint.addr.saturating_sub(hint.distance), hint.addr),
+        };
+        let keep = (std::cmp::min(hint.addr, ahead.0), std::cmp::max(hint_end, ahead.1));
+
+        self.cache.prefetch(clip(ahead), clip(keep), hint.direction, cx);
+    }
+
     pub fn to_filter(self) -> Filter {
         Filter::LoadSpace(self)
     }
@@ -358,20 +745,27 @@ impl LoadSpaceFilter {
     pub fn cache_block_count(&self) -> std::num::NonZeroUsize {
         self.cache.block_count
     }
+
+    pub fn cache_statistics(&self) -> space::cache::Statistics {
+        self.cache.statistics()
+    }
 }
 
 impl OverwriteFilter {
-    fn fetch<'a, 'b, 'c>(&self, iter: impl iter::Iterator<Item = &'a Filter> + Clone, range: &'c mut ByteRecordRange<'b>, cx: &mut task::Context) {
+    fn fetch<'a, 'b, 'c>(&self, iter: index::Below<'a>, range: &'c mut ByteRecordRange<'b>, cx: &mut task::Context) {
         /* so we set load flags properly */
         Filter::fetch_next(iter, range, cx);
 
-        // TODO: optimize this
-        let addr = range.addr;
-        
-        for (i, br) in range.out.iter_mut().enumerate() {
-            if addr + i as u64 >= self.offset && addr + i as u64 - self.offset < self.bytes.len() as u64 {
+        let begin = std::cmp::max(range.addr, self.offset);
+        let end = std::cmp::min(range.addr + range.out.len() as u64, self.offset + self.bytes.len() as u64);
+
+        if begin < end {
+            let out = &mut range.out[(begin - range.addr) as usize..(end - range.addr) as usize];
+            let bytes = &self.bytes[(begin - self.offset) as usize..(end - self.offset) as usize];
+
+            for (br, b) in out.iter_mut().zip(bytes.iter()) {
                 br.overwritten = true;
-                br.value = self.bytes[(addr + i as u64 - self.offset) as usize];
+                br.value = *b;
             }
         }
     }
@@ -435,7 +829,7 @@ impl OverwriteFilter {
 }
 
 impl MoveFilter {
-    fn fetch<'a, 'b, 'c>(&self, _iter: impl iter::Iterator<Item = &'a Filter>, _range: &'c mut ByteRecordRange<'b>, _cx: &mut task::Context) {
+    fn fetch<'a, 'b, 'c>(&self, _iter: index::Below<'a>, _range: &'c mut ByteRecordRange<'b>, _cx: &mut task::Context) {
         todo!("implement MoveFilter::fetch");
     }
     
@@ -467,7 +861,7 @@ impl MoveFilter {
 }
 
 impl InsertFilter {
-    fn fetch<'a, 'b, 'c>(&self, iter: impl iter::Iterator<Item = &'a Filter> + Clone, range: &'c mut ByteRecordRange<'b>, cx: &mut task::Context) {
+    fn fetch<'a, 'b, 'c>(&self, iter: index::Below<'a>, range: &'c mut ByteRecordRange<'b>, cx: &mut task::Context) {
         let (mut before, mut overlap, mut after) = range.split3((self.offset, Some(self.bytes.len() as u64)));
 
         if !before.is_empty() {
@@ -486,16 +880,11 @@ impl InsertFilter {
         if !overlap.is_empty() {
             /* so we set load flags properly */
             Filter::fetch_next(iter, &mut overlap, cx);
-            
-            // TODO: optimize this
-            let addr = range.addr;
-            
-            for (i, br) in range.out.iter_mut().enumerate() {
-                if addr + i as u64 >= self.offset && addr + i as u64 - self.offset < self.bytes.len() as u64 {
-                    br.inserted = true;
-                    br.value = self.bytes[(addr + i as u64 - self.offset) as usize];
-                }
-            }            
+
+            for (br, b) in overlap.out.iter_mut().zip(self.bytes[(overlap.addr - self.offset) as usize..].iter()) {
+                br.inserted = true;
+                br.value = *b;
+            }
         }
     }
 
@@ -542,3 +931,423 @@ impl InsertFilter {
         Filter::Insert(self)
     }
 }
+
+impl TransformFilter {
+    fn fetch<'a, 'b, 'c>(&self, iter: index::Below<'a>, range: &'c mut ByteRecordRange<'b>, cx: &mut task::Context) {
+        Filter::fetch_next(iter, range, cx);
+
+        if self.key.is_empty() {
+            return;
+        }
+
+        let addr = range.addr;
+        let begin = std::cmp::max(addr, self.offset);
+        let end = std::cmp::min(addr + range.out.len() as u64, self.offset.saturating_add(self.size));
+
+        for i in begin..end {
+            let br = &mut range.out[(i - addr) as usize];
+
+            /* don't make up values for bytes that didn't have any */
+            if br.has_any_value() {
+                br.transformed = true;
+                br.value = self.op.apply(br.value, self.key[((i - self.offset) % self.key.len() as u64) as usize]);
+            }
+        }
+    }
+
+    fn stack(a: &TransformFilter, b: &TransformFilter) -> Option<TransformFilter> {
+        if a.offset != b.offset || a.size != b.size || a.key.len() != b.key.len() {
+            return None;
+        }
+
+        /* Stack if b does the same kind of thing to the same bytes as a */
+        let combine: fn(u8, u8) -> u8 = match (a.op, b.op) {
+            (TransformOp::Xor, TransformOp::Xor) => |x, y| x ^ y,
+            (TransformOp::Add, TransformOp::Add) | (TransformOp::Subtract, TransformOp::Subtract) => |x, y| x.wrapping_add(y),
+            (TransformOp::RotateLeft, TransformOp::RotateLeft) | (TransformOp::RotateRight, TransformOp::RotateRight) => |x, y| x.wrapping_add(y) & 7,
+            _ => return None,
+        };
+
+        Some(TransformFilter {
+            offset: a.offset,
+            size: a.size,
+            op: a.op,
+            key: a.key.iter().zip(b.key.iter()).map(|(x, y)| combine(*x, *y)).collect(),
+        })
+    }
+
+    fn human_details(&self) -> string::String {
+        format!("{} {}", self.op.describe(), util::fmt_hex_slice(&self.key).unwrap_or_else(|_| "error".to_string()))
+    }
+
+    fn human_affects_addr(&self) -> u64 {
+        self.offset
+    }
+
+    fn human_affects_size(&self) -> Option<u64> {
+        Some(self.size)
+    }
+
+    pub fn to_filter(self) -> Filter {
+        Filter::Transform(self)
+    }
+}
+
+impl TransformOp {
+    pub fn apply(&self, value: u8, key: u8) -> u8 {
+        match self {
+            TransformOp::Xor => value ^ key,
+            TransformOp::Add => value.wrapping_add(key),
+            TransformOp::Subtract => value.wrapping_sub(key),
+            TransformOp::RotateLeft => value.rotate_left(key as u32 % 8),
+            TransformOp::RotateRight => value.rotate_right(key as u32 % 8),
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            TransformOp::Xor => "XOR",
+            TransformOp::Add => "Add",
+            TransformOp::Subtract => "Subtract",
+            TransformOp::RotateLeft => "Rotate left",
+            TransformOp::RotateRight => "Rotate right",
+        }
+    }
+}
+
+impl DeleteFilter {
+    fn fetch<'a, 'b, 'c>(&self, iter: index::Below<'a>, range: &'c mut ByteRecordRange<'b>, cx: &mut task::Context) {
+        /* Everything from the offset onwards comes from past the deleted extent. */
+        let (mut before, mut after, _) = range.split3((self.offset, None));
+
+        if !before.is_empty() {
+            Filter::fetch_next(iter.clone(), &mut before, cx);
+        }
+
+        if !after.is_empty() {
+            after.addr+= self.size;
+            Filter::fetch_next(iter, &mut after, cx);
+
+            for br in after.out.iter_mut() {
+                br.moved = true;
+            }
+        }
+    }
+
+    fn stack(a: &DeleteFilter, b: &DeleteFilter) -> Option<DeleteFilter> {
+        if b.offset <= a.offset && a.offset <= b.offset + b.size {
+            /* Stack if b deletes up to or across the gap a left behind */
+            Some(DeleteFilter {
+                offset: b.offset,
+                size: a.size + b.size,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn human_details(&self) -> string::String {
+        format!("0x{:x} bytes", self.size)
+    }
+
+    fn human_affects_addr(&self) -> u64 {
+        self.offset
+    }
+
+    fn human_affects_size(&self) -> Option<u64> {
+        Some(self.size)
+    }
+
+    pub fn to_filter(self) -> Filter {
+        Filter::Delete(self)
+    }
+}
+
+impl DisabledFilter {
+    pub fn to_filter(self) -> Filter {
+        Filter::Disabled(self)
+    }
+}
+
+impl DecompressFilter {
+    pub fn new(format: decompress::Format, source_offset: u64, source_size: u64, offset: u64) -> DecompressFilter {
+        DecompressFilter {
+            offset,
+            source_offset,
+            source_size,
+            format,
+            cache: Default::default(),
+        }
+    }
+
+    fn fetch<'a, 'b, 'c>(&self, iter: index::Below<'a>, range: &'c mut ByteRecordRange<'b>, cx: &mut task::Context) {
+        /* Anything past the end of the decompressed data comes from underneath. We don't know where that is until
+         * we've decompressed everything, so just fetch the whole range. */
+        Filter::fetch_next(iter.clone(), range, cx);
+
+        let addr = range.addr;
+        let end = addr + range.out.len() as u64;
+
+        if end <= self.offset {
+            return;
+        }
+
+        let mut cache = self.cache.lock();
+
+        let Some(inflated) = self.inflate(&mut cache, iter, cx) else {
+            for br in &mut range.out[(std::cmp::max(addr, self.offset) - addr) as usize..] {
+                br.pending = true;
+            }
+            return;
+        };
+
+        for i in std::cmp::max(addr, self.offset)..end {
+            let index = i - self.offset;
+            let br = &mut range.out[(i - addr) as usize];
+
+            if let Some(value) = inflated.bytes.get(index as usize) {
+                *br = ByteRecord {
+                    value: *value,
+                    loaded: true,
+                    decompressed: true,
+                    ..Default::default()
+                };
+            } else if index == inflated.bytes.len() as u64 && inflated.error.is_some() {
+                *br = ByteRecord {
+                    loaded: true,
+                    error: inflated.error,
+                    ..Default::default()
+                };
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Reads as much of the source as is ready, and decompresses it on the blocking pool once all of it has been read.
+    /// Returns None if some of it is still pending, or if it's still being decompressed.
+    fn inflate<'a, 's>(&self, cache: &'s mut decompress::Cache, iter: index::Below<'a>, cx: &mut task::Context) -> Option<&'s decompress::Inflated> {
+        /* If the data underneath us changed, so might have the source. */
+        let generations = iter.clone().filter_map(Filter::space_generation);
+
+        if !cache.generations.iter().copied().eq(generations.clone()) {
+            *cache = decompress::Cache {
+                generations: generations.collect(),
+                attempt: cache.attempt + 1,
+                state: Default::default(),
+            };
+        }
+
+        let attempt = cache.attempt;
+        let state = &mut cache.state;
+
+        if let decompress::State::Reading { source } = &mut *state {
+            let mut records = vec![ByteRecord::default(); std::cmp::min(self.source_size, decompress::CHUNK_SIZE as u64) as usize];
+            /* why the source stopped short, if it did */
+            let mut source_error = None;
+
+            while (source.len() as u64) < self.source_size {
+                let records = &mut records[..std::cmp::min(self.source_size - source.len() as u64, decompress::CHUNK_SIZE as u64) as usize];
+                records.fill(ByteRecord::default());
+                Filter::fetch_next(iter.clone(), &mut ByteRecordRange::new(self.source_offset + source.len() as u64, records), cx);
+
+                let ready = records.iter().position(|br| br.pending || !br.has_any_value()).unwrap_or(records.len());
+                source.extend(records[..ready].iter().map(|br| br.value));
+
+                match records.get(ready) {
+                    Some(br) if br.pending => return None,
+                    Some(br) => {
+                        source_error = br.error;
+                        break;
+                    },
+                    None => {},
+                }
+            }
+
+            let format = self.format;
+            let source = std::mem::take(source);
+            let finish = move || {
+                let (bytes, error) = decompress::inflate(format, &source);
+
+                decompress::Inflated {
+                    bytes,
+                    error: match error {
+                        /* blame whatever stopped us from reading the rest of the stream */
+                        Some(decompress::DecompressionError::Truncated) if source_error.is_some() => source_error,
+                        error => error.map(ByteError::Decompression),
+                    },
+                }
+            };
+
+            match tokio::runtime::Handle::try_current() {
+                /* big streams take a while, and whoever is fetching might be drawing the listing */
+                Ok(rt) => {
+                    *state = decompress::State::Inflating { wakers: vec::Vec::new() };
+
+                    let shared = self.cache.clone();
+                    rt.spawn_blocking(move || {
+                        let inflated = finish();
+                        let mut cache = shared.lock();
+
+                        /* something underneath us might have changed in the meantime */
+                        if cache.attempt == attempt {
+                            if let decompress::State::Inflating { wakers } = std::mem::replace(&mut cache.state, decompress::State::Finished(inflated)) {
+                                wakers.into_iter().for_each(task::Waker::wake);
+                            }
+                        }
+                    });
+                },
+                /* nowhere to send it off to, like when exporting from a plain executor */
+                Err(_) => *state = decompress::State::Finished(finish()),
+            }
+        }
+
+        match state {
+            decompress::State::Finished(inflated) => Some(inflated),
+            decompress::State::Inflating { wakers } => {
+                if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                    wakers.push(cx.waker().clone());
+                }
+
+                None
+            },
+            decompress::State::Reading { .. } => unreachable!(),
+        }
+    }
+
+    fn human_details(&self) -> string::String {
+        format!("{} from 0x{:x} (0x{:x} bytes)", self.format.describe(), self.source_offset, self.source_size)
+    }
+
+    fn human_affects_addr(&self) -> u64 {
+        self.offset
+    }
+
+    fn human_affects_size(&self) -> Option<u64> {
+        /* this gets asked while drawing, so don't wait on a fetch that's reading the source */
+        match &self.cache.try_lock()?.state {
+            decompress::State::Finished(inflated) => Some(inflated.bytes.len() as u64),
+            /* don't know yet */
+            decompress::State::Reading { .. } | decompress::State::Inflating { .. } => None,
+        }
+    }
+
+    pub fn to_filter(self) -> Filter {
+        Filter::Decompress(self)
+    }
+}
+
+/// Loads `space` for tests, through a cache with blocks small enough that most fetches in tests straddle a few of them.
+#[cfg(test)]
+pub(crate) fn test_load_space(space: sync::Arc<space::AddressSpace>, load_offset: u64, space_offset: u64, size: Option<u64>) -> Filter {
+    LoadSpaceFilter::new_complete(space, load_offset, space_offset, size, 0x10, std::num::NonZeroUsize::new(4).unwrap()).to_filter()
+}
+
+/// Loads `bytes` at address zero for tests.
+#[cfg(test)]
+pub(crate) fn test_load(bytes: vec::Vec<u8>) -> Filter {
+    test_load_space(sync::Arc::new(space::buffer::BufferAddressSpace::new(bytes, "test").into()), 0, 0, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_matches::assert_matches;
+
+    #[test]
+    fn fetch_through_stack() {
+        let mut datapath: DataPath = imbl::vector![
+            test_load((0..=0xff).collect()),
+        ].into();
+
+        /* plenty of edits that have nothing to do with the range we fetch */
+        for i in 0..0x100 {
+            datapath.push_back(OverwriteFilter { offset: 0x1000 + i * 2, bytes: vec![0xff] }.to_filter());
+        }
+
+        datapath.push_back(OverwriteFilter { offset: 0x1e, bytes: vec![0xaa; 4] }.to_filter());
+        datapath.push_back(InsertFilter { offset: 0x10, bytes: vec![0xbb; 2] }.to_filter());
+        datapath.push_back(OverwriteFilter { offset: 0x2000, bytes: vec![0xff] }.to_filter());
+
+        let mut records = vec![ByteRecord::default(); 0x20];
+        datapath.fetch(ByteRecordRange::new(0x8, &mut records), &mut task::Context::from_waker(futures::task::noop_waker_ref()));
+
+        let mut expected: vec::Vec<u8> = (0x8..0x10).collect();
+        expected.extend([0xbb; 2]);
+        expected.extend(0x10..0x1e);
+        expected.extend([0xaa; 4]);
+        expected.extend(0x22..0x26);
+
+        assert_eq!(records.iter().map(|br| br.value).collect::<vec::Vec<u8>>(), expected);
+        assert!(records.iter().all(|br| br.loaded && !br.pending && br.error.is_none()));
+        assert!(records[8..10].iter().all(|br| br.inserted));
+        assert!(records[10..].iter().all(|br| br.moved));
+        assert_eq!(records.iter().filter(|br| br.overwritten).count(), 4);
+    }
+
+    #[test]
+    fn delete() {
+        let mut datapath: DataPath = imbl::vector![
+            test_load((0..=0xff).collect()),
+            DeleteFilter { offset: 0x10, size: 0x4 }.to_filter(),
+        ].into();
+
+        /* deleting right up to the gap left by the first delete merges with it */
+        let stacked = Filter::stack(&datapath[1], &DeleteFilter { offset: 0xc, size: 0x4 }.to_filter());
+        assert_matches!(stacked, Some(Filter::Delete(DeleteFilter { offset: 0xc, size: 0x8 })));
+        /* and so does deleting more at the same place */
+        assert_matches!(Filter::stack(&datapath[1], &DeleteFilter { offset: 0x10, size: 0x2 }.to_filter()), Some(Filter::Delete(DeleteFilter { offset: 0x10, size: 0x6 })));
+        /* but not somewhere else */
+        assert!(Filter::stack(&datapath[1], &DeleteFilter { offset: 0x11, size: 0x2 }.to_filter()).is_none());
+
+        datapath.push_back(DeleteFilter { offset: 0xc, size: 0x4 }.to_filter());
+
+        let mut records = vec![ByteRecord::default(); 0x10];
+        datapath.fetch(ByteRecordRange::new(0x8, &mut records), &mut task::Context::from_waker(futures::task::noop_waker_ref()));
+
+        let mut expected: vec::Vec<u8> = (0x8..0xc).collect();
+        expected.extend(0x14..0x20);
+
+        assert_eq!(records.iter().map(|br| br.value).collect::<vec::Vec<u8>>(), expected);
+        assert!(records.iter().all(|br| br.loaded && !br.pending && br.error.is_none()));
+        assert!(records[..4].iter().all(|br| !br.moved));
+        assert!(records[4..].iter().all(|br| br.moved));
+
+        /* the stacked filter does the same thing */
+        datapath.pop_back();
+        datapath.pop_back();
+        datapath.push_back(stacked.unwrap());
+
+        let mut stacked_records = vec![ByteRecord::default(); 0x10];
+        datapath.fetch(ByteRecordRange::new(0x8, &mut stacked_records), &mut task::Context::from_waker(futures::task::noop_waker_ref()));
+        assert_eq!(stacked_records.iter().map(|br| br.value).collect::<vec::Vec<u8>>(), expected);
+    }
+
+    #[test]
+    fn disabled() {
+        let mut datapath: DataPath = imbl::vector![
+            test_load((0..=0xff).collect()),
+            OverwriteFilter { offset: 0x4, bytes: vec![0xaa; 4] }.to_filter().toggled(),
+            DeleteFilter { offset: 0x2, size: 0x2 }.to_filter(),
+        ].into();
+
+        assert!(!datapath[1].is_enabled());
+        assert!(datapath[2].shifts_addresses() && datapath[2].toggled().shifts_addresses());
+        assert_eq!(datapath[1].human_affects_addr(), 0x4);
+        assert_matches!(datapath[1].relocated(0x8), Some(Filter::Disabled(DisabledFilter { filter })) if filter.human_affects_addr() == 0x8);
+        assert!(datapath[2].relocated(0x8).is_none());
+
+        let mut records = vec![ByteRecord::default(); 0x8];
+        datapath.fetch(ByteRecordRange::new(0x0, &mut records), &mut task::Context::from_waker(futures::task::noop_waker_ref()));
+        assert_eq!(records.iter().map(|br| br.value).collect::<vec::Vec<u8>>(), vec![0x0, 0x1, 0x4, 0x5, 0x6, 0x7, 0x8, 0x9]);
+
+        /* turning them around */
+        datapath[1] = datapath[1].toggled();
+        datapath[2] = datapath[2].toggled();
+        assert!(datapath[1].is_enabled() && !datapath[2].is_enabled());
+
+        datapath.fetch(ByteRecordRange::new(0x0, &mut records), &mut task::Context::from_waker(futures::task::noop_waker_ref()));
+        assert_eq!(records.iter().map(|br| br.value).collect::<vec::Vec<u8>>(), vec![0x0, 0x1, 0x2, 0x3, 0xaa, 0xaa, 0xaa, 0xaa]);
+    }
+}
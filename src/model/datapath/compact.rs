@@ -0,0 +1,222 @@
+use crate::model::datapath;
+
+/// Folds the datapath's filters together into as few as it takes to produce the same bytes. Long editing sessions
+/// leave behind lots of small edits that every fetch has to walk through, and most of them can be combined: overlapping
+/// or adjacent overwrites, insertions into or next to other insertions, and edits to bytes that were inserted.
+/// Disabled filters are left alone, since they're meant to be turned back on as they were.
+pub fn compact(datapath: &datapath::DataPath) -> datapath::DataPath {
+    let mut compacted = datapath::DataPath::new();
+
+    for filter in datapath.iter() {
+        push(&mut compacted, filter.clone());
+    }
+
+    compacted
+}
+
+fn push(datapath: &mut datapath::DataPath, filter: datapath::Filter) {
+    if does_nothing(&filter) {
+        return;
+    }
+
+    /* Look for a filter further down to fold this one into, as long as the ones in between would end up doing the
+     * same thing underneath it as they did on top of it. */
+    for index in (0..datapath.len()).rev() {
+        if let Some(folded) = fold(&datapath[index], &filter) {
+            if index + 1 == datapath.len() {
+                /* the folded filter might fold into the next one down too */
+                datapath.pop_back();
+                push(datapath, folded);
+            } else if does_nothing(&folded) {
+                datapath.remove(index);
+            } else {
+                datapath.set(index, folded);
+            }
+
+            return;
+        }
+
+        if !commutes(&datapath[index], &filter) {
+            break;
+        }
+    }
+
+    datapath.push_back(filter);
+}
+
+fn does_nothing(filter: &datapath::Filter) -> bool {
+    match filter {
+        datapath::Filter::Overwrite(f) => f.bytes.is_empty(),
+        datapath::Filter::Insert(f) => f.bytes.is_empty(),
+        datapath::Filter::Delete(f) => f.size == 0,
+        datapath::Filter::Transform(f) => f.size == 0,
+        _ => false,
+    }
+}
+
+/// Whether `upper` could go underneath `lower` without changing anything. Only overwrites get moved around like this,
+/// and only past edits to other bytes.
+fn commutes(lower: &datapath::Filter, upper: &datapath::Filter) -> bool {
+    let datapath::Filter::Overwrite(upper) = upper else { return false };
+    let (begin, end) = (upper.offset, upper.offset + upper.bytes.len() as u64);
+
+    match lower {
+        datapath::Filter::Overwrite(f) => f.offset >= end || f.offset + f.bytes.len() as u64 <= begin,
+        datapath::Filter::Transform(f) => f.offset >= end || f.offset + f.size <= begin,
+        _ => false,
+    }
+}
+
+/// A single filter that does what `lower` followed by `upper` does, if there is one. This covers more cases than
+/// [datapath::Filter::stack], since the result doesn't need to make sense as a single edit.
+fn fold(lower: &datapath::Filter, upper: &datapath::Filter) -> Option<datapath::Filter> {
+    match (lower, upper) {
+        (datapath::Filter::Overwrite(a), datapath::Filter::Overwrite(b)) => {
+            let (a_end, b_end) = (a.offset + a.bytes.len() as u64, b.offset + b.bytes.len() as u64);
+
+            /* the result has to be contiguous */
+            if b.offset > a_end || a.offset > b_end {
+                return None;
+            }
+
+            let offset = std::cmp::min(a.offset, b.offset);
+            let mut bytes = vec![0; (std::cmp::max(a_end, b_end) - offset) as usize];
+            bytes[(a.offset - offset) as usize..(a_end - offset) as usize].copy_from_slice(&a.bytes);
+            bytes[(b.offset - offset) as usize..(b_end - offset) as usize].copy_from_slice(&b.bytes);
+
+            Some(datapath::OverwriteFilter { offset, bytes }.to_filter())
+        },
+
+        /* Inserting into or right next to inserted bytes is the same as inserting more bytes in the first place. */
+        (datapath::Filter::Insert(a), datapath::Filter::Insert(b)) if b.offset >= a.offset && b.offset <= a.offset + a.bytes.len() as u64 => {
+            let mut bytes = a.bytes.clone();
+            let at = (b.offset - a.offset) as usize;
+            bytes.splice(at..at, b.bytes.iter().cloned());
+
+            Some(datapath::InsertFilter { offset: a.offset, bytes }.to_filter())
+        },
+
+        /* Edits that only touch inserted bytes can be made to the insertion instead. */
+        (datapath::Filter::Insert(a), datapath::Filter::Overwrite(b)) if b.offset >= a.offset && b.offset + b.bytes.len() as u64 <= a.offset + a.bytes.len() as u64 => {
+            let mut bytes = a.bytes.clone();
+            let at = (b.offset - a.offset) as usize;
+            bytes[at..at + b.bytes.len()].copy_from_slice(&b.bytes);
+
+            Some(datapath::InsertFilter { offset: a.offset, bytes }.to_filter())
+        },
+
+        (datapath::Filter::Insert(a), datapath::Filter::Delete(b)) if b.offset >= a.offset && b.offset + b.size <= a.offset + a.bytes.len() as u64 => {
+            let mut bytes = a.bytes.clone();
+            let at = (b.offset - a.offset) as usize;
+            bytes.drain(at..at + b.size as usize);
+
+            Some(datapath::InsertFilter { offset: a.offset, bytes }.to_filter())
+        },
+
+        _ => datapath::Filter::stack(lower, upper),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::vec;
+
+    use assert_matches::assert_matches;
+
+    use crate::model::datapath::export;
+
+    /* stays clear of the end of the space, even after a few bytes are inserted */
+    fn export_bytes(datapath: &datapath::DataPath) -> vec::Vec<u8> {
+        let mut out = vec::Vec::new();
+        futures::executor::block_on(export::export(datapath, 0xf0, &mut out, &export::Progress::default())).unwrap();
+        out
+    }
+
+    fn assert_compacts_to(datapath: datapath::DataPath, expected_len: usize) -> datapath::DataPath {
+        let compacted = compact(&datapath);
+
+        assert_eq!(export_bytes(&compacted), export_bytes(&datapath));
+        assert_eq!(compacted.len(), expected_len, "{:?}", compacted);
+
+        compacted
+    }
+
+    #[test]
+    fn overwrites() {
+        let compacted = assert_compacts_to(imbl::vector![
+            datapath::test_load((0..=0xff).collect()),
+            datapath::OverwriteFilter { offset: 0x10, bytes: vec![0xa0, 0xa1, 0xa2, 0xa3] }.to_filter(),
+            /* unrelated edit in between */
+            datapath::OverwriteFilter { offset: 0x40, bytes: vec![0xb0] }.to_filter(),
+            /* overlaps the first one from the left */
+            datapath::OverwriteFilter { offset: 0xe, bytes: vec![0xc0, 0xc1, 0xc2] }.to_filter(),
+            /* touches the end of the first one */
+            datapath::OverwriteFilter { offset: 0x14, bytes: vec![0xd0] }.to_filter(),
+            datapath::OverwriteFilter { offset: 0x41, bytes: vec![0xe0] }.to_filter(),
+        ].into(), 3);
+
+        assert_matches!(&compacted[1], datapath::Filter::Overwrite(f) if f.offset == 0xe && f.bytes == vec![0xc0, 0xc1, 0xc2, 0xa1, 0xa2, 0xa3, 0xd0]);
+    }
+
+    #[test]
+    fn overwrites_stay_above_transforms() {
+        assert_compacts_to(imbl::vector![
+            datapath::test_load((0..=0xff).collect()),
+            datapath::OverwriteFilter { offset: 0x10, bytes: vec![0xa0, 0xa1] }.to_filter(),
+            datapath::TransformFilter { offset: 0x11, size: 2, op: datapath::TransformOp::Xor, key: vec![0xff] }.to_filter(),
+            datapath::OverwriteFilter { offset: 0x12, bytes: vec![0xb0] }.to_filter(),
+        ].into(), 4);
+
+        /* but can go past ones that don't touch them */
+        assert_compacts_to(imbl::vector![
+            datapath::test_load((0..=0xff).collect()),
+            datapath::OverwriteFilter { offset: 0x10, bytes: vec![0xa0, 0xa1] }.to_filter(),
+            datapath::TransformFilter { offset: 0x20, size: 2, op: datapath::TransformOp::Xor, key: vec![0xff] }.to_filter(),
+            datapath::OverwriteFilter { offset: 0x12, bytes: vec![0xb0] }.to_filter(),
+        ].into(), 3);
+    }
+
+    #[test]
+    fn inserts() {
+        let compacted = assert_compacts_to(imbl::vector![
+            datapath::test_load((0..=0xff).collect()),
+            datapath::InsertFilter { offset: 0x10, bytes: vec![0xa0, 0xa1] }.to_filter(),
+            /* in the middle */
+            datapath::InsertFilter { offset: 0x11, bytes: vec![0xb0] }.to_filter(),
+            /* right after */
+            datapath::InsertFilter { offset: 0x13, bytes: vec![0xc0, 0xc1] }.to_filter(),
+            /* editing the inserted bytes */
+            datapath::OverwriteFilter { offset: 0x14, bytes: vec![0xd0] }.to_filter(),
+            datapath::DeleteFilter { offset: 0x10, size: 1 }.to_filter(),
+        ].into(), 2);
+
+        assert_matches!(&compacted[1], datapath::Filter::Insert(f) if f.offset == 0x10 && f.bytes == vec![0xb0, 0xa1, 0xc0, 0xd0]);
+
+        /* inserting and then deleting the same bytes leaves nothing */
+        assert_compacts_to(imbl::vector![
+            datapath::test_load((0..=0xff).collect()),
+            datapath::InsertFilter { offset: 0x10, bytes: vec![0xa0, 0xa1] }.to_filter(),
+            datapath::DeleteFilter { offset: 0x10, size: 2 }.to_filter(),
+        ].into(), 1);
+
+        /* overwrites can't go past insertions */
+        assert_compacts_to(imbl::vector![
+            datapath::test_load((0..=0xff).collect()),
+            datapath::OverwriteFilter { offset: 0x20, bytes: vec![0xa0] }.to_filter(),
+            datapath::InsertFilter { offset: 0x10, bytes: vec![0xb0] }.to_filter(),
+            datapath::OverwriteFilter { offset: 0x22, bytes: vec![0xc0] }.to_filter(),
+        ].into(), 4);
+    }
+
+    #[test]
+    fn disabled() {
+        assert_compacts_to(imbl::vector![
+            datapath::test_load((0..=0xff).collect()),
+            datapath::OverwriteFilter { offset: 0x10, bytes: vec![0xa0] }.to_filter(),
+            datapath::OverwriteFilter { offset: 0x11, bytes: vec![0xb0] }.to_filter().toggled(),
+            datapath::OverwriteFilter { offset: 0x11, bytes: vec![0xc0] }.to_filter(),
+        ].into(), 4);
+    }
+}
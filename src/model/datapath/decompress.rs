@@ -0,0 +1,340 @@
+use std::io::Read;
+use std::task;
+use std::vec;
+
+use crate::model::datapath;
+
+/// How many bytes of the source we fetch at a time, and how much room we make for output at a time.
+pub const CHUNK_SIZE: usize = 0x10000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Zlib,
+    Gzip,
+    /// The LZ4 frame format, not raw blocks.
+    Lz4,
+}
+
+/// Why the output of a [datapath::DecompressFilter] stops where it does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecompressionError {
+    /// The compressed data doesn't make sense past this point.
+    Corrupt,
+    /// The compressed data ran out before the end of the stream.
+    Truncated,
+}
+
+/// What a [datapath::DecompressFilter] has read and decompressed so far. Shared between clones of the filter, so the
+/// work only gets done once, until something changes underneath the filter (see [datapath::refresh_decompression]).
+#[derive(Debug, Default)]
+pub struct Cache {
+    /// The generations of the address spaces underneath the filter as of when it started reading. If any of them
+    /// change, it has to start over.
+    pub generations: vec::Vec<u64>,
+    /// Counts how many times it has started over, so that decompression that was already underway when it did can tell
+    /// that its result is stale.
+    pub attempt: u64,
+    pub state: State,
+}
+
+/// How far a [datapath::DecompressFilter] has gotten.
+#[derive(Debug)]
+pub enum State {
+    /// Still reading the source. Whatever has been read so far is kept, since the whole source might not fit in the
+    /// address space's cache at once.
+    Reading {
+        source: vec::Vec<u8>,
+    },
+    /// Decompressing on the blocking pool. Whoever fetched in the meantime gets woken up when it's done.
+    Inflating {
+        wakers: vec::Vec<task::Waker>,
+    },
+    Finished(Inflated),
+}
+
+#[derive(Debug)]
+pub struct Inflated {
+    pub bytes: vec::Vec<u8>,
+    /// Shows up on the byte right after the last one that could be decompressed.
+    pub error: Option<datapath::ByteError>,
+}
+
+impl Default for State {
+    fn default() -> State {
+        State::Reading {
+            source: vec::Vec::new(),
+        }
+    }
+}
+
+impl Format {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Format::Zlib => "zlib",
+            Format::Gzip => "gzip",
+            Format::Lz4 => "LZ4",
+        }
+    }
+}
+
+impl DecompressionError {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            DecompressionError::Corrupt => "The compressed data is corrupt.",
+            DecompressionError::Truncated => "The compressed data ends in the middle of the stream.",
+        }
+    }
+}
+
+/// Decompresses as much of `source` as possible. If something goes wrong partway through, everything up to that point
+/// is still returned.
+pub fn inflate(format: Format, source: &[u8]) -> (vec::Vec<u8>, Option<DecompressionError>) {
+    let mut bytes = vec::Vec::new();
+
+    let result = match format {
+        Format::Zlib => inflate_zlib(source, &mut bytes),
+        Format::Gzip => read_all(flate2::bufread::MultiGzDecoder::new(source), &mut bytes),
+        Format::Lz4 => read_all(lz4_flex::frame::FrameDecoder::new(source), &mut bytes),
+    };
+
+    (bytes, result.err())
+}
+
+/* The streaming readers in flate2 can't tell a truncated zlib stream apart from a finished one, so this drives the
+ * decompressor by hand. */
+fn inflate_zlib(source: &[u8], out: &mut vec::Vec<u8>) -> Result<(), DecompressionError> {
+    let mut decompress = flate2::Decompress::new(true);
+
+    loop {
+        out.reserve(CHUNK_SIZE);
+
+        let consumed = decompress.total_in() as usize;
+        let produced = out.len();
+
+        match decompress.decompress_vec(&source[consumed..], out, flate2::FlushDecompress::None) {
+            Ok(flate2::Status::StreamEnd) => return Ok(()),
+            /* there's room for more output, so it must be waiting on input that isn't there */
+            Ok(_) if decompress.total_in() as usize == consumed && out.len() == produced => return Err(DecompressionError::Truncated),
+            Ok(_) => {},
+            Err(_) => return Err(DecompressionError::Corrupt),
+        }
+    }
+}
+
+fn read_all(mut reader: impl Read, out: &mut vec::Vec<u8>) -> Result<(), DecompressionError> {
+    let mut buffer = vec![0; CHUNK_SIZE];
+
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(n) => out.extend_from_slice(&buffer[..n]),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {},
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Err(DecompressionError::Truncated),
+            Err(_) => return Err(DecompressionError::Corrupt),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+    use std::sync;
+    use std::task;
+
+    use crate::model::datapath::DataPathExt;
+    use crate::model::datapath::export;
+    use crate::model::document;
+    use crate::model::document::structure;
+    use crate::model::space;
+
+    fn plaintext() -> vec::Vec<u8> {
+        (0..0x3000u32).map(|i| (i * 7 / 5) as u8).collect()
+    }
+
+    fn compress(format: Format, bytes: &[u8]) -> vec::Vec<u8> {
+        match format {
+            Format::Zlib => {
+                let mut encoder = flate2::write::ZlibEncoder::new(vec::Vec::new(), flate2::Compression::default());
+                encoder.write_all(bytes).unwrap();
+                encoder.finish().unwrap()
+            },
+            Format::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(vec::Vec::new(), flate2::Compression::default());
+                encoder.write_all(bytes).unwrap();
+                encoder.finish().unwrap()
+            },
+            Format::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(vec::Vec::new());
+                encoder.write_all(bytes).unwrap();
+                encoder.finish().unwrap()
+            },
+        }
+    }
+
+    #[test]
+    fn inflate_formats() {
+        for format in [Format::Zlib, Format::Gzip, Format::Lz4] {
+            let compressed = compress(format, &plaintext());
+
+            assert_eq!(inflate(format, &compressed), (plaintext(), None), "{}", format.describe());
+
+            let (bytes, error) = inflate(format, &compressed[..compressed.len() - 10]);
+            assert_eq!(error, Some(DecompressionError::Truncated), "{}", format.describe());
+            assert!(plaintext().starts_with(&bytes), "{}", format.describe());
+        }
+
+        assert_eq!(inflate(Format::Zlib, &[0x78, 0x9c, 0xff, 0xff, 0xff, 0xff]).1, Some(DecompressionError::Corrupt));
+    }
+
+    fn datapath(source: &[u8], source_size: u64) -> datapath::DataPath {
+        let mut bytes = source.to_vec();
+        bytes.resize(0x1000, 0);
+
+        imbl::vector![
+            /* a cache much smaller than the source, so it has to be read in pieces */
+            datapath::test_load(bytes),
+            /* right after the end of the file */
+            datapath::DecompressFilter::new(Format::Zlib, 0, source_size, 0x1000).to_filter(),
+        ].into()
+    }
+
+    fn fetch_cached(datapath: &datapath::DataPath, addr: u64, size: usize) -> vec::Vec<datapath::ByteRecord> {
+        let mut records = vec![datapath::ByteRecord::default(); size];
+        datapath.fetch(datapath::ByteRecordRange::new(addr, &mut records), &mut task::Context::from_waker(futures::task::noop_waker_ref()));
+        assert!(records.iter().all(|br| !br.pending));
+        records
+    }
+
+    #[test]
+    fn filter() {
+        let compressed = compress(Format::Zlib, &plaintext());
+        let datapath = datapath(&compressed, compressed.len() as u64);
+
+        let mut out = vec::Vec::new();
+        futures::executor::block_on(export::export(&datapath, 0x4000, &mut out, &export::Progress::default())).unwrap();
+
+        /* the source is still there underneath */
+        assert_eq!(out[..compressed.len()], compressed);
+        assert_eq!(out[0x1000..], plaintext());
+        assert_eq!(datapath[1].human_affects_size(), Some(0x3000));
+
+        /* the decompressed data is cached by now, and nothing past it is decompressed */
+        let records = fetch_cached(&datapath, 0x3fff, 1);
+        assert!(records[0].decompressed && records[0].value == *plaintext().last().unwrap());
+        let mut records = vec![datapath::ByteRecord::default(); 1];
+        datapath.fetch(datapath::ByteRecordRange::new(0x4000, &mut records), &mut task::Context::from_waker(futures::task::noop_waker_ref()));
+        assert!(!records[0].decompressed);
+    }
+
+    #[test]
+    fn filter_error() {
+        let compressed = compress(Format::Zlib, &plaintext());
+        /* cut off the end of the stream */
+        let datapath = datapath(&compressed, compressed.len() as u64 - 10);
+
+        let mut out = vec::Vec::new();
+        let error = futures::executor::block_on(export::export(&datapath, 0x4000, &mut out, &export::Progress::default())).unwrap_err();
+
+        let export::ExportError::Unreadable { addr, error: datapath::ByteError::Decompression(DecompressionError::Truncated) } = error else { panic!("expected decompression error, got {:?}", error) };
+        assert!((0x1000..0x4000).contains(&addr));
+
+        let records = fetch_cached(&datapath, 0x1000, (addr - 0x1000) as usize);
+        assert_eq!(records.iter().map(|br| br.value).collect::<vec::Vec<u8>>(), plaintext()[..(addr - 0x1000) as usize]);
+    }
+
+    #[test]
+    fn filter_inflates_in_background() {
+        let compressed = compress(Format::Zlib, &plaintext());
+        let datapath = datapath(&compressed, compressed.len() as u64);
+        let datapath::Filter::Decompress(filter) = &datapath[1] else { panic!("expected decompress filter") };
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let _guard = rt.enter();
+        let mut cx = task::Context::from_waker(futures::task::noop_waker_ref());
+        let mut records = vec![datapath::ByteRecord::default(); 0x10];
+
+        /* the whole source is there right away, but whoever is drawing the listing shouldn't have to wait for it to get
+         * decompressed */
+        datapath.fetch(datapath::ByteRecordRange::new(0x1000, &mut records), &mut cx);
+        assert!(records.iter().all(|br| br.pending));
+        assert!(!matches!(filter.cache.lock().state, State::Reading { .. }));
+
+        let start = std::time::Instant::now();
+        while records.iter().any(|br| br.pending) {
+            assert!(start.elapsed() < std::time::Duration::from_secs(5), "decompression never finished");
+            std::thread::sleep(std::time::Duration::from_millis(1));
+
+            records.fill(datapath::ByteRecord::default());
+            datapath.fetch(datapath::ByteRecordRange::new(0x1000, &mut records), &mut cx);
+        }
+
+        assert_eq!(records.iter().map(|br| br.value).collect::<vec::Vec<u8>>(), plaintext()[..0x10]);
+    }
+
+    /* Exports everything up to the end of the decompressed data, and returns what got decompressed. */
+    async fn decompressed(datapath: &datapath::DataPath) -> Result<vec::Vec<u8>, export::ExportError> {
+        let mut out = vec::Vec::new();
+        export::export(datapath, 0x4000, &mut out, &export::Progress::default()).await?;
+        Ok(out.split_off(0x1000))
+    }
+
+    #[test]
+    fn filter_source_edited() {
+        let compressed = compress(Format::Zlib, &plaintext());
+        let host = document::Builder::new(structure::Node::builder().name("root").size(0x4000).build())
+            .datapath(datapath(&compressed, compressed.len() as u64))
+            .host();
+
+        let before = host.get();
+        assert_eq!(futures::executor::block_on(decompressed(&before.datapath)).unwrap(), plaintext());
+
+        /* swap out the source underneath the filter for a different stream */
+        host.change(before.add_filter(1, datapath::OverwriteFilter { offset: 0, bytes: compress(Format::Zlib, &[0; 0x3000]) }.to_filter())).unwrap();
+        assert_eq!(futures::executor::block_on(decompressed(&host.get().datapath)).unwrap(), vec![0; 0x3000]);
+
+        /* the document from before the edit still decompresses the old source */
+        assert_eq!(futures::executor::block_on(decompressed(&before.datapath)).unwrap(), plaintext());
+
+        /* edits on top of the filter don't make it start over */
+        let after = host.get();
+        host.change(after.stack_filter(datapath::OverwriteFilter { offset: 0x1000, bytes: vec![0xaa] }.to_filter())).unwrap();
+        let datapath::Filter::Decompress(a) = &after.datapath[2] else { panic!("expected decompress filter") };
+        let datapath::Filter::Decompress(b) = &host.get().datapath[2] else { panic!("expected decompress filter") };
+        assert!(sync::Arc::ptr_eq(&a.cache, &b.cache));
+    }
+
+    #[test]
+    fn filter_space_changed() {
+        let path = std::env::temp_dir().join(format!("charm-decompress-test-{}-space-changed", std::process::id()));
+        /* the whole file gets loaded, so pad it out to where the decompressed data goes */
+        let padded = |bytes: &[u8]| {
+            let mut compressed = compress(Format::Zlib, bytes);
+            compressed.resize(0x1000, 0);
+            compressed
+        };
+
+        std::fs::File::create(&path).unwrap().write_all(&padded(&plaintext())).unwrap();
+
+        let space = sync::Arc::new(space::AddressSpace::from(space::file::FileAddressSpace::new(path.clone(), "test")));
+        let space::AddressSpace::File(fas) = &*space else { unreachable!() };
+        fas.try_open().unwrap();
+
+        let datapath: datapath::DataPath = imbl::vector![
+            /* a cache that's too small for a chunk of the source would keep evicting blocks before they're ready */
+            datapath::LoadSpaceFilter::new_complete(space.clone(), 0, 0, Some(0x1000), 0x100, std::num::NonZeroUsize::new(16).unwrap()).to_filter(),
+            datapath::DecompressFilter::new(Format::Zlib, 0, 0x1000, 0x1000).to_filter(),
+        ].into();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        assert_eq!(rt.block_on(decompressed(&datapath)).unwrap(), plaintext());
+
+        /* the way saving in place would */
+        std::fs::File::create(&path).unwrap().write_all(&padded(&[0; 0x3000])).unwrap();
+        fas.reload();
+        assert_eq!(rt.block_on(decompressed(&datapath)).unwrap(), vec![0; 0x3000]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
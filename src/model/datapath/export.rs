@@ -0,0 +1,191 @@
+use std::sync;
+use std::task;
+use std::vec;
+
+use crate::model::datapath;
+use crate::model::datapath::DataPathExt;
+
+/// How many bytes we fetch at a time, so that exporting a huge document doesn't need a huge buffer.
+pub const CHUNK_SIZE: usize = 0x10000;
+
+#[derive(Debug)]
+pub enum ExportError {
+    Io(std::io::Error),
+    /// A byte couldn't be read, so we'd have had to make something up.
+    Unreadable {
+        addr: u64,
+        error: datapath::ByteError,
+    },
+    /// Nothing in the datapath covers this byte.
+    NoData {
+        addr: u64,
+    },
+    /// There's no way to fetch through this filter yet.
+    UnsupportedFilter {
+        index: usize,
+    },
+    Cancelled,
+}
+
+/// Shared between an export and whoever is watching it, so they can see how far it's gotten and stop it early.
+#[derive(Default)]
+pub struct Progress {
+    bytes_written: sync::atomic::AtomicU64,
+    cancelled: sync::atomic::AtomicBool,
+    /* so cancelling doesn't have to wait for a slow fetch to finish */
+    waker: futures::task::AtomicWaker,
+}
+
+impl Progress {
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written.load(sync::atomic::Ordering::Relaxed)
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, sync::atomic::Ordering::Release);
+        self.waker.wake();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(sync::atomic::Ordering::Acquire)
+    }
+}
+
+impl From<std::io::Error> for ExportError {
+    fn from(e: std::io::Error) -> ExportError {
+        ExportError::Io(e)
+    }
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportError::Io(e) => write!(f, "I/O error: {}", e),
+            ExportError::Unreadable { addr, error } => write!(f, "Byte at 0x{:x} couldn't be read. {}", addr, error.describe()),
+            ExportError::NoData { addr } => write!(f, "Nothing in the datapath provides the byte at 0x{:x}.", addr),
+            ExportError::UnsupportedFilter { index } => write!(f, "Filter #{} can't be exported yet.", index),
+            ExportError::Cancelled => write!(f, "Export was cancelled."),
+        }
+    }
+}
+
+/// Streams `size` bytes of the datapath's output, starting from address zero, into `out`. Waits for bytes that are
+/// still pending, and fails on the first byte that couldn't be read or that nothing provides.
+pub async fn export(datapath: &datapath::DataPath, size: u64, out: &mut impl std::io::Write, progress: &Progress) -> Result<(), ExportError> {
+    if let Some(index) = datapath.iter().position(|filter| matches!(filter, datapath::Filter::Move(_))) {
+        return Err(ExportError::UnsupportedFilter { index });
+    }
+
+    let mut records = vec![datapath::ByteRecord::default(); std::cmp::min(CHUNK_SIZE as u64, size) as usize];
+    let mut bytes = vec::Vec::with_capacity(records.len());
+    let mut addr = 0;
+
+    while addr < size {
+        let records = &mut records[..std::cmp::min(CHUNK_SIZE as u64, size - addr) as usize];
+
+        /* Write out whatever was ready as soon as it's ready, instead of waiting for the whole chunk. If the chunk
+         * spans more blocks than the cache holds, the whole chunk might never be ready at the same time. */
+        let ready = std::future::poll_fn(|cx| fetch_ready(datapath, addr, records, progress, cx)).await?;
+
+        bytes.clear();
+        for (i, br) in records[..ready].iter().enumerate() {
+            if let Some(error) = br.error {
+                return Err(ExportError::Unreadable { addr: addr + i as u64, error });
+            }
+
+            if !br.has_any_value() {
+                return Err(ExportError::NoData { addr: addr + i as u64 });
+            }
+
+            bytes.push(br.value);
+        }
+
+        out.write_all(&bytes)?;
+        addr+= ready as u64;
+        progress.bytes_written.store(addr, sync::atomic::Ordering::Relaxed);
+    }
+
+    out.flush()?;
+
+    Ok(())
+}
+
+/// Resolves to how many bytes from the start of `records` are no longer pending.
+fn fetch_ready(datapath: &datapath::DataPath, addr: u64, records: &mut [datapath::ByteRecord], progress: &Progress, cx: &mut task::Context) -> task::Poll<Result<usize, ExportError>> {
+    progress.waker.register(cx.waker());
+
+    if progress.is_cancelled() {
+        return task::Poll::Ready(Err(ExportError::Cancelled));
+    }
+
+    /* polling first gets us woken up by fetches that were already underway */
+    datapath.poll(cx);
+    datapath.fetch(datapath::ByteRecordRange::new(addr, records), cx);
+
+    match records.iter().position(|br| br.pending) {
+        Some(0) => task::Poll::Pending,
+        Some(ready) => task::Poll::Ready(Ok(ready)),
+        None => task::Poll::Ready(Ok(records.len())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_matches::assert_matches;
+
+    fn datapath() -> datapath::DataPath {
+        imbl::vector![
+            datapath::test_load((0..=0xff).collect()),
+            datapath::OverwriteFilter {
+                offset: 0x20,
+                bytes: vec![0xaa; 4],
+            }.to_filter(),
+        ].into()
+    }
+
+    #[test]
+    fn export_edited() {
+        let progress = Progress::default();
+        let mut out = vec::Vec::new();
+
+        futures::executor::block_on(export(&datapath(), 0x100, &mut out, &progress)).unwrap();
+
+        let mut expected: vec::Vec<u8> = (0..0x100).map(|i| i as u8).collect();
+        expected[0x20..0x24].fill(0xaa);
+
+        assert_eq!(out, expected);
+        assert_eq!(progress.bytes_written(), 0x100);
+    }
+
+    #[test]
+    fn export_past_end() {
+        let progress = Progress::default();
+        let mut out = vec::Vec::new();
+
+        assert_matches!(futures::executor::block_on(export(&datapath(), 0x110, &mut out, &progress)), Err(ExportError::Unreadable { addr: 0x100, error: datapath::ByteError::EndOfSpace }));
+    }
+
+    #[test]
+    fn export_cancelled() {
+        let progress = Progress::default();
+        let mut out = vec::Vec::new();
+
+        progress.cancel();
+        assert_matches!(futures::executor::block_on(export(&datapath(), 0x100, &mut out, &progress)), Err(ExportError::Cancelled));
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn export_move() {
+        let progress = Progress::default();
+        let mut out = vec::Vec::new();
+
+        let mut datapath = datapath();
+        datapath.push_back(datapath::Filter::Move(datapath::MoveFilter { from: 0, to: 8, size: 4 }));
+
+        assert_matches!(futures::executor::block_on(export(&datapath, 0x100, &mut out, &progress)), Err(ExportError::UnsupportedFilter { index: 2 }));
+        assert!(out.is_empty());
+    }
+}
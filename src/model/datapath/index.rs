@@ -0,0 +1,146 @@
+use std::iter;
+use std::vec;
+
+use crate::model::datapath;
+
+/// Filters that affect more bytes than this get checked on every lookup instead of being looked up by address, so
+/// that looking up by address only has to look a little ways back from the start of the range.
+const WIDE: u64 = 0x10000;
+
+/// Which filters in a datapath affect which addresses. Fetches use this to go straight to the next filter that can
+/// affect the range they're fetching, instead of asking every filter in between. It gets built the first time a
+/// datapath is fetched from after it changes.
+#[derive(Debug, Default)]
+pub struct Index {
+    /// Filters that affect up to [WIDE] bytes, as their extent and their position in the datapath, sorted by where
+    /// their extent begins.
+    narrow: vec::Vec<(u64, u64, usize)>,
+    /// Every other filter that affects anything, in datapath order. Filters that affect everything after an address
+    /// don't have an end.
+    wide: vec::Vec<(u64, Option<u64>, usize)>,
+}
+
+impl Index {
+    pub fn new(datapath: &datapath::DataPath) -> Index {
+        let mut index = Index::default();
+
+        for (position, filter) in datapath.iter().enumerate() {
+            match filter.affected() {
+                Some((begin, Some(size))) if size <= WIDE => index.narrow.push((begin, begin.saturating_add(size), position)),
+                Some((begin, size)) => index.wide.push((begin, size.map(|size| begin.saturating_add(size)), position)),
+                None => {},
+            }
+        }
+
+        index.narrow.sort_unstable();
+        index
+    }
+
+    /// The position of the highest filter underneath `position` that might affect `[begin, end)`.
+    fn below(&self, position: usize, begin: u64, end: u64) -> Option<usize> {
+        let wide = self.wide[..self.wide.partition_point(|(_, _, p)| *p < position)].iter().rev()
+            .find(|(b, e, _)| end > *b && e.map_or(true, |e| begin < e))
+            .map(|(_, _, p)| *p);
+
+        /* nothing that begins further back than this can reach the range */
+        let first = self.narrow.partition_point(|(b, _, _)| b.saturating_add(WIDE) <= begin);
+        let last = self.narrow.partition_point(|(b, _, _)| *b < end);
+
+        let narrow = self.narrow[first..std::cmp::max(first, last)].iter()
+            .filter(|(_, e, p)| begin < *e && *p < position)
+            .map(|(_, _, p)| *p)
+            .max();
+
+        std::cmp::max(wide, narrow)
+    }
+}
+
+/// The filters underneath one that's being fetched through, from the top down.
+#[derive(Clone)]
+pub struct Below<'a> {
+    datapath: &'a datapath::DataPath,
+    position: usize,
+}
+
+impl<'a> Below<'a> {
+    /// Every filter in the datapath.
+    pub fn top(datapath: &'a datapath::DataPath) -> Below<'a> {
+        Below {
+            datapath,
+            position: datapath.len(),
+        }
+    }
+
+    /// Skips past the filters that can't affect `[begin, end)`, and returns the first one that can.
+    pub fn next_affecting(&mut self, begin: u64, end: u64) -> Option<&'a datapath::Filter> {
+        let position = self.datapath.index().below(self.position, begin, end)?;
+        self.position = position;
+        Some(&self.datapath[position])
+    }
+}
+
+impl<'a> iter::Iterator for Below<'a> {
+    type Item = &'a datapath::Filter;
+
+    fn next(&mut self) -> Option<&'a datapath::Filter> {
+        self.position = self.position.checked_sub(1)?;
+        Some(&self.datapath[self.position])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync;
+
+    use crate::model::space;
+
+    /* the slow way */
+    fn below_linear(datapath: &datapath::DataPath, position: usize, begin: u64, end: u64) -> Option<usize> {
+        (0..position).rev().find(|i| datapath[*i].affected().is_some_and(|(offset, size)| end > offset && size.map_or(true, |size| begin < offset.saturating_add(size))))
+    }
+
+    #[test]
+    fn matches_linear() {
+        let space = sync::Arc::new(space::AddressSpace::from(space::buffer::BufferAddressSpace::new(vec![0; 0x100], "test")));
+
+        let mut datapath: datapath::DataPath = imbl::vector![
+            datapath::test_load_space(space, 0, 0, Some(0x100000)),
+        ].into();
+
+        for i in 0..0x100u64 {
+            datapath.push_back(datapath::OverwriteFilter { offset: (i * 0x1337) % 0x40000, bytes: vec![0xaa; (i % 7) as usize] }.to_filter());
+
+            match i % 0x40 {
+                0x10 => datapath.push_back(datapath::InsertFilter { offset: i * 0x100, bytes: vec![0xbb; 4] }.to_filter()),
+                0x20 => datapath.push_back(datapath::TransformFilter { offset: i * 0x80, size: 0x20000, op: datapath::TransformOp::Xor, key: vec![0xff] }.to_filter()),
+                0x30 => datapath.push_back(datapath::OverwriteFilter { offset: i, bytes: vec![0xcc] }.to_filter().toggled()),
+                _ => {},
+            }
+        }
+
+        let index = Index::new(&datapath);
+
+        for position in 0..=datapath.len() {
+            for (begin, end) in [(0, 1), (0x1337, 0x1338), (0x1000, 0x3000), (0x2000, 0x2000), (0x3ffff, 0x50000), (0x200000, 0x200010)] {
+                assert_eq!(index.below(position, begin, end), below_linear(&datapath, position, begin, end), "at {} for [{:#x}, {:#x})", position, begin, end);
+            }
+        }
+    }
+
+    #[test]
+    fn rebuilt_on_change() {
+        let mut datapath: datapath::DataPath = imbl::vector![
+            datapath::OverwriteFilter { offset: 0x10, bytes: vec![0xaa] }.to_filter(),
+        ].into();
+
+        let mut below = Below::top(&datapath);
+        assert!(below.next_affecting(0x20, 0x21).is_none());
+
+        datapath.push_back(datapath::OverwriteFilter { offset: 0x20, bytes: vec![0xbb] }.to_filter());
+        let mut below = Below::top(&datapath);
+        assert!(below.next_affecting(0x20, 0x21).is_some());
+        assert!(below.next_affecting(0x20, 0x21).is_none());
+    }
+}
@@ -0,0 +1,935 @@
+use std::vec;
+
+use crate::model::datapath;
+use crate::model::datapath::export;
+
+/// IPS offsets are 24 bits.
+const IPS_MAX_OFFSET: u64 = 0xffffff;
+/// An IPS record at this offset would read as the "EOF" marker.
+const IPS_EOF_OFFSET: u64 = 0x454f46;
+const IPS_MAX_RECORD: u64 = 0xffff;
+/// Shorter runs than this are cheaper to store as plain data than as an RLE record.
+const IPS_MIN_RLE: usize = 8;
+
+/// Matching runs shorter than this get folded into the surrounding literal data, since a copy command costs about as
+/// much as the bytes it saves.
+const BPS_MIN_COPY: u64 = 4;
+
+const BPS_SOURCE_READ: u64 = 0;
+const BPS_TARGET_READ: u64 = 1;
+const BPS_SOURCE_COPY: u64 = 2;
+const BPS_TARGET_COPY: u64 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Overwrites only. Can't change the size of the data and can't reach past 16 MiB.
+    Ips,
+    /// Handles inserted data, and carries checksums of the source and target.
+    Bps,
+}
+
+#[derive(Debug)]
+pub enum PatchError {
+    /// Filters that neither patch format can describe.
+    UnsupportedFilter {
+        index: usize,
+    },
+    /// IPS can't address anything this far in.
+    TooLarge {
+        addr: u64,
+    },
+    Fetch(export::ExportError),
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    /// The patch ended in the middle of something.
+    Truncated,
+    /// A line of a text patch that isn't "offset: hex bytes".
+    InvalidLine {
+        line: usize,
+    },
+    /// A BPS command that refers to data that isn't there.
+    InvalidCommand {
+        offset: usize,
+    },
+    /// The patch's own checksum is wrong, so it got damaged somewhere along the way.
+    PatchChecksum,
+    /// The patch was made for different data than what the document has.
+    SourceMismatch,
+    /// Applying the patch didn't produce what the patch says it should.
+    TargetMismatch,
+    Fetch(export::ExportError),
+}
+
+#[derive(Debug, Clone)]
+pub struct Patch {
+    pub format: Format,
+    pub bytes: vec::Vec<u8>,
+}
+
+/// Where a run of bytes in the output came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Segment {
+    len: u64,
+    /// Offset into the source that the run lines up with, or None if it was inserted.
+    source: Option<u64>,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Ips => "ips",
+            Format::Bps => "bps",
+        }
+    }
+}
+
+impl From<export::ExportError> for PatchError {
+    fn from(e: export::ExportError) -> PatchError {
+        PatchError::Fetch(e)
+    }
+}
+
+impl From<export::ExportError> for ImportError {
+    fn from(e: export::ExportError) -> ImportError {
+        ImportError::Fetch(e)
+    }
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Truncated => write!(f, "The patch ends unexpectedly."),
+            ImportError::InvalidLine { line } => write!(f, "Line {} isn't of the form \"offset: hex bytes\".", line),
+            ImportError::InvalidCommand { offset } => write!(f, "The command at 0x{:x} in the patch refers to data outside of the file.", offset),
+            ImportError::PatchChecksum => write!(f, "The patch is corrupt (its checksum doesn't match)."),
+            ImportError::SourceMismatch => write!(f, "The patch was made for different data than this document has."),
+            ImportError::TargetMismatch => write!(f, "Applying the patch didn't produce the result it should have."),
+            ImportError::Fetch(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::fmt::Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::UnsupportedFilter { index } => write!(f, "Filter #{} can't be expressed as a patch.", index),
+            PatchError::TooLarge { addr } => write!(f, "The edit at 0x{:x} is too far in for an IPS patch.", addr),
+            PatchError::Fetch(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Picks the format for a patch of this datapath: IPS if the edits keep everything where it was, BPS if anything got
+/// inserted or deleted.
+pub fn choose_format(datapath: &datapath::DataPath) -> Result<Format, PatchError> {
+    let mut format = Format::Ips;
+
+    for (index, filter) in datapath.iter().enumerate() {
+        match filter {
+            datapath::Filter::LoadSpace(_) | datapath::Filter::Overwrite(_) | datapath::Filter::Transform(_) | datapath::Filter::Decompress(_) | datapath::Filter::Disabled(_) => {},
+            datapath::Filter::Insert(_) | datapath::Filter::Delete(_) => format = Format::Bps,
+            /* there's no way to fetch through these yet */
+            datapath::Filter::Move(_) => return Err(PatchError::UnsupportedFilter { index }),
+        }
+    }
+
+    Ok(format)
+}
+
+/// The datapath as it was before any edits, which is what the patch gets applied to.
+fn source_datapath(datapath: &datapath::DataPath) -> datapath::DataPath {
+    datapath.iter().filter(|filter| matches!(filter, datapath::Filter::LoadSpace(_))).cloned().collect()
+}
+
+/// Works out which parts of the output line up with which parts of the source, by replaying the insertions and
+/// deletions in the order they were made. The last segment goes on forever.
+fn replay(datapath: &datapath::DataPath) -> vec::Vec<Segment> {
+    let mut segments = vec![Segment { len: u64::MAX, source: Some(0) }];
+
+    for filter in datapath.iter() {
+        match filter {
+            datapath::Filter::Insert(insert) => {
+                let mut begin: u64 = 0;
+                for i in 0..segments.len() {
+                    let segment = segments[i];
+
+                    if insert.offset < begin.saturating_add(segment.len) {
+                        let split = insert.offset - begin;
+                        let pieces = [
+                            Segment { len: split, source: segment.source },
+                            Segment { len: insert.bytes.len() as u64, source: None },
+                            Segment { len: segment.len - split, source: segment.source.map(|s| s + split) },
+                        ];
+
+                        segments.splice(i..=i, pieces.into_iter().filter(|piece| piece.len > 0));
+                        break;
+                    }
+
+                    begin+= segment.len;
+                }
+            },
+            datapath::Filter::Delete(delete) => {
+                /* The deletion can span several segments. Once we reach it, each one we look at starts right where
+                 * the deletion does. */
+                let mut begin: u64 = 0;
+                let mut remaining = delete.size;
+                let mut i = 0;
+                while remaining > 0 && i < segments.len() {
+                    let segment = segments[i];
+
+                    if delete.offset >= begin.saturating_add(segment.len) {
+                        begin+= segment.len;
+                        i+= 1;
+                        continue;
+                    }
+
+                    let split = delete.offset - begin;
+                    let cut = std::cmp::min(segment.len - split, remaining);
+                    let pieces = [
+                        Segment { len: split, source: segment.source },
+                        Segment { len: segment.len - split - cut, source: segment.source.map(|s| s + split + cut) },
+                    ];
+
+                    segments.splice(i..=i, pieces.into_iter().filter(|piece| piece.len > 0));
+                    remaining-= cut;
+
+                    if split > 0 {
+                        begin+= split;
+                        i+= 1;
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+
+    segments
+}
+
+/// Works out which parts of the first `size` bytes of output line up with which parts of the source.
+fn layout(datapath: &datapath::DataPath, size: u64) -> vec::Vec<Segment> {
+    let mut segments = replay(datapath);
+
+    /* clip to the size of the output */
+    let mut remaining = size;
+    segments.retain_mut(|segment| {
+        segment.len = std::cmp::min(segment.len, remaining);
+        remaining-= segment.len;
+        segment.len > 0
+    });
+
+    segments
+}
+
+/// How much of the source the first `size` bytes of output were made from, counting anything that got deleted from the
+/// end.
+fn source_size(datapath: &datapath::DataPath, size: u64) -> u64 {
+    let mut begin: u64 = 0;
+
+    for segment in replay(datapath) {
+        if let Some(source) = segment.source {
+            if begin.saturating_add(segment.len) > size {
+                return source + size.saturating_sub(begin);
+            }
+        }
+
+        begin = begin.saturating_add(segment.len);
+    }
+
+    unreachable!("the last segment goes on forever")
+}
+
+/// Reads the datapath's output and the data underneath it, and describes the difference as a patch. Both reads count
+/// towards `progress`, one after the other.
+pub async fn create(datapath: &datapath::DataPath, size: u64, progress: &export::Progress) -> Result<Patch, PatchError> {
+    let format = choose_format(datapath)?;
+    let layout = layout(datapath, size);
+    let source_size = source_size(datapath, size);
+
+    let mut target = vec::Vec::new();
+    export::export(datapath, size, &mut target, progress).await?;
+
+    let mut source = vec::Vec::new();
+    export::export(&source_datapath(datapath), source_size, &mut source, progress).await?;
+
+    let bytes = match format {
+        Format::Ips => encode_ips(&source, &target)?,
+        Format::Bps => encode_bps(&source, &target, &layout),
+    };
+
+    Ok(Patch { format, bytes })
+}
+
+fn encode_ips(source: &[u8], target: &[u8]) -> Result<vec::Vec<u8>, PatchError> {
+    let mut patch = b"PATCH".to_vec();
+    let differs = |i: usize| source.get(i) != Some(&target[i]);
+
+    let mut i = 0;
+    while i < target.len() {
+        if !differs(i) {
+            i+= 1;
+            continue;
+        }
+
+        /* Keep going through short stretches of unchanged bytes, since a new record costs five bytes anyway. */
+        let start = i;
+        let mut end = i;
+        while i < target.len() && i - end <= 5 {
+            if differs(i) {
+                end = i + 1;
+            }
+            i+= 1;
+        }
+
+        let mut offset = start as u64;
+        while offset < end as u64 {
+            /* rewrite the byte before too, since a record here would look like the end of the patch */
+            if offset == IPS_EOF_OFFSET {
+                offset-= 1;
+            }
+
+            if offset > IPS_MAX_OFFSET {
+                return Err(PatchError::TooLarge { addr: offset });
+            }
+
+            let len = std::cmp::min(end as u64 - offset, IPS_MAX_RECORD);
+            let record = &target[offset as usize..(offset + len) as usize];
+
+            patch.extend_from_slice(&offset.to_be_bytes()[5..]);
+
+            if record.len() >= IPS_MIN_RLE && record.iter().all(|b| *b == record[0]) {
+                patch.extend_from_slice(&[0, 0]);
+                patch.extend_from_slice(&(len as u16).to_be_bytes());
+                patch.push(record[0]);
+            } else {
+                patch.extend_from_slice(&(len as u16).to_be_bytes());
+                patch.extend_from_slice(record);
+            }
+
+            offset+= len;
+        }
+    }
+
+    patch.extend_from_slice(b"EOF");
+
+    Ok(patch)
+}
+
+fn encode_bps(source: &[u8], target: &[u8], layout: &[Segment]) -> vec::Vec<u8> {
+    let mut patch = b"BPS1".to_vec();
+    write_bps_number(&mut patch, source.len() as u64);
+    write_bps_number(&mut patch, target.len() as u64);
+    write_bps_number(&mut patch, 0); /* no metadata */
+
+    let mut source_relative_offset = 0;
+    let mut pos = 0;
+
+    for segment in layout {
+        let Some(segment_source) = segment.source else {
+            write_bps_command(&mut patch, BPS_TARGET_READ, segment.len);
+            patch.extend_from_slice(&target[pos as usize..(pos + segment.len) as usize]);
+            pos+= segment.len;
+            continue;
+        };
+
+        /* Copy whatever still matches the source, and spell out whatever doesn't. */
+        let matches = |i: u64| source.get((segment_source + i) as usize) == Some(&target[(pos + i) as usize]);
+        let mut literal_start = None;
+        let mut i = 0;
+
+        while i < segment.len {
+            let mut run = 0;
+            while i + run < segment.len && matches(i + run) {
+                run+= 1;
+            }
+
+            if run < BPS_MIN_COPY {
+                literal_start.get_or_insert(i);
+                i+= std::cmp::max(run, 1);
+                continue;
+            }
+
+            if let Some(start) = literal_start.take() {
+                write_bps_command(&mut patch, BPS_TARGET_READ, i - start);
+                patch.extend_from_slice(&target[(pos + start) as usize..(pos + i) as usize]);
+            }
+
+            let from = segment_source + i;
+            if from == pos + i {
+                write_bps_command(&mut patch, BPS_SOURCE_READ, run);
+            } else {
+                let relative = from as i64 - source_relative_offset as i64;
+                write_bps_command(&mut patch, BPS_SOURCE_COPY, run);
+                write_bps_number(&mut patch, (relative.unsigned_abs() << 1) | (relative < 0) as u64);
+                source_relative_offset = from + run;
+            }
+
+            i+= run;
+        }
+
+        if let Some(start) = literal_start {
+            write_bps_command(&mut patch, BPS_TARGET_READ, segment.len - start);
+            patch.extend_from_slice(&target[(pos + start) as usize..(pos + segment.len) as usize]);
+        }
+
+        pos+= segment.len;
+    }
+
+    patch.extend_from_slice(&crc32(source).to_le_bytes());
+    patch.extend_from_slice(&crc32(target).to_le_bytes());
+    let patch_crc = crc32(&patch);
+    patch.extend_from_slice(&patch_crc.to_le_bytes());
+
+    patch
+}
+
+fn write_bps_command(patch: &mut vec::Vec<u8>, command: u64, len: u64) {
+    write_bps_number(patch, ((len - 1) << 2) | command);
+}
+
+/// BPS numbers are little-endian groups of seven bits, where the high bit marks the last group. Each group after the
+/// first is offset by one so that every number has exactly one encoding.
+fn write_bps_number(patch: &mut vec::Vec<u8>, mut number: u64) {
+    loop {
+        let bits = (number & 0x7f) as u8;
+        number>>= 7;
+
+        if number == 0 {
+            patch.push(0x80 | bits);
+            return;
+        }
+
+        patch.push(bits);
+        number-= 1;
+    }
+}
+
+/// Turns a patch file into filters that make the same changes, merged through [datapath::Filter::stack] wherever they
+/// can be. IPS, BPS, and "offset: hex bytes" text patches are recognized. BPS patches describe their changes in terms
+/// of the data they get applied to, so that gets read from the datapath.
+pub async fn import(patch: &[u8], datapath: &datapath::DataPath, progress: &export::Progress) -> Result<vec::Vec<datapath::Filter>, ImportError> {
+    let filters = if patch.starts_with(b"PATCH") {
+        parse_ips(patch)?
+    } else if patch.starts_with(b"BPS1") {
+        let mut reader = Reader::new(patch);
+        reader.take(4)?;
+        let source_size = reader.bps_number()?;
+
+        let mut source = vec::Vec::new();
+        export::export(datapath, source_size, &mut source, progress).await?;
+
+        parse_bps(patch, &source)?
+    } else {
+        parse_text(patch)?
+    };
+
+    let mut merged: vec::Vec<datapath::Filter> = vec::Vec::new();
+
+    for filter in filters {
+        match merged.last_mut() {
+            Some(top) => match datapath::Filter::stack(top, &filter) {
+                Some(stacked) => *top = stacked,
+                None => merged.push(filter),
+            },
+            None => merged.push(filter),
+        }
+    }
+
+    Ok(merged)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes, position: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ImportError> {
+        let bytes = self.bytes.get(self.position..).and_then(|rest| rest.get(..len)).ok_or(ImportError::Truncated)?;
+        self.position+= len;
+        Ok(bytes)
+    }
+
+    fn big_endian(&mut self, len: usize) -> Result<u64, ImportError> {
+        Ok(self.take(len)?.iter().fold(0, |acc, byte| (acc << 8) | *byte as u64))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    /// The reverse of [write_bps_number].
+    fn bps_number(&mut self) -> Result<u64, ImportError> {
+        let start = self.position;
+        let invalid = || ImportError::InvalidCommand { offset: start };
+
+        let mut number: u64 = 0;
+        let mut shift: u64 = 1;
+
+        loop {
+            let byte = self.take(1)?[0];
+            number = ((byte & 0x7f) as u64).checked_mul(shift).and_then(|bits| number.checked_add(bits)).ok_or_else(invalid)?;
+
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+
+            shift = shift.checked_mul(0x80).ok_or_else(invalid)?;
+            number = number.checked_add(shift).ok_or_else(invalid)?;
+        }
+    }
+}
+
+fn parse_ips(patch: &[u8]) -> Result<vec::Vec<datapath::Filter>, ImportError> {
+    let mut reader = Reader::new(patch);
+    reader.take(5)?;
+
+    let mut filters = vec::Vec::new();
+
+    loop {
+        let offset = reader.take(3)?;
+
+        /* Some patches follow this with a size to truncate the file to. That would take deleting bytes, so it's
+         * ignored. */
+        if offset == b"EOF" {
+            break;
+        }
+
+        let offset = offset.iter().fold(0, |acc, byte| (acc << 8) | *byte as u64);
+        let bytes = match reader.big_endian(2)? {
+            0 => {
+                let count = reader.big_endian(2)?;
+                vec![reader.take(1)?[0]; count as usize]
+            },
+            len => reader.take(len as usize)?.to_vec(),
+        };
+
+        if !bytes.is_empty() {
+            filters.push(datapath::OverwriteFilter { offset, bytes }.to_filter());
+        }
+    }
+
+    Ok(filters)
+}
+
+fn parse_text(patch: &[u8]) -> Result<vec::Vec<datapath::Filter>, ImportError> {
+    let mut filters = vec::Vec::new();
+
+    for (i, line) in String::from_utf8_lossy(patch).lines().enumerate() {
+        let invalid = || ImportError::InvalidLine { line: i + 1 };
+
+        /* everything after a '#' is a comment */
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (offset, bytes) = line.split_once(':').ok_or_else(invalid)?;
+        let offset = offset.trim();
+        let offset = offset.strip_prefix("0x").or_else(|| offset.strip_prefix("0X")).unwrap_or(offset);
+        let offset = u64::from_str_radix(offset, 16).map_err(|_| invalid())?;
+
+        let digits: vec::Vec<u8> = bytes.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+        if digits.is_empty() || digits.len() % 2 != 0 {
+            return Err(invalid());
+        }
+
+        let bytes = digits.chunks(2).map(|pair| {
+            std::str::from_utf8(pair).ok().and_then(|pair| u8::from_str_radix(pair, 16).ok()).ok_or_else(invalid)
+        }).collect::<Result<vec::Vec<u8>, ImportError>>()?;
+
+        filters.push(datapath::OverwriteFilter { offset, bytes }.to_filter());
+    }
+
+    Ok(filters)
+}
+
+/// Replays a BPS patch against `source`, and works out which parts of the result line up with the source (and are
+/// either untouched or overwritten) and which parts don't line up with anything (and must have been inserted).
+fn parse_bps(patch: &[u8], source: &[u8]) -> Result<vec::Vec<datapath::Filter>, ImportError> {
+    if patch.len() < 4 + 3 + 12 {
+        return Err(ImportError::Truncated);
+    }
+
+    let (body, footer) = patch.split_at(patch.len() - 12);
+    let footer_crc = |i: usize| u32::from_le_bytes([footer[i], footer[i + 1], footer[i + 2], footer[i + 3]]);
+
+    if crc32(&patch[..patch.len() - 4]) != footer_crc(8) {
+        return Err(ImportError::PatchChecksum);
+    }
+
+    let mut reader = Reader::new(body);
+    reader.take(4)?;
+    let source_size = reader.bps_number()?;
+    let target_size = reader.bps_number()?;
+    let metadata_size = reader.bps_number()?;
+    reader.take(metadata_size as usize)?;
+
+    if source.len() as u64 != source_size || crc32(source) != footer_crc(0) {
+        return Err(ImportError::SourceMismatch);
+    }
+
+    let mut replay = BpsReplay {
+        source,
+        /* don't trust the header with how much to allocate */
+        target: vec::Vec::with_capacity(std::cmp::min(target_size, 0x100000) as usize),
+        filters: vec::Vec::new(),
+        source_pos: 0,
+        pending: None,
+    };
+
+    let mut source_relative_offset: u64 = 0;
+    let mut target_relative_offset: u64 = 0;
+
+    while !reader.is_empty() {
+        let command_offset = reader.position;
+        let invalid = || ImportError::InvalidCommand { offset: command_offset };
+
+        let command = reader.bps_number()?;
+        let len = (command >> 2).checked_add(1).ok_or_else(invalid)?;
+        let out = replay.target.len() as u64;
+
+        match command & 3 {
+            BPS_SOURCE_READ => {
+                let bytes = source.get(out as usize..).and_then(|rest| rest.get(..len as usize)).ok_or_else(invalid)?;
+                replay.target.extend_from_slice(bytes);
+                replay.from_source(out, len);
+            },
+            BPS_TARGET_READ => {
+                replay.target.extend_from_slice(reader.take(len as usize)?);
+                replay.pending.get_or_insert(out);
+            },
+            BPS_SOURCE_COPY => {
+                source_relative_offset = apply_bps_offset(source_relative_offset, reader.bps_number()?).ok_or_else(invalid)?;
+                let bytes = source.get(source_relative_offset as usize..).and_then(|rest| rest.get(..len as usize)).ok_or_else(invalid)?;
+                replay.target.extend_from_slice(bytes);
+                replay.from_source(source_relative_offset, len);
+                source_relative_offset+= len;
+            },
+            BPS_TARGET_COPY => {
+                target_relative_offset = apply_bps_offset(target_relative_offset, reader.bps_number()?).ok_or_else(invalid)?;
+
+                /* this can overlap with what it's producing, so it has to go a byte at a time */
+                for _ in 0..len {
+                    let byte = *replay.target.get(target_relative_offset as usize).ok_or_else(invalid)?;
+                    replay.target.push(byte);
+                    target_relative_offset+= 1;
+                }
+
+                replay.pending.get_or_insert(out);
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    if replay.target.len() as u64 != target_size || crc32(&replay.target) != footer_crc(4) {
+        return Err(ImportError::TargetMismatch);
+    }
+
+    let skipped = source.len() as u64 - replay.source_pos;
+    replay.flush(replay.target.len() as u64, skipped);
+
+    Ok(replay.filters)
+}
+
+fn apply_bps_offset(offset: u64, encoded: u64) -> Option<u64> {
+    let magnitude = encoded >> 1;
+
+    if encoded & 1 != 0 {
+        offset.checked_sub(magnitude)
+    } else {
+        offset.checked_add(magnitude)
+    }
+}
+
+struct BpsReplay<'a> {
+    source: &'a [u8],
+    target: vec::Vec<u8>,
+    filters: vec::Vec<datapath::Filter>,
+
+    /// Where in the source we'd expect the next run of source data to come from if nothing was inserted.
+    source_pos: u64,
+    /// Where the target data that doesn't line up with the source started, if there is any so far.
+    pending: Option<u64>,
+}
+
+impl<'a> BpsReplay<'a> {
+    /// Accounts for `len` bytes that were just copied from `from` in the source.
+    fn from_source(&mut self, from: u64, len: u64) {
+        let out = self.target.len() as u64 - len;
+
+        if from < self.source_pos {
+            /* copied from somewhere we've already gone past, so it's as good as new data */
+            self.pending.get_or_insert(out);
+            return;
+        }
+
+        self.flush(out, from - self.source_pos);
+        self.source_pos = from + len;
+    }
+
+    /// Turns the pending data that ends at `end` into filters. `skipped` source bytes went by in the meantime, so that
+    /// many of the pending bytes replace them and the rest were inserted. If there weren't enough pending bytes to
+    /// replace them all, the rest were deleted.
+    fn flush(&mut self, end: u64, skipped: u64) {
+        let start = self.pending.take().unwrap_or(end);
+        let literal = &self.target[start as usize..end as usize];
+
+        let (overwritten, inserted) = literal.split_at(std::cmp::min(skipped, literal.len() as u64) as usize);
+        let original = &self.source[self.source_pos as usize..self.source_pos as usize + overwritten.len()];
+
+        /* only overwrite the bytes that actually changed */
+        let mut i = 0;
+        while i < overwritten.len() {
+            if overwritten[i] == original[i] {
+                i+= 1;
+                continue;
+            }
+
+            let run_start = i;
+            while i < overwritten.len() && overwritten[i] != original[i] {
+                i+= 1;
+            }
+
+            self.filters.push(datapath::OverwriteFilter {
+                offset: start + run_start as u64,
+                bytes: overwritten[run_start..i].to_vec(),
+            }.to_filter());
+        }
+
+        if !inserted.is_empty() {
+            self.filters.push(datapath::InsertFilter {
+                offset: start + skipped,
+                bytes: inserted.to_vec(),
+            }.to_filter());
+        }
+
+        if skipped > overwritten.len() as u64 {
+            self.filters.push(datapath::DeleteFilter {
+                offset: end,
+                size: skipped - overwritten.len() as u64,
+            }.to_filter());
+        }
+    }
+}
+
+/// The usual CRC-32 (the one zlib and PNG use), which is what BPS checksums with.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for byte in bytes {
+        crc^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb88320 & (crc & 1).wrapping_neg());
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_matches::assert_matches;
+
+    fn create_patch(datapath: &datapath::DataPath, size: u64) -> Result<Patch, PatchError> {
+        futures::executor::block_on(create(datapath, size, &export::Progress::default()))
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn ips() {
+        let datapath: datapath::DataPath = imbl::vector![
+            datapath::test_load(vec![0; 0x100]),
+            datapath::OverwriteFilter { offset: 0x10, bytes: vec![1, 2, 3] }.to_filter(),
+            datapath::OverwriteFilter { offset: 0x80, bytes: vec![0xff; 0x10] }.to_filter(),
+        ].into();
+
+        let patch = create_patch(&datapath, 0x100).unwrap();
+        assert_eq!(patch.format, Format::Ips);
+        assert_eq!(patch.bytes, [
+            &b"PATCH"[..],
+            &[0x00, 0x00, 0x10, 0x00, 0x03, 1, 2, 3],
+            &[0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0x10, 0xff],
+            &b"EOF"[..],
+        ].concat());
+    }
+
+    #[test]
+    fn bps() {
+        let datapath: datapath::DataPath = imbl::vector![
+            datapath::test_load((0..8).collect()),
+            datapath::InsertFilter { offset: 4, bytes: vec![0xaa, 0xbb] }.to_filter(),
+        ].into();
+
+        let patch = create_patch(&datapath, 10).unwrap();
+        assert_eq!(patch.format, Format::Bps);
+
+        let (body, crcs) = patch.bytes.split_at(patch.bytes.len() - 12);
+        assert_eq!(body, [
+            &b"BPS1"[..],
+            &[0x88, 0x8a, 0x80],
+            &[0x8c], /* read 4 bytes from source */
+            &[0x85, 0xaa, 0xbb], /* 2 literal bytes */
+            &[0x8e, 0x88], /* copy 4 bytes from source, starting 4 bytes ahead */
+        ].concat());
+        assert_eq!(crcs[0..4], crc32(&(0..8).collect::<vec::Vec<u8>>()).to_le_bytes());
+        assert_eq!(crcs[4..8], crc32(&[0, 1, 2, 3, 0xaa, 0xbb, 4, 5, 6, 7]).to_le_bytes());
+        /* the patch's own checksum covers the other two checksums too */
+        assert_eq!(crcs[8..12], crc32(&patch.bytes[..patch.bytes.len() - 4]).to_le_bytes());
+    }
+
+    #[test]
+    fn layout_inserts() {
+        let datapath: datapath::DataPath = imbl::vector![
+            datapath::test_load(vec![0; 0x10]),
+            datapath::InsertFilter { offset: 0, bytes: vec![1; 2] }.to_filter(),
+            datapath::InsertFilter { offset: 6, bytes: vec![2; 3] }.to_filter(),
+        ].into();
+
+        assert_eq!(layout(&datapath, 0x15), vec![
+            Segment { len: 2, source: None },
+            Segment { len: 4, source: Some(0) },
+            Segment { len: 3, source: None },
+            Segment { len: 0xc, source: Some(4) },
+        ]);
+    }
+
+    #[test]
+    fn layout_deletes() {
+        let datapath: datapath::DataPath = imbl::vector![
+            datapath::test_load(vec![0; 0x20]),
+            datapath::InsertFilter { offset: 4, bytes: vec![1; 4] }.to_filter(),
+            /* takes out the last two inserted bytes and the two after them */
+            datapath::DeleteFilter { offset: 6, size: 4 }.to_filter(),
+            datapath::DeleteFilter { offset: 0x18, size: 2 }.to_filter(),
+        ].into();
+
+        assert_eq!(layout(&datapath, 0x1a), vec![
+            Segment { len: 4, source: Some(0) },
+            Segment { len: 2, source: None },
+            Segment { len: 0x12, source: Some(6) },
+            Segment { len: 2, source: Some(0x1a) },
+        ]);
+
+        /* the bytes deleted off the end still count as part of the source */
+        assert_eq!(source_size(&datapath, 0x1a), 0x1c);
+        assert_eq!(source_size(&datapath, 0x18), 0x1a);
+    }
+
+    #[test]
+    fn bps_delete() {
+        let datapath: datapath::DataPath = imbl::vector![
+            datapath::test_load((0..0x20).collect()),
+            datapath::DeleteFilter { offset: 0x8, size: 0x8 }.to_filter(),
+        ].into();
+
+        let patch = create_patch(&datapath, 0x18).unwrap();
+        assert_eq!(patch.format, Format::Bps);
+
+        let (body, crcs) = patch.bytes.split_at(patch.bytes.len() - 12);
+        assert_eq!(body, [
+            &b"BPS1"[..],
+            &[0xa0, 0x98, 0x80],
+            &[0x9c], /* read 8 bytes from source */
+            &[0xbe, 0xa0], /* copy 16 bytes from source, skipping the 8 deleted ones */
+        ].concat());
+        assert_eq!(crcs[0..4], crc32(&(0..0x20).collect::<vec::Vec<u8>>()).to_le_bytes());
+    }
+
+    fn import_patch(patch: &[u8], datapath: &datapath::DataPath) -> Result<vec::Vec<datapath::Filter>, ImportError> {
+        futures::executor::block_on(import(patch, datapath, &export::Progress::default()))
+    }
+
+    fn output(datapath: &datapath::DataPath, size: u64) -> vec::Vec<u8> {
+        let mut out = vec::Vec::new();
+        futures::executor::block_on(export::export(datapath, size, &mut out, &export::Progress::default())).unwrap();
+        out
+    }
+
+    #[test]
+    fn import_ips() {
+        let base: datapath::DataPath = imbl::vector![datapath::test_load(vec![0; 0x100])].into();
+        let mut edited = base.clone();
+        edited.push_back(datapath::OverwriteFilter { offset: 0x10, bytes: vec![1, 2, 3] }.to_filter());
+        edited.push_back(datapath::OverwriteFilter { offset: 0x80, bytes: vec![0xff; 0x10] }.to_filter());
+
+        let filters = import_patch(&create_patch(&edited, 0x100).unwrap().bytes, &base).unwrap();
+        assert_matches!(&filters[..], [datapath::Filter::Overwrite(a), datapath::Filter::Overwrite(b)] if a.offset == 0x10 && b.offset == 0x80 && b.bytes == vec![0xff; 0x10]);
+
+        let mut imported = base.clone();
+        imported.extend(filters);
+        assert_eq!(output(&imported, 0x100), output(&edited, 0x100));
+    }
+
+    #[test]
+    fn import_bps() {
+        let base: datapath::DataPath = imbl::vector![datapath::test_load((0..0x40).collect())].into();
+        let mut edited = base.clone();
+        edited.push_back(datapath::OverwriteFilter { offset: 0x2, bytes: vec![9, 9] }.to_filter());
+        edited.push_back(datapath::InsertFilter { offset: 0x8, bytes: vec![7; 3] }.to_filter());
+        edited.push_back(datapath::OverwriteFilter { offset: 0x30, bytes: vec![1, 2, 3, 4, 5] }.to_filter());
+
+        let filters = import_patch(&create_patch(&edited, 0x43).unwrap().bytes, &base).unwrap();
+        assert_eq!(filters.iter().filter(|filter| matches!(filter, datapath::Filter::Insert(insert) if insert.offset == 0x8)).count(), 1);
+
+        let mut imported = base.clone();
+        imported.extend(filters);
+        assert_eq!(output(&imported, 0x43), output(&edited, 0x43));
+
+        /* made for something else */
+        let other: datapath::DataPath = imbl::vector![datapath::test_load(vec![0; 0x40])].into();
+        assert_matches!(import_patch(&create_patch(&edited, 0x43).unwrap().bytes, &other), Err(ImportError::SourceMismatch));
+    }
+
+    #[test]
+    fn import_bps_delete() {
+        let base: datapath::DataPath = imbl::vector![datapath::test_load((0..0x40).collect())].into();
+        let mut edited = base.clone();
+        edited.push_back(datapath::OverwriteFilter { offset: 0x10, bytes: vec![9, 9] }.to_filter());
+        /* right after the overwrite, so the patch replaces and deletes in one go */
+        edited.push_back(datapath::DeleteFilter { offset: 0x12, size: 6 }.to_filter());
+        edited.push_back(datapath::DeleteFilter { offset: 0x30, size: 4 }.to_filter());
+        /* off the end */
+        edited.push_back(datapath::DeleteFilter { offset: 0x34, size: 2 }.to_filter());
+
+        let filters = import_patch(&create_patch(&edited, 0x34).unwrap().bytes, &base).unwrap();
+        assert_eq!(filters.iter().filter(|filter| matches!(filter, datapath::Filter::Delete(_))).count(), 3);
+
+        let mut imported = base.clone();
+        imported.extend(filters);
+        assert_eq!(output(&imported, 0x34), output(&edited, 0x34));
+    }
+
+    #[test]
+    fn import_text() {
+        let base: datapath::DataPath = imbl::vector![datapath::test_load(vec![0; 0x20])].into();
+
+        let filters = import_patch(b"0x10: de ad\n# comment\n\n12: BE EF # trailing\n", &base).unwrap();
+        assert_matches!(&filters[..], [datapath::Filter::Overwrite(of)] if of.offset == 0x10 && of.bytes == vec![0xde, 0xad, 0xbe, 0xef]);
+
+        assert_matches!(import_patch(b"10: aa\nzz\n", &base), Err(ImportError::InvalidLine { line: 2 }));
+        assert_matches!(import_patch(b"10: aab\n", &base), Err(ImportError::InvalidLine { line: 1 }));
+    }
+
+    #[test]
+    fn move_unsupported() {
+        let datapath: datapath::DataPath = imbl::vector![
+            datapath::test_load(vec![0; 0x10]),
+            datapath::Filter::Move(datapath::MoveFilter { from: 0, to: 8, size: 4 }),
+        ].into();
+
+        assert_matches!(create_patch(&datapath, 0x10), Err(PatchError::UnsupportedFilter { index: 1 }));
+    }
+}
@@ -0,0 +1,192 @@
+use std::string;
+use std::vec;
+
+use crate::model::datapath;
+use crate::model::space;
+
+/// Something a filter did to a byte on its way to the top of the datapath.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Contribution {
+    /// Which filter in the datapath this was.
+    pub index: usize,
+    /// Where the byte was in this filter's output.
+    pub addr: u64,
+    pub kind: ContributionKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContributionKind {
+    /// The byte was read from the filter's address space at this offset.
+    Loaded {
+        space_offset: u64,
+    },
+    Overwritten,
+    /// The byte was inserted. This is the offset into the inserted bytes.
+    Inserted {
+        offset: u64,
+    },
+    /// An insertion or deletion before the byte shifted it here from `from`.
+    Shifted {
+        from: u64,
+    },
+    Moved {
+        from: u64,
+    },
+    Transformed,
+    /// The byte was decompressed. This is the offset into the decompressed stream.
+    Decompressed {
+        offset: u64,
+    },
+}
+
+/// Works out which filters had a hand in the byte at `addr`, following it down from the top of the datapath until we
+/// find where it came from. The contributions are in datapath order, so the one that the byte came from is first (if
+/// anything provided it at all) and the most recent edit is last.
+pub fn provenance(datapath: &datapath::DataPath, mut addr: u64) -> vec::Vec<Contribution> {
+    let mut contributions = vec::Vec::new();
+
+    for (index, filter) in datapath.iter().enumerate().rev() {
+        let contribute = move |kind| Contribution { index, addr, kind };
+
+        /* This follows the same path that fetching the byte would. Filters that provide the byte themselves stop the
+         * walk, since nothing underneath them makes it into the output. */
+        match filter {
+            datapath::Filter::LoadSpace(f) => if addr >= f.load_offset && f.size.map_or(true, |size| addr - f.load_offset < size) {
+                contributions.push(contribute(ContributionKind::Loaded { space_offset: f.convert_to_space(addr) }));
+                break;
+            },
+            datapath::Filter::Overwrite(f) => if addr >= f.offset && addr - f.offset < f.bytes.len() as u64 {
+                contributions.push(contribute(ContributionKind::Overwritten));
+            },
+            datapath::Filter::Move(f) => if addr >= f.to && addr - f.to < f.size {
+                let from = f.from + (addr - f.to);
+                contributions.push(contribute(ContributionKind::Moved { from }));
+                addr = from;
+            },
+            datapath::Filter::Insert(f) => if addr >= f.offset {
+                if addr - f.offset < f.bytes.len() as u64 {
+                    contributions.push(contribute(ContributionKind::Inserted { offset: addr - f.offset }));
+                    break;
+                }
+
+                let from = addr - f.bytes.len() as u64;
+                contributions.push(contribute(ContributionKind::Shifted { from }));
+                addr = from;
+            },
+            datapath::Filter::Transform(f) => if addr >= f.offset && addr - f.offset < f.size {
+                contributions.push(contribute(ContributionKind::Transformed));
+            },
+            datapath::Filter::Delete(f) => if addr >= f.offset {
+                let from = addr + f.size;
+                contributions.push(contribute(ContributionKind::Shifted { from }));
+                addr = from;
+            },
+            /* if we don't know how much decompressed data there is yet, assume it covers the byte */
+            datapath::Filter::Decompress(f) => if addr >= f.offset && f.human_affects_size().map_or(true, |size| addr - f.offset < size) {
+                contributions.push(contribute(ContributionKind::Decompressed { offset: addr - f.offset }));
+                break;
+            },
+            datapath::Filter::Disabled(_) => {},
+        }
+    }
+
+    contributions.reverse();
+    contributions
+}
+
+/// Describes where a byte came from, one line per contribution, for showing to the user.
+pub fn describe(datapath: &datapath::DataPath, contributions: &[Contribution]) -> string::String {
+    let mut lines: vec::Vec<string::String> = contributions.iter().map(|c| c.describe(datapath)).collect();
+
+    if !contributions.first().is_some_and(|c| matches!(c.kind, ContributionKind::Loaded { .. } | ContributionKind::Inserted { .. } | ContributionKind::Decompressed { .. })) {
+        lines.insert(0, "Nothing provides this byte.".to_string());
+    }
+
+    lines.join("\n")
+}
+
+impl Contribution {
+    pub fn describe(&self, datapath: &datapath::DataPath) -> string::String {
+        let what = match self.kind {
+            ContributionKind::Loaded { space_offset } => {
+                let filter = datapath.get(self.index);
+                let mut what = format!("Loaded from {} at 0x{:x}", filter.map_or_else(|| "?".to_string(), |f| f.human_details()), space_offset);
+
+                /* a concatenated space can say which of its pieces the byte came from */
+                if let Some(datapath::Filter::LoadSpace(f)) = filter {
+                    if let space::AddressSpace::Concat(cas) = &**f.space() {
+                        if let Some(piece) = cas.describe_offset(space_offset) {
+                            what+= &format!(" ({})", piece);
+                        }
+                    }
+                }
+
+                what
+            },
+            ContributionKind::Overwritten => "Overwritten".to_string(),
+            ContributionKind::Inserted { offset } => format!("Inserted (byte 0x{:x} of the insertion)", offset),
+            ContributionKind::Shifted { from } => format!("Shifted here from 0x{:x}", from),
+            ContributionKind::Moved { from } => format!("Moved here from 0x{:x}", from),
+            ContributionKind::Transformed => "Transformed".to_string(),
+            ContributionKind::Decompressed { offset } => format!("Decompressed (byte 0x{:x} of the stream)", offset),
+        };
+
+        format!("{} by filter #{}, at 0x{:x}", what, self.index, self.addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync;
+
+    #[test]
+    fn provenance_through_edits() {
+        let space = sync::Arc::new(space::AddressSpace::from(space::buffer::BufferAddressSpace::new(vec![0; 0x100], "test")));
+
+        let datapath: datapath::DataPath = imbl::vector![
+            datapath::test_load_space(space, 0x1000, 0x20, Some(0x80)),
+            datapath::OverwriteFilter { offset: 0x1010, bytes: vec![0xaa; 4] }.to_filter(),
+            datapath::InsertFilter { offset: 0x1008, bytes: vec![0xbb; 2] }.to_filter(),
+            datapath::OverwriteFilter { offset: 0x1012, bytes: vec![0xcc] }.to_filter(),
+        ].into();
+
+        assert_eq!(provenance(&datapath, 0x1012), vec![
+            Contribution { index: 0, addr: 0x1010, kind: ContributionKind::Loaded { space_offset: 0x30 } },
+            Contribution { index: 1, addr: 0x1010, kind: ContributionKind::Overwritten },
+            Contribution { index: 2, addr: 0x1012, kind: ContributionKind::Shifted { from: 0x1010 } },
+            Contribution { index: 3, addr: 0x1012, kind: ContributionKind::Overwritten },
+        ]);
+
+        assert_eq!(provenance(&datapath, 0x1009), vec![
+            Contribution { index: 2, addr: 0x1009, kind: ContributionKind::Inserted { offset: 1 } },
+        ]);
+
+        assert_eq!(provenance(&datapath, 0x1004), vec![
+            Contribution { index: 0, addr: 0x1004, kind: ContributionKind::Loaded { space_offset: 0x24 } },
+        ]);
+
+        /* before the load */
+        assert_eq!(provenance(&datapath, 0x10), vec![]);
+    }
+
+    #[test]
+    fn describe_concat_piece() {
+        let piece = |label: &str, bytes: vec::Vec<u8>| {
+            let size = bytes.len() as u64;
+            (sync::Arc::new(space::AddressSpace::from(space::buffer::BufferAddressSpace::new(bytes, label))), size)
+        };
+
+        let space = sync::Arc::new(space::AddressSpace::from(space::concat::ConcatAddressSpace::new(vec![
+            piece("flash0.bin", vec![0; 0x10]),
+            piece("flash1.bin", vec![0; 0x10]),
+        ], "flash")));
+
+        let datapath: datapath::DataPath = imbl::vector![
+            datapath::test_load_space(space, 0, 0, Some(0x20)),
+        ].into();
+
+        assert_eq!(describe(&datapath, &provenance(&datapath, 0x14)), "Loaded from flash at 0x14 (flash1.bin+0x4) by filter #0, at 0x14");
+    }
+}
@@ -0,0 +1,285 @@
+use std::os::unix::fs::FileExt;
+use std::path;
+use std::vec;
+
+use crate::model::datapath;
+use crate::model::space;
+use crate::model::space::AddressSpaceExt;
+
+#[derive(Debug)]
+pub enum WriteBackError {
+    /// The bottom of the datapath doesn't load from a file, so there's nothing to write back to.
+    NotAFile,
+    /// This filter inserts, moves, or deletes bytes, which would change the layout of the file.
+    SizeChanging {
+        index: usize,
+    },
+    /// This filter loads from somewhere else, so we can't tell which of the bytes on top of it belong to the file.
+    UnsupportedFilter {
+        index: usize,
+    },
+    /// This filter changes bytes based on what's underneath it, so there's no fixed set of bytes to write.
+    Transformed {
+        index: usize,
+    },
+    /// This filter is disabled. Collapsing the datapath onto the file would throw it away, and that can't be undone.
+    Disabled {
+        index: usize,
+    },
+    /// An overwrite reaches past the part of the file that the datapath loads, so writing it would grow the file or
+    /// land somewhere the user never saw.
+    OutOfBounds {
+        addr: u64,
+    },
+    /// The file changed on disk since we opened it, so the edits might not line up with it anymore.
+    FileChanged,
+    Backup(std::io::Error),
+    Io(std::io::Error),
+}
+
+/// Which bytes need to be written where to make a file look the way the datapath on top of it does.
+#[derive(Debug, Clone)]
+pub struct Plan {
+    base: datapath::LoadSpaceFilter,
+    /* (file offset, bytes), in the order they need to be written */
+    writes: vec::Vec<(u64, vec::Vec<u8>)>,
+}
+
+impl From<std::io::Error> for WriteBackError {
+    fn from(e: std::io::Error) -> WriteBackError {
+        WriteBackError::Io(e)
+    }
+}
+
+impl std::fmt::Display for WriteBackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WriteBackError::NotAFile => write!(f, "The datapath doesn't load from a file."),
+            WriteBackError::SizeChanging { index } => write!(f, "Filter #{} inserts, moves, or deletes bytes, which can't be done without rewriting the whole file. Export the data to a new file instead.", index),
+            WriteBackError::UnsupportedFilter { index } => write!(f, "Filter #{} loads data from somewhere other than the file.", index),
+            WriteBackError::Transformed { index } => write!(f, "Filter #{} transforms bytes instead of overwriting them. Export the data to a new file instead.", index),
+            WriteBackError::Disabled { index } => write!(f, "Filter #{} is disabled, and saving would throw it away. Turn it back on or remove it first.", index),
+            WriteBackError::OutOfBounds { addr } => write!(f, "The edit at 0x{:x} is outside of the file.", addr),
+            WriteBackError::FileChanged => write!(f, "The file was changed by another program since it was opened."),
+            WriteBackError::Backup(e) => write!(f, "Couldn't make a backup: {}", e),
+            WriteBackError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl Plan {
+    /// Works out how to write the datapath's edits back to the file it loads from. Only works for a file loaded at the
+    /// bottom of the datapath with nothing but overwrites on top of it.
+    pub fn new(datapath: &datapath::DataPath) -> Result<Plan, WriteBackError> {
+        let Some(datapath::Filter::LoadSpace(base)) = datapath.front() else { return Err(WriteBackError::NotAFile) };
+        let space::AddressSpace::File(fas) = &**base.space() else { return Err(WriteBackError::NotAFile) };
+
+        /* writing past the end of the file would grow it */
+        let file_size = fas.size().ok_or(WriteBackError::NotAFile)?;
+        let end = std::cmp::min(
+            base.size.map_or(u64::MAX, |size| base.load_offset.saturating_add(size)),
+            base.load_offset.saturating_add(file_size.saturating_sub(base.space_offset)));
+
+        let mut writes = vec::Vec::new();
+
+        for (index, filter) in datapath.iter().enumerate().skip(1) {
+            match filter {
+                datapath::Filter::Overwrite(of) => {
+                    if of.offset < base.load_offset {
+                        return Err(WriteBackError::OutOfBounds { addr: of.offset });
+                    }
+
+                    if of.offset.saturating_add(of.bytes.len() as u64) > end {
+                        return Err(WriteBackError::OutOfBounds { addr: std::cmp::max(of.offset, end) });
+                    }
+
+                    writes.push((base.convert_to_space(of.offset), of.bytes.clone()));
+                },
+                datapath::Filter::Insert(_) | datapath::Filter::Move(_) | datapath::Filter::Delete(_) => return Err(WriteBackError::SizeChanging { index }),
+                datapath::Filter::Transform(_) => return Err(WriteBackError::Transformed { index }),
+                datapath::Filter::LoadSpace(_) | datapath::Filter::Decompress(_) => return Err(WriteBackError::UnsupportedFilter { index }),
+                datapath::Filter::Disabled(_) => return Err(WriteBackError::Disabled { index }),
+            }
+        }
+
+        Ok(Plan {
+            base: base.clone(),
+            writes,
+        })
+    }
+
+    fn file(&self) -> &space::file::FileAddressSpace {
+        match &**self.base.space() {
+            space::AddressSpace::File(fas) => fas,
+            _ => unreachable!("Plan::new only accepts file address spaces"),
+        }
+    }
+
+    pub fn path(&self) -> &path::Path {
+        &self.file().path
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Copies the file to `backup`, writes the edits into the file, and then reloads the file so that nothing keeps
+    /// showing the old contents. Blocks, so don't call this from the main thread.
+    pub fn execute(&self, backup: &path::Path) -> Result<(), WriteBackError> {
+        let fas = self.file();
+
+        if fas.signature().is_some_and(|signature| Some(signature) != space::file::Signature::of_path(&fas.path).ok()) {
+            return Err(WriteBackError::FileChanged);
+        }
+
+        std::fs::copy(&fas.path, backup).map_err(WriteBackError::Backup)?;
+        /* the backup isn't much good if it can be lost while the original is being overwritten */
+        std::fs::File::open(backup).and_then(|file| file.sync_all()).map_err(WriteBackError::Backup)?;
+
+        let file = std::fs::OpenOptions::new().write(true).open(&fas.path)?;
+
+        for (offset, bytes) in &self.writes {
+            file.write_all_at(bytes, *offset)?;
+        }
+
+        file.sync_all()?;
+
+        fas.reload();
+
+        Ok(())
+    }
+
+    /// What the datapath should look like once the edits are in the file.
+    pub fn collapsed(&self) -> datapath::DataPath {
+        imbl::vector![self.base.clone().to_filter()].into()
+    }
+}
+
+/// Picks a name next to `path` that nothing is using yet, so that we never overwrite an older backup.
+pub fn backup_path_for(path: &path::Path) -> path::PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+
+    let mut candidate = path::PathBuf::from(&backup);
+    let mut i = 1;
+
+    while candidate.symlink_metadata().is_ok() {
+        let mut numbered = backup.clone();
+        numbered.push(format!(".{}", i));
+        candidate = path::PathBuf::from(numbered);
+        i+= 1;
+    }
+
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+    use std::sync;
+
+    use assert_matches::assert_matches;
+
+    fn create_file(name: &str, contents: &[u8]) -> (path::PathBuf, sync::Arc<space::AddressSpace>) {
+        let path = std::env::temp_dir().join(format!("charm-write-back-test-{}-{}", std::process::id(), name));
+        std::fs::File::create(&path).unwrap().write_all(contents).unwrap();
+
+        let fas = space::file::FileAddressSpace::new(path.clone(), "test");
+        fas.try_open().unwrap();
+
+        (path, sync::Arc::new(space::AddressSpace::from(fas)))
+    }
+
+    #[test]
+    fn write_back() {
+        let (path, space) = create_file("write-back", &[0xaa; 0x40]);
+
+        let datapath: datapath::DataPath = imbl::vector![
+            datapath::test_load_space(space.clone(), 0x100, 0x10, Some(0x20)),
+            datapath::OverwriteFilter { offset: 0x104, bytes: vec![1, 2, 3, 4] }.to_filter(),
+            datapath::OverwriteFilter { offset: 0x106, bytes: vec![5] }.to_filter(),
+        ].into();
+
+        let plan = Plan::new(&datapath).unwrap();
+        let backup = backup_path_for(&path);
+        plan.execute(&backup).unwrap();
+
+        let mut expected = vec![0xaa; 0x40];
+        expected[0x14..0x18].copy_from_slice(&[1, 2, 5, 4]);
+        assert_eq!(std::fs::read(&path).unwrap(), expected);
+        assert_eq!(std::fs::read(&backup).unwrap(), vec![0xaa; 0x40]);
+        assert_eq!(space.generation(), 1);
+        let collapsed = plan.collapsed();
+        assert_eq!(collapsed.len(), 1);
+        assert_matches!(&collapsed[0], datapath::Filter::LoadSpace(lsf) if lsf.load_offset == 0x100 && lsf.space_offset == 0x10);
+
+        /* a second save shouldn't clobber the first backup */
+        assert_ne!(backup_path_for(&path), backup);
+
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(backup).unwrap();
+    }
+
+    #[test]
+    fn refuse() {
+        let (path, space) = create_file("refuse", &[0xaa; 0x40]);
+
+        assert_matches!(Plan::new(&imbl::vector![
+            datapath::test_load_space(space.clone(), 0, 0, None),
+            datapath::InsertFilter { offset: 0x10, bytes: vec![1] }.to_filter(),
+        ].into()), Err(WriteBackError::SizeChanging { index: 1 }));
+
+        /* it would be lost when the datapath gets collapsed */
+        assert_matches!(Plan::new(&imbl::vector![
+            datapath::test_load_space(space.clone(), 0, 0, None),
+            datapath::OverwriteFilter { offset: 0x10, bytes: vec![1] }.to_filter(),
+            datapath::OverwriteFilter { offset: 0x20, bytes: vec![2] }.to_filter().toggled(),
+        ].into()), Err(WriteBackError::Disabled { index: 2 }));
+
+        assert_matches!(Plan::new(&imbl::vector![
+            datapath::test_load_space(space.clone(), 0, 0, None),
+            datapath::OverwriteFilter { offset: 0x3f, bytes: vec![1, 2] }.to_filter(),
+        ].into()), Err(WriteBackError::OutOfBounds { addr: 0x40 }));
+
+        assert_matches!(Plan::new(&imbl::vector![
+            datapath::test_load_space(space.clone(), 0, 0x10, Some(0x10)),
+            datapath::OverwriteFilter { offset: 0x18, bytes: vec![1; 0x10] }.to_filter(),
+        ].into()), Err(WriteBackError::OutOfBounds { addr: 0x18 }));
+
+        assert_matches!(Plan::new(&imbl::vector![datapath::test_load(vec![0; 0x10])].into()), Err(WriteBackError::NotAFile));
+
+        /* someone else got to the file first */
+        let plan = Plan::new(&imbl::vector![
+            datapath::test_load_space(space.clone(), 0, 0, None),
+            datapath::OverwriteFilter { offset: 0, bytes: vec![1] }.to_filter(),
+        ].into()).unwrap();
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(&[0xbb]).unwrap();
+        assert_matches!(plan.execute(&backup_path_for(&path)), Err(WriteBackError::FileChanged));
+        assert_eq!(std::fs::read(&path).unwrap()[0], 0xaa);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn refuse_locked_snapshot() {
+        let (path, space) = create_file("refuse-locked", &[0xaa; 0x40]);
+
+        let plan = Plan::new(&imbl::vector![
+            datapath::test_load_space(space.clone(), 0, 0, None),
+            datapath::OverwriteFilter { offset: 0, bytes: vec![1] }.to_filter(),
+        ].into()).unwrap();
+
+        let new_path = path.with_extension("new");
+        std::fs::File::create(&new_path).unwrap().write_all(&[0xbb; 0x40]).unwrap();
+        std::fs::rename(&new_path, &path).unwrap();
+
+        /* the watcher noticing the change doesn't make the edits line up with the new file */
+        assert!(space.check_for_changes(false));
+        assert_matches!(plan.execute(&backup_path_for(&path)), Err(WriteBackError::FileChanged));
+        assert_eq!(std::fs::read(&path).unwrap(), vec![0xbb; 0x40]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,4 +1,5 @@
 pub mod change;
+pub mod undo;
 pub mod search;
 pub mod structure;
 
@@ -132,6 +133,85 @@ impl Document {
         (current_node, node_addr)
     }
 
+    /// Finds where bytes inserted at `addr` should go in the structure: the deepest node that covers `addr` without any
+    /// of its children straddling it. Returns the path to that node and the offset into it, or None if `addr` is past
+    /// the end of the document.
+    pub fn insertion_point(&self, addr: addr::Address) -> Option<(structure::Path, addr::Address)> {
+        if addr > self.root.size.to_addr() {
+            return None;
+        }
+
+        let mut path = structure::Path::new();
+        let mut node = &self.root;
+        let mut offset = addr;
+
+        'descend: loop {
+            for (i, childhood) in node.children.iter().enumerate() {
+                if childhood.offset < offset && childhood.end() > offset {
+                    path.push(i);
+                    offset = (offset - childhood.offset).to_addr();
+                    node = &childhood.node;
+                    continue 'descend;
+                }
+            }
+
+            return Some((path, offset));
+        }
+    }
+
+    /// Finds where bytes in `extent` should be deleted from in the structure: the deepest node that covers all of
+    /// `extent`. Returns the path to that node and the offset of `extent` within it, or None if `extent` goes past the
+    /// end of the document.
+    pub fn deletion_point(&self, extent: addr::Extent) -> Option<(structure::Path, addr::Address)> {
+        if extent.end > self.root.size.to_addr() {
+            return None;
+        }
+
+        let mut path = structure::Path::new();
+        let mut node = &self.root;
+        let mut begin = extent.begin;
+        let length = extent.length();
+
+        'descend: loop {
+            for (i, childhood) in node.children.iter().enumerate() {
+                if childhood.offset <= begin && childhood.end() >= begin + length {
+                    path.push(i);
+                    begin = (begin - childhood.offset).to_addr();
+                    node = &childhood.node;
+                    continue 'descend;
+                }
+            }
+
+            return Some((path, begin));
+        }
+    }
+
+    /// Builds a change that deletes the bytes in `extent` and shrinks the structure around them. Nodes inside `extent`
+    /// would be left with nothing to point at, so they have to be deleted first.
+    pub fn delete_extent(&self, extent: addr::Extent) -> Result<change::Change, EditDataError> {
+        let (path, offset) = self.deletion_point(extent).ok_or(EditDataError::PastEnd)?;
+
+        let end = offset + extent.length();
+        if self.lookup_node(&path).0.children.iter().any(|childhood| childhood.offset < end && childhood.end() > offset) {
+            return Err(EditDataError::ContainsNodes);
+        }
+
+        Ok(self.delete_data(path, offset, extent.length().bytes))
+    }
+
+    /// Builds a change that applies a filter imported from a patch. Insertions and deletions resize the structure around
+    /// them, the same as if they'd been made by hand.
+    pub fn import_filter(&self, filter: datapath::Filter) -> Result<change::Change, EditDataError> {
+        match filter {
+            datapath::Filter::Insert(insert) => {
+                let (path, offset) = self.insertion_point(addr::Address::from(insert.offset)).ok_or(EditDataError::PastEnd)?;
+                Ok(self.insert_data(path, offset, insert.bytes))
+            },
+            datapath::Filter::Delete(delete) => self.delete_extent(addr::Extent::sized_u64(delete.offset, delete.size)),
+            filter => Ok(self.stack_filter(filter)),
+        }
+    }
+
     pub fn search_addr<A: Into<addr::Address>>(&self, addr: A, traversal: search::Traversal) -> Result<search::AddressSearch<'_>, search::SetupError> {
         search::AddressSearch::new(self, addr.into(), traversal)
     }
@@ -211,64 +291,94 @@ impl Document {
             generation: self.generation(),
         }
     }
-}
 
-    /*
-    pub fn patch_byte(&self, location: u64, patch: u8) {
-        self.apply_filter(datapath::OverwriteFilter {
-            offset: location,
-            bytes: vec![patch]
-        }.to_filter())
+    #[must_use]
+    pub fn stack_filter(&self, filter: datapath::Filter) -> change::Change {
+        change::Change {
+            ty: change::ChangeType::StackFilter { filter },
+            generation: self.generation(),
+        }
     }
 
-    pub fn insert_byte(&self, location: u64, patch: u8) {
-        self.apply_filter(datapath::InsertFilter {
-            offset: location,
-            bytes: vec![patch]
-        }.to_filter())
+    #[must_use]
+    pub fn insert_data(&self, path: structure::Path, offset: addr::Address, bytes: vec::Vec<u8>) -> change::Change {
+        change::Change {
+            ty: change::ChangeType::InsertData { path, offset, bytes },
+            generation: self.generation(),
+        }
     }
 
-    pub fn apply_filter(&self, filter: datapath::Filter) {
-        let mut interior = self.interior.write();
+    #[must_use]
+    pub fn delete_data(&self, path: structure::Path, offset: addr::Address, length: u64) -> change::Change {
+        change::Change {
+            ty: change::ChangeType::DeleteData { path, offset, length },
+            generation: self.generation(),
+        }
+    }
 
-        let last_undo = interior.undo.last_mut();
-        
-        /* Try to stack this filter with the most recent one. */
-        let stack = match last_undo {
-            /* If the last undo operation was a filter, try to stack with it. */
-            Some((UndoOp::AddFilter(ref mut undo_filter), ref undo_doc)) => datapath::Filter::stack(undo_filter, &filter).map(|se| (undo_filter, undo_doc, se)).ok_or(filter),
-            _ => Err(filter)
-        };
-
-        match stack {
-            /* If we successfully stacked, replace the undo op's filter with the
-             * new stacked version, revert the document, and apply the new
-             * stacked filter to the document in place of the old filter. */
-            Ok((undo_filter, undo_doc, stacked_filter)) => {
-                *undo_filter = stacked_filter.clone();
-                interior.current = undo_doc.clone();
-                interior.current.datapath.push_back(stacked_filter);
-            },
-            
-            /* Stacking failed. Just add a new undo op. */
-            Err(filter) => {
-                let old_doc = interior.current.clone();
-                interior.undo.push((UndoOp::AddFilter(filter.clone()), old_doc));
-                interior.current.datapath.push_back(filter);
-            }
+    #[must_use]
+    pub fn add_filter(&self, index: usize, filter: datapath::Filter) -> change::Change {
+        change::Change {
+            ty: change::ChangeType::AddFilter { index, filter },
+            generation: self.generation(),
         }
+    }
 
-        interior.current.datapath_generation = NEXT_DATAPATH_GENERATION.fetch_add(1, sync::atomic::Ordering::SeqCst);
-        
-        self.notifier.notify();
+    #[must_use]
+    pub fn remove_filter(&self, index: usize) -> change::Change {
+        change::Change {
+            ty: change::ChangeType::RemoveFilter { index },
+            generation: self.generation(),
+        }
+    }
+
+    #[must_use]
+    pub fn replace_filter(&self, index: usize, filter: datapath::Filter) -> change::Change {
+        change::Change {
+            ty: change::ChangeType::ReplaceFilter { index, filter },
+            generation: self.generation(),
+        }
+    }
+
+    #[must_use]
+    pub fn reorder_filter(&self, from: usize, to: usize) -> change::Change {
+        change::Change {
+            ty: change::ChangeType::ReorderFilter { from, to },
+            generation: self.generation(),
+        }
+    }
+
+    /// Folds the datapath's filters together without changing the data it produces. See [datapath::compact::compact].
+    #[must_use]
+    pub fn compact_datapath(&self) -> change::Change {
+        change::Change {
+            ty: change::ChangeType::RewriteDatapath { datapath: datapath::compact::compact(&self.datapath) },
+            generation: self.generation(),
+        }
+    }
+
+    #[must_use]
+    pub fn replace_datapath(&self, datapath: datapath::DataPath) -> change::Change {
+        change::Change {
+            ty: change::ChangeType::ReplaceDatapath { datapath },
+            generation: self.generation(),
+        }
+    }
 }
-     */
 
 #[derive(Debug, Clone, Copy)]
 pub enum DestructureError {
     AttemptToDestructureRoot,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum EditDataError {
+    /// The bytes go past the end of the document.
+    PastEnd,
+    /// The bytes being deleted have structure nodes in them.
+    ContainsNodes,
+}
+
 impl std::fmt::Debug for Document {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Document")
@@ -312,4 +422,109 @@ mod tests {
         assert_eq!(d.describe_path(&vec![1, 1]), "root.child1.child1:1");
         assert_eq!(d.describe_path(&vec![2]), "root.child2");
     }
+
+    #[test]
+    fn test_insertion_point() {
+        let root = structure::Node::builder()
+            .name("root")
+            .size(0x40)
+            .child(0x10, |b| b
+                   .name("child0")
+                   .size(0x10)
+                   .child(0x4, |b| b
+                          .name("child0:0")
+                          .size(0x4)))
+            .build();
+
+        let d = Builder::new(root).build();
+
+        assert_eq!(d.insertion_point(addr::Address::from(0x8)), Some((vec![], addr::Address::from(0x8))));
+        /* between children, so it doesn't need to go inside either one */
+        assert_eq!(d.insertion_point(addr::Address::from(0x10)), Some((vec![], addr::Address::from(0x10))));
+        assert_eq!(d.insertion_point(addr::Address::from(0x12)), Some((vec![0], addr::Address::from(0x2))));
+        assert_eq!(d.insertion_point(addr::Address::from(0x16)), Some((vec![0, 0], addr::Address::from(0x2))));
+        assert_eq!(d.insertion_point(addr::Address::from(0x40)), Some((vec![], addr::Address::from(0x40))));
+        assert_eq!(d.insertion_point(addr::Address::from(0x41)), None);
+    }
+
+    #[test]
+    fn test_deletion_point() {
+        let root = structure::Node::builder()
+            .name("root")
+            .size(0x40)
+            .child(0x10, |b| b
+                   .name("child0")
+                   .size(0x10)
+                   .child(0x4, |b| b
+                          .name("child0:0")
+                          .size(0x4)))
+            .build();
+
+        let d = Builder::new(root).build();
+
+        assert_eq!(d.deletion_point(addr::Extent::sized_u64(0x8, 0x4)), Some((vec![], addr::Address::from(0x8))));
+        assert_eq!(d.deletion_point(addr::Extent::sized_u64(0x10, 0x4)), Some((vec![0], addr::Address::from(0x0))));
+        assert_eq!(d.deletion_point(addr::Extent::sized_u64(0x15, 0x2)), Some((vec![0, 0], addr::Address::from(0x1))));
+        /* spans the end of a child, so it has to come out of the parent */
+        assert_eq!(d.deletion_point(addr::Extent::sized_u64(0x1c, 0x8)), Some((vec![], addr::Address::from(0x1c))));
+        assert_eq!(d.deletion_point(addr::Extent::sized_u64(0x3c, 0x8)), None);
+    }
+
+    #[test]
+    fn test_import_bps_delete() {
+        use crate::model::datapath::export;
+        use crate::model::datapath::patch;
+
+        let root = structure::Node::builder()
+            .name("root")
+            .size(0x40)
+            .child(0x30, |b| b
+                   .name("child0")
+                   .size(0x8))
+            .build();
+
+        let space = sync::Arc::new(space::buffer::BufferAddressSpace::new((0..0x40).collect(), "test").into());
+        let host = Builder::new(root).load_space(space).host();
+        let original = host.get();
+
+        let mut edited = original.datapath.clone();
+        edited.push_back(datapath::DeleteFilter { offset: 0x8, size: 0x8 }.to_filter());
+        let bps = futures::executor::block_on(patch::create(&edited, 0x38, &export::Progress::default())).unwrap();
+        let filters = futures::executor::block_on(patch::import(&bps.bytes, &original.datapath, &export::Progress::default())).unwrap();
+
+        for filter in filters {
+            host.change(host.get().import_filter(filter).unwrap()).unwrap();
+        }
+
+        /* the node moves back along with the bytes it covers */
+        let document = host.get();
+        assert_eq!(document.root.size, addr::Size::from(0x38));
+        assert_eq!(document.root.children[0].offset, addr::Address::from(0x28));
+
+        let mut out = vec::Vec::new();
+        futures::executor::block_on(export::export(&document.datapath, 0x38, &mut out, &export::Progress::default())).unwrap();
+        assert_eq!(out[0x28..0x30], (0x30..0x38).collect::<vec::Vec<u8>>());
+
+        /* deleting bytes out from under the node would leave it pointing at nothing */
+        let mut edited = original.datapath.clone();
+        edited.push_back(datapath::DeleteFilter { offset: 0x28, size: 0x10 }.to_filter());
+        let bps = futures::executor::block_on(patch::create(&edited, 0x30, &export::Progress::default())).unwrap();
+        let filters = futures::executor::block_on(patch::import(&bps.bytes, &original.datapath, &export::Progress::default())).unwrap();
+        assert!(filters.into_iter().any(|filter| matches!(original.import_filter(filter), Err(EditDataError::ContainsNodes))));
+    }
+
+    #[test]
+    fn test_load_space_size() {
+        let sized = sync::Arc::new(space::buffer::BufferAddressSpace::new(vec![0; 0x30], "sized").into());
+        let d = Builder::default().load_space(sized).build();
+
+        let datapath::Filter::LoadSpace(lsf) = &d.datapath[0] else { panic!("expected load space filter") };
+        assert_eq!(lsf.size, Some(0x30));
+
+        let unknown = sync::Arc::new(space::gdb::GdbAddressSpace::new(space::gdb::Target::Tcp("localhost:0".to_string()), "unknown").into());
+        let d = Builder::default().load_space(unknown).build();
+
+        let datapath::Filter::LoadSpace(lsf) = &d.datapath[0] else { panic!("expected load space filter") };
+        assert_eq!(lsf.size, None);
+    }
 }
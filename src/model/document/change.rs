@@ -1,6 +1,10 @@
 use std::sync;
+use std::task;
+use std::vec;
 
 use crate::model::addr;
+use crate::model::datapath;
+use crate::model::datapath::DataPathExt;
 use crate::model::document;
 use crate::model::document::structure;
 use crate::model::selection;
@@ -51,6 +55,92 @@ pub enum ChangeType {
     DeleteRange {
         range: structure::SiblingRange,
     },
+
+    /// Pushes a filter onto the top of the datapath, stacking it with the topmost filter if possible.
+    StackFilter {
+        filter: datapath::Filter,
+    },
+
+    /// Removes the topmost filter from the datapath (if `remove` is set), then pushes `filter` (if any) without trying
+    /// to stack it. This is mostly useful for undoing StackFilter.
+    ReplaceTopFilter {
+        remove: bool,
+        filter: Option<datapath::Filter>,
+    },
+
+    /// Inserts bytes into the data at an offset within the node referred to by the given path. That node and all of its
+    /// ancestors grow to fit the new bytes, and any nodes after the insertion point get shifted over. The insertion
+    /// point can't be in the middle of one of the node's children.
+    InsertData {
+        path: structure::Path,
+        offset: addr::Address,
+        bytes: vec::Vec<u8>,
+    },
+
+    /// Undoes InsertData. Shrinks the structure back down around the inserted bytes, removes the topmost filter from
+    /// the datapath, and pushes `filter` (if any) in its place. There can't be any nodes inside the removed range.
+    RemoveInsertedData {
+        path: structure::Path,
+        offset: addr::Address,
+        length: u64,
+        filter: Option<datapath::Filter>,
+    },
+
+    /// Deletes bytes from the data at an offset within the node referred to by the given path. That node and all of its
+    /// ancestors shrink around the deleted bytes, and any nodes after them get shifted back. There can't be any nodes
+    /// inside the deleted range.
+    DeleteData {
+        path: structure::Path,
+        offset: addr::Address,
+        length: u64,
+    },
+
+    /// Undoes DeleteData. Grows the structure back out around the deleted bytes, removes the topmost filter from the
+    /// datapath, and pushes `filter` (if any) in its place.
+    RestoreDeletedData {
+        path: structure::Path,
+        offset: addr::Address,
+        length: u64,
+        filter: Option<datapath::Filter>,
+    },
+
+    /// Inserts a filter into the datapath at the given index, without trying to stack it. An index equal to the
+    /// length of the datapath puts the filter on top.
+    AddFilter {
+        index: usize,
+        filter: datapath::Filter,
+    },
+
+    /// Removes the filter at the given index from the datapath.
+    RemoveFilter {
+        index: usize,
+    },
+
+    /// Replaces the filter at the given index in the datapath.
+    ReplaceFilter {
+        index: usize,
+        filter: datapath::Filter,
+    },
+
+    /// Moves the filter at index `from` in the datapath so that it ends up at index `to`. The filters in between can't
+    /// include any that shift addresses, since the moved filter would no longer line up with the data it was meant for.
+    ReorderFilter {
+        from: usize,
+        to: usize,
+    },
+
+    /// Replaces the entire datapath. This is for after the data underneath the datapath has been changed to match what
+    /// the datapath produced (e.g. by writing edits back to a file), so it can't be undone. Undoing edits from before
+    /// this point wouldn't bring back the old data anymore.
+    ReplaceDatapath {
+        datapath: datapath::DataPath,
+    },
+
+    /// Replaces the entire datapath with one that produces the same data, like a compacted version of it. Unlike
+    /// ReplaceDatapath, the old datapath still produces the right data afterwards, so this can be undone.
+    RewriteDatapath {
+        datapath: datapath::DataPath,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -102,6 +192,8 @@ pub enum UpdateError {
     NotYetImplemented,
     NodeDeleted,
     RangeSplit,
+    FilterRemoved,
+    FilterReplaced,
 }
 
 #[derive(Debug, Clone)]
@@ -211,6 +303,103 @@ impl Change {
                     UpdatePathResult::Unmoved
                 }
             },
+            ChangeType::StackFilter { .. } => UpdatePathResult::Unmoved,
+            ChangeType::ReplaceTopFilter { .. } => UpdatePathResult::Unmoved,
+            ChangeType::InsertData { .. } => UpdatePathResult::Unmoved,
+            ChangeType::RemoveInsertedData { .. } => UpdatePathResult::Unmoved,
+            ChangeType::DeleteData { .. } => UpdatePathResult::Unmoved,
+            ChangeType::RestoreDeletedData { .. } => UpdatePathResult::Unmoved,
+            ChangeType::AddFilter { .. } => UpdatePathResult::Unmoved,
+            ChangeType::RemoveFilter { .. } => UpdatePathResult::Unmoved,
+            ChangeType::ReplaceFilter { .. } => UpdatePathResult::Unmoved,
+            ChangeType::ReorderFilter { .. } => UpdatePathResult::Unmoved,
+            ChangeType::ReplaceDatapath { .. } => UpdatePathResult::Unmoved,
+            ChangeType::RewriteDatapath { .. } => UpdatePathResult::Unmoved,
+        }
+    }
+
+    /// Updates the index of a filter in the datapath to account for this change. If `insertion_point` is set, the
+    /// index refers to a position between filters (where a new filter would go) instead of an existing filter.
+    /// `before` and `after` are the documents from before and after this change was applied.
+    pub fn update_filter_index(&self, before: &document::Document, after: &document::Document, index: usize, insertion_point: bool) -> Result<usize, UpdateError> {
+        let top = before.datapath.len().checked_sub(1);
+        
+        match &self.ty {
+            ChangeType::AddFilter { index: added, .. } if *added <= index => Ok(index + 1),
+            ChangeType::AddFilter { .. } => Ok(index),
+            
+            ChangeType::RemoveFilter { index: removed } if *removed == index && !insertion_point => Err(UpdateError::FilterRemoved),
+            ChangeType::RemoveFilter { index: removed } if *removed < index => Ok(index - 1),
+            ChangeType::RemoveFilter { .. } => Ok(index),
+            
+            ChangeType::ReplaceFilter { index: replaced, .. } if *replaced == index && !insertion_point => Err(UpdateError::FilterReplaced),
+            ChangeType::ReplaceFilter { .. } => Ok(index),
+
+            ChangeType::ReorderFilter { from, to } if *from == index && !insertion_point => Ok(*to),
+            ChangeType::ReorderFilter { from, to } => {
+                /* take it out, then put it back somewhere else */
+                let index = if *from < index { index - 1 } else { index };
+                Ok(if *to <= index { index + 1 } else { index })
+            },
+
+            /* None of the old filters are where they used to be. */
+            ChangeType::ReplaceDatapath { .. } | ChangeType::RewriteDatapath { .. } => Err(UpdateError::FilterReplaced),
+
+            /* These can change the topmost filter, either by stacking onto it or by removing it. */
+            ChangeType::StackFilter { .. } | ChangeType::InsertData { .. } | ChangeType::DeleteData { .. } if Some(index) == top && !insertion_point && after.datapath.len() == before.datapath.len() => Err(UpdateError::FilterReplaced),
+            ChangeType::ReplaceTopFilter { remove: true, .. } | ChangeType::RemoveInsertedData { .. } | ChangeType::RestoreDeletedData { .. } if Some(index) == top && !insertion_point => Err(UpdateError::FilterRemoved),
+
+            _ => Ok(index),
+        }
+    }
+
+    /// Updates an offset within the node referred to by the given path to account for data that was inserted or removed
+    /// by this change. `root` should be the root node of the document produced by this change.
+    pub fn update_offset(&self, root: &structure::Node, path: structure::PathSlice, offset: &mut addr::Address) {
+        let (insertion_path, insertion_offset) = match &self.ty {
+            ChangeType::InsertData { path, offset, .. } => (path, offset),
+            ChangeType::RemoveInsertedData { path, offset, .. } => (path, offset),
+            ChangeType::DeleteData { path, offset, .. } => (path, offset),
+            ChangeType::RestoreDeletedData { path, offset, .. } => (path, offset),
+            _ => return,
+        };
+
+        /* If the data went into this node's descendants, they grew but the offset into this node didn't move. If it
+         * went somewhere else entirely, this node wasn't affected at all. */
+        if insertion_path.len() < path.len() || insertion_path[0..path.len()] != path[..] {
+            return;
+        }
+
+        /* Figure out where the data was inserted relative to this node. The offsets of the nodes along the way were not
+         * affected by the change, so it's fine to look them up in the new document. */
+        let mut point = *insertion_offset;
+        let mut node = root;
+        for index in path {
+            node = &node.children[*index].node;
+        }
+        
+        for index in &insertion_path[path.len()..] {
+            point+= node.children[*index].offset.to_size();
+            node = &node.children[*index].node;
+        }
+
+        match &self.ty {
+            ChangeType::InsertData { bytes, .. } if *offset > point => {
+                *offset+= addr::Size::from(bytes.len() as u64);
+            },
+            ChangeType::RestoreDeletedData { length, .. } if *offset > point => {
+                *offset+= addr::Size::from(*length);
+            },
+            ChangeType::RemoveInsertedData { length, .. } | ChangeType::DeleteData { length, .. } => {
+                let end = point + addr::Size::from(*length);
+                
+                if *offset >= end {
+                    *offset-= addr::Size::from(*length);
+                } else if *offset > point {
+                    *offset = point;
+                }
+            },
+            _ => {},
         }
     }
 
@@ -285,6 +474,19 @@ impl Change {
                     UpdateRangeResult::Unmoved(subject)
                 }
             },
+
+            ChangeType::StackFilter { .. } => UpdateRangeResult::Unmoved(subject),
+            ChangeType::ReplaceTopFilter { .. } => UpdateRangeResult::Unmoved(subject),
+            ChangeType::InsertData { .. } => UpdateRangeResult::Unmoved(subject),
+            ChangeType::RemoveInsertedData { .. } => UpdateRangeResult::Unmoved(subject),
+            ChangeType::DeleteData { .. } => UpdateRangeResult::Unmoved(subject),
+            ChangeType::RestoreDeletedData { .. } => UpdateRangeResult::Unmoved(subject),
+            ChangeType::AddFilter { .. } => UpdateRangeResult::Unmoved(subject),
+            ChangeType::RemoveFilter { .. } => UpdateRangeResult::Unmoved(subject),
+            ChangeType::ReplaceFilter { .. } => UpdateRangeResult::Unmoved(subject),
+            ChangeType::ReorderFilter { .. } => UpdateRangeResult::Unmoved(subject),
+            ChangeType::ReplaceDatapath { .. } => UpdateRangeResult::Unmoved(subject),
+            ChangeType::RewriteDatapath { .. } => UpdateRangeResult::Unmoved(subject),
         }
     }
 
@@ -338,6 +540,57 @@ impl Change {
                                 | UpdateRangeResult::Inserted { .. }
                             => Err(UpdateError::RangeSplit),
                         },
+                        /* Stacking doesn't care what else is on the datapath. */
+                        ChangeType::StackFilter { .. } => Ok(self.ty),
+                        /* The top of the datapath is only the same if no other datapath changes were made in the meantime. */
+                        ChangeType::ReplaceTopFilter { .. } => match doc_change.ty {
+                            ChangeType::StackFilter { .. }
+                                | ChangeType::ReplaceTopFilter { .. }
+                                | ChangeType::InsertData { .. }
+                                | ChangeType::RemoveInsertedData { .. }
+                                | ChangeType::DeleteData { .. }
+                                | ChangeType::RestoreDeletedData { .. }
+                                | ChangeType::AddFilter { .. }
+                                | ChangeType::RemoveFilter { .. }
+                                | ChangeType::ReplaceFilter { .. }
+                                | ChangeType::ReorderFilter { .. }
+                                | ChangeType::ReplaceDatapath { .. }
+                                | ChangeType::RewriteDatapath { .. }
+                            => Err(UpdateError::NotUpdatable),
+                            _ => Ok(self.ty),
+                        },
+                        ChangeType::InsertData { ref mut path, .. } | ChangeType::DeleteData { ref mut path, .. } => match doc_change.update_path(path) {
+                            UpdatePathResult::Unmoved | UpdatePathResult::Moved => Ok(self.ty),
+                            UpdatePathResult::Deleted | UpdatePathResult::Destructured => Err(UpdateError::NodeDeleted),
+                        },
+                        /* Same deal as ReplaceTopFilter, plus the structure needs to look the same as it did after the
+                         * insertion or deletion. */
+                        ChangeType::RemoveInsertedData { .. } | ChangeType::RestoreDeletedData { .. } => Err(UpdateError::NotUpdatable),
+                        ChangeType::AddFilter { index, filter } => doc_change.update_filter_index(prev_document, to, index, true).map(|index| ChangeType::AddFilter { index, filter }),
+                        ChangeType::RemoveFilter { index } => doc_change.update_filter_index(prev_document, to, index, false).map(|index| ChangeType::RemoveFilter { index }),
+                        ChangeType::ReplaceFilter { index, filter } => doc_change.update_filter_index(prev_document, to, index, false).map(|index| ChangeType::ReplaceFilter { index, filter }),
+                        /* The filter that used to be at the destination needs to still be around, since the moved filter
+                         * was meant to end up next to it. */
+                        ChangeType::ReorderFilter { from, to: destination } => doc_change.update_filter_index(prev_document, to, from, false).and_then(|from| {
+                            doc_change.update_filter_index(prev_document, to, destination, false).map(|destination| ChangeType::ReorderFilter { from, to: destination })
+                        }),
+                        /* The new datapath was based on the old one, so it's only right if nobody touched the old one. */
+                        ChangeType::ReplaceDatapath { .. } | ChangeType::RewriteDatapath { .. } => match doc_change.ty {
+                            ChangeType::StackFilter { .. }
+                                | ChangeType::ReplaceTopFilter { .. }
+                                | ChangeType::InsertData { .. }
+                                | ChangeType::RemoveInsertedData { .. }
+                                | ChangeType::DeleteData { .. }
+                                | ChangeType::RestoreDeletedData { .. }
+                                | ChangeType::AddFilter { .. }
+                                | ChangeType::RemoveFilter { .. }
+                                | ChangeType::ReplaceFilter { .. }
+                                | ChangeType::ReorderFilter { .. }
+                                | ChangeType::ReplaceDatapath { .. }
+                                | ChangeType::RewriteDatapath { .. }
+                            => Err(UpdateError::NotUpdatable),
+                            _ => Ok(self.ty),
+                        },
                     }.map_err(|e| (e, backup, Some(doc_change.clone())))?,
                     generation: to.generation()
                 })
@@ -459,6 +712,132 @@ impl Change {
 
                 Ok(())
             })?),
+            ChangeType::StackFilter { filter } => {
+                /* those come in through InsertData and DeleteData, which resize the structure to match */
+                if filter.resizes_structure() {
+                    return Err(ApplyErrorType::InvalidParameters("attempted to stack filter that inserts or deletes bytes"));
+                }
+
+                stack_filter(&mut document.datapath, filter);
+            },
+            ChangeType::ReplaceTopFilter { remove, filter } => {
+                if *remove && document.datapath.pop_back().is_none() {
+                    return Err(ApplyErrorType::InvalidParameters("attempted to remove filter from empty datapath"));
+                }
+
+                if let Some(filter) = filter {
+                    document.datapath.push_back(filter.clone());
+                }
+            },
+            ChangeType::InsertData { path, offset, bytes } => {
+                let address = document.lookup_node(path).1 + offset.to_size();
+                if address.bit != 0 {
+                    return Err(ApplyErrorType::InvalidParameters("attempted to insert data at an address that isn't byte-aligned"));
+                }
+
+                document.root = sync::Arc::new(insert_space(&document.root, path, *offset, addr::Size::from(bytes.len() as u64))?);
+
+                stack_filter(&mut document.datapath, &datapath::InsertFilter {
+                    offset: address.byte,
+                    bytes: bytes.clone(),
+                }.to_filter());
+            },
+            ChangeType::RemoveInsertedData { path, offset, length, filter } => {
+                document.root = sync::Arc::new(remove_space(&document.root, path, *offset, addr::Size::from(*length))?);
+
+                if document.datapath.pop_back().is_none() {
+                    return Err(ApplyErrorType::InvalidParameters("attempted to remove filter from empty datapath"));
+                }
+
+                if let Some(filter) = filter {
+                    document.datapath.push_back(filter.clone());
+                }
+            },
+            ChangeType::DeleteData { path, offset, length } => {
+                let address = document.lookup_node(path).1 + offset.to_size();
+                if address.bit != 0 {
+                    return Err(ApplyErrorType::InvalidParameters("attempted to delete data at an address that isn't byte-aligned"));
+                }
+
+                if *length == 0 {
+                    return Err(ApplyErrorType::InvalidParameters("attempted to delete zero bytes"));
+                }
+
+                document.root = sync::Arc::new(remove_space(&document.root, path, *offset, addr::Size::from(*length))?);
+
+                stack_filter(&mut document.datapath, &datapath::DeleteFilter {
+                    offset: address.byte,
+                    size: *length,
+                }.to_filter());
+            },
+            ChangeType::RestoreDeletedData { path, offset, length, filter } => {
+                document.root = sync::Arc::new(insert_space(&document.root, path, *offset, addr::Size::from(*length))?);
+
+                if document.datapath.pop_back().is_none() {
+                    return Err(ApplyErrorType::InvalidParameters("attempted to remove filter from empty datapath"));
+                }
+
+                if let Some(filter) = filter {
+                    document.datapath.push_back(filter.clone());
+                }
+            },
+            ChangeType::AddFilter { index, filter } => {
+                if *index > document.datapath.len() {
+                    return Err(ApplyErrorType::InvalidParameters("attempted to add filter at out-of-bounds index"));
+                }
+
+                if filter.resizes_structure() {
+                    return Err(ApplyErrorType::InvalidParameters("attempted to add filter that inserts or deletes bytes"));
+                }
+
+                document.datapath.insert(*index, filter.clone());
+            },
+            ChangeType::RemoveFilter { index } => {
+                if *index >= document.datapath.len() {
+                    return Err(ApplyErrorType::InvalidParameters("attempted to remove filter at out-of-bounds index"));
+                }
+
+                /* the structure was resized around it, so only undoing the change that made it can take it out */
+                if document.datapath[*index].resizes_structure() {
+                    return Err(ApplyErrorType::InvalidParameters("attempted to remove filter that inserts or deletes bytes"));
+                }
+
+                document.datapath.remove(*index);
+            },
+            ChangeType::ReplaceFilter { index, filter } => {
+                if *index >= document.datapath.len() {
+                    return Err(ApplyErrorType::InvalidParameters("attempted to replace filter at out-of-bounds index"));
+                }
+
+                let replaced = &document.datapath[*index];
+                if (replaced.resizes_structure() || filter.resizes_structure()) && !replaced.resizes_like(filter) {
+                    return Err(ApplyErrorType::InvalidParameters("attempted to replace filter with one that inserts or deletes different bytes"));
+                }
+
+                document.datapath.set(*index, filter.clone());
+            },
+            ChangeType::ReorderFilter { from, to } => {
+                if *from >= document.datapath.len() || *to >= document.datapath.len() {
+                    return Err(ApplyErrorType::InvalidParameters("attempted to move filter to or from out-of-bounds index"));
+                }
+
+                /* The moved filter passes every filter between the two indices, so neither it nor any of them can be
+                 * one that the others depend on for their addresses. */
+                let (low, high) = (std::cmp::min(*from, *to), std::cmp::max(*from, *to));
+                if low != high && document.datapath.iter().skip(low).take(high - low + 1).any(|filter| filter.shifts_addresses()) {
+                    return Err(ApplyErrorType::InvalidParameters("attempted to move filter past one that shifts addresses"));
+                }
+
+                /* nothing can go underneath the address space that everything else is loaded from */
+                if low == 0 && low != high && matches!(document.datapath[0], datapath::Filter::LoadSpace(_)) {
+                    return Err(ApplyErrorType::InvalidParameters("attempted to move filter underneath the address space it's loaded from"));
+                }
+
+                let filter = document.datapath.remove(*from);
+                document.datapath.insert(*to, filter);
+            },
+            ChangeType::ReplaceDatapath { datapath } => document.datapath = datapath.clone(),
+            ChangeType::RewriteDatapath { datapath } => document.datapath = datapath.clone(),
         };
 
         Ok(())
@@ -472,8 +851,147 @@ impl Change {
             ChangeType::Nest { range, .. } => format!("Nest children under {}", document.describe_path(&range.parent)),
             ChangeType::Destructure { parent, .. } => format!("Destructure child under {}", document.describe_path(parent)),
             ChangeType::DeleteRange { range, .. } => format!("Delete children under {}", document.describe_path(&range.parent)),
+            ChangeType::StackFilter { filter } => format!("Edit data at {:#x}", filter.human_affects_addr()),
+            ChangeType::ReplaceTopFilter { .. } => "Revert data edit".to_string(),
+            ChangeType::InsertData { path, bytes, .. } => format!("Insert {} bytes into {}", bytes.len(), document.describe_path(path)),
+            ChangeType::RemoveInsertedData { path, length, .. } => format!("Remove {} inserted bytes from {}", length, document.describe_path(path)),
+            ChangeType::DeleteData { path, length, .. } => format!("Delete {} bytes from {}", length, document.describe_path(path)),
+            ChangeType::RestoreDeletedData { path, length, .. } => format!("Restore {} deleted bytes to {}", length, document.describe_path(path)),
+            ChangeType::AddFilter { filter, .. } => format!("Add filter at {:#x}", filter.human_affects_addr()),
+            ChangeType::RemoveFilter { index } => format!("Remove filter #{}", index),
+            ChangeType::ReplaceFilter { filter, .. } => format!("Replace filter at {:#x}", filter.human_affects_addr()),
+            ChangeType::ReorderFilter { from, to } => format!("Move filter #{} to #{}", from, to),
+            ChangeType::ReplaceDatapath { .. } => "Replace datapath".to_string(),
+            ChangeType::RewriteDatapath { datapath } => format!("Rewrite datapath with {} filters", datapath.len()),
         }
     }
+
+    /// Produces a change that undoes this one. `before` and `after` are the documents from before and after this change
+    /// was applied. The resulting change is stamped with the generation of `after`. Returns None if this type of
+    /// change can't be inverted.
+    pub fn invert(&self, before: &document::Document, after: &document::Document) -> Option<Change> {
+        let ty = match &self.ty {
+            ChangeType::AlterNode { path, .. } => ChangeType::AlterNode {
+                path: path.clone(),
+                props: before.lookup_node(path).0.props.clone()
+            },
+            ChangeType::AlterNodesBulk { .. } => return None,
+            ChangeType::InsertNode { parent, index, .. } => ChangeType::DeleteRange {
+                range: structure::SiblingRange::new(parent.clone(), *index, *index)
+            },
+            ChangeType::Nest { range, extent, .. } => ChangeType::Destructure {
+                parent: range.parent.clone(),
+                child_index: range.first,
+                num_grandchildren: range.count(),
+                offset: extent.begin,
+            },
+            ChangeType::Destructure { parent, child_index, num_grandchildren, offset } => {
+                let childhood = &before.lookup_node(parent).0.children[*child_index];
+
+                if *num_grandchildren == 0 {
+                    /* Can't nest zero children, but destructuring a childless node is the same as deleting it. */
+                    ChangeType::InsertNode {
+                        parent: parent.clone(),
+                        index: *child_index,
+                        child: childhood.clone(),
+                    }
+                } else {
+                    ChangeType::Nest {
+                        range: structure::SiblingRange::new(parent.clone(), *child_index, *child_index + *num_grandchildren - 1),
+                        extent: addr::Extent::sized(*offset, childhood.node.size),
+                        props: childhood.node.props.clone(),
+                    }
+                }
+            },
+            ChangeType::DeleteRange { range } if range.count() == 1 => ChangeType::InsertNode {
+                parent: range.parent.clone(),
+                index: range.first,
+                child: before.lookup_node(&range.parent).0.children[range.first].clone(),
+            },
+            // TODO: need a way to insert multiple nodes at once
+            ChangeType::DeleteRange { .. } => return None,
+            ChangeType::StackFilter { .. } => ChangeType::ReplaceTopFilter {
+                remove: true,
+                /* If the filter got stacked, we need to put back the one it was stacked onto. */
+                filter: if after.datapath.len() == before.datapath.len() {
+                    before.datapath.last().cloned()
+                } else {
+                    None
+                },
+            },
+            ChangeType::ReplaceTopFilter { remove, filter } => ChangeType::ReplaceTopFilter {
+                remove: filter.is_some(),
+                filter: if *remove {
+                    before.datapath.last().cloned()
+                } else {
+                    None
+                },
+            },
+            ChangeType::InsertData { path, offset, bytes } => ChangeType::RemoveInsertedData {
+                path: path.clone(),
+                offset: *offset,
+                length: bytes.len() as u64,
+                /* If the insertion got stacked, we need to put back the filter it was stacked onto. */
+                filter: if after.datapath.len() == before.datapath.len() {
+                    before.datapath.last().cloned()
+                } else {
+                    None
+                },
+            },
+            ChangeType::RemoveInsertedData { path, offset, length, .. } => {
+                /* The bytes came from an InsertFilter, so they're always available without waiting on any I/O. */
+                let address = before.lookup_node(path).1 + offset.to_size();
+                let mut records = vec![datapath::ByteRecord::default(); *length as usize];
+                before.datapath.fetch(datapath::ByteRecordRange::new(address.byte, &mut records), &mut task::Context::from_waker(futures::task::noop_waker_ref()));
+
+                ChangeType::InsertData {
+                    path: path.clone(),
+                    offset: *offset,
+                    bytes: records.iter().map(|record| record.value).collect(),
+                }
+            },
+            ChangeType::DeleteData { path, offset, length } => ChangeType::RestoreDeletedData {
+                path: path.clone(),
+                offset: *offset,
+                length: *length,
+                /* If the deletion got stacked, we need to put back the filter it was stacked onto. */
+                filter: if after.datapath.len() == before.datapath.len() {
+                    before.datapath.last().cloned()
+                } else {
+                    None
+                },
+            },
+            ChangeType::RestoreDeletedData { path, offset, length, .. } => ChangeType::DeleteData {
+                path: path.clone(),
+                offset: *offset,
+                length: *length,
+            },
+            ChangeType::AddFilter { index, .. } => ChangeType::RemoveFilter {
+                index: *index,
+            },
+            ChangeType::RemoveFilter { index } => ChangeType::AddFilter {
+                index: *index,
+                filter: before.datapath[*index].clone(),
+            },
+            ChangeType::ReplaceFilter { index, .. } => ChangeType::ReplaceFilter {
+                index: *index,
+                filter: before.datapath[*index].clone(),
+            },
+            ChangeType::ReorderFilter { from, to } => ChangeType::ReorderFilter {
+                from: *to,
+                to: *from,
+            },
+            ChangeType::ReplaceDatapath { .. } => return None,
+            ChangeType::RewriteDatapath { .. } => ChangeType::RewriteDatapath {
+                datapath: before.datapath.clone(),
+            },
+        };
+
+        Some(Change {
+            ty,
+            generation: after.generation(),
+        })
+    }
 }
 
 fn rebuild_node_tree_visiting_path<F, Iter: std::iter::Iterator<Item = usize>>(target: &structure::Node, mut path_segment: Iter, target_modifier: F) -> Result<structure::Node, ApplyErrorType> where
@@ -496,6 +1014,108 @@ fn rebuild_node_tree_visiting_path<F, Iter: std::iter::Iterator<Item = usize>>(t
     }
 }
 
+/// Like [rebuild_node_tree_visiting_path], but also lets each node along the path be modified after its child has been
+/// rebuilt. `ancestor_modifier` gets the index of the child that was on the path.
+fn rebuild_node_tree_resizing_path<F, G, Iter: std::iter::Iterator<Item = usize>>(target: &structure::Node, mut path_segment: Iter, target_modifier: F, ancestor_modifier: G) -> Result<structure::Node, ApplyErrorType> where
+    F: FnOnce(&mut structure::Node) -> Result<(), ApplyErrorType>,
+    G: Fn(&mut structure::Node, usize) + Copy {
+    match path_segment.next() {
+        Some(index) => {
+            let child = &*target.children[index].node;
+            let new_child = rebuild_node_tree_resizing_path(child, path_segment, target_modifier, ancestor_modifier)?;
+            let mut new_target = (*target).clone();
+            new_target.children[index].node = sync::Arc::new(new_child);
+            ancestor_modifier(&mut new_target, index);
+            Ok(new_target)
+        },
+        None => {
+            let mut new_target = (*target).clone();
+            target_modifier(&mut new_target)?;
+            Ok(new_target)
+        }
+    }
+}
+
+/// Makes room for `size` bytes at `offset` within the node at `path`. That node and all of its ancestors grow to fit,
+/// and anything after the offset gets shifted over.
+fn insert_space(root: &structure::Node, path: &structure::Path, offset: addr::Address, size: addr::Size) -> Result<structure::Node, ApplyErrorType> {
+    rebuild_node_tree_resizing_path(root, path.iter().cloned(), |target| {
+        if offset > target.size.to_addr() {
+            return Err(ApplyErrorType::InvalidParameters("attempted to insert data beyond node's size"));
+        }
+
+        if target.children.iter().any(|childhood| childhood.offset < offset && childhood.end() > offset) {
+            return Err(ApplyErrorType::InvalidParameters("attempted to insert data in the middle of a child"));
+        }
+
+        for childhood in target.children.iter_mut().filter(|childhood| childhood.offset >= offset) {
+            childhood.offset+= size;
+        }
+
+        grow_node(target, size);
+
+        Ok(())
+    }, |target, index| {
+        for childhood in &mut target.children[index+1..] {
+            childhood.offset+= size;
+        }
+
+        grow_node(target, size);
+    })
+}
+
+/// Takes `size` bytes at `offset` out of the node at `path`. That node and all of its ancestors shrink around them, and
+/// anything after them gets shifted back.
+fn remove_space(root: &structure::Node, path: &structure::Path, offset: addr::Address, size: addr::Size) -> Result<structure::Node, ApplyErrorType> {
+    let end = offset + size;
+
+    rebuild_node_tree_resizing_path(root, path.iter().cloned(), |target| {
+        if end > target.size.to_addr() {
+            return Err(ApplyErrorType::InvalidParameters("attempted to remove data beyond node's size"));
+        }
+
+        if target.children.iter().any(|childhood| childhood.offset < end && childhood.end() > offset) {
+            return Err(ApplyErrorType::InvalidParameters("attempted to remove data that children still occupy"));
+        }
+
+        for childhood in target.children.iter_mut().filter(|childhood| childhood.offset >= end) {
+            childhood.offset-= size;
+        }
+
+        shrink_node(target, size);
+
+        Ok(())
+    }, |target, index| {
+        for childhood in &mut target.children[index+1..] {
+            childhood.offset-= size;
+        }
+
+        shrink_node(target, size);
+    })
+}
+
+/* Nodes with the maximum size are treated as unbounded, so they don't get resized. */
+fn grow_node(node: &mut structure::Node, size: addr::Size) {
+    if node.size != addr::unit::MAX {
+        node.size = node.size + size;
+    }
+}
+
+fn shrink_node(node: &mut structure::Node, size: addr::Size) {
+    if node.size != addr::unit::MAX {
+        node.size-= size;
+    }
+}
+
+fn stack_filter(datapath: &mut datapath::DataPath, filter: &datapath::Filter) {
+    let stacked = datapath.last().and_then(|top| datapath::Filter::stack(top, filter));
+
+    match stacked {
+        Some(stacked) => *datapath.back_mut().unwrap() = stacked,
+        None => datapath.push_back(filter.clone()),
+    }
+}
+
 struct TreeRewritingVisitor<F: Fn(&mut structure::Node) -> Result<(), ApplyErrorType>>(F);
 
 impl<'a, F: Fn(&mut structure::Node) -> Result<(), ApplyErrorType>> selection::tree::TreeVisitor<'a> for TreeRewritingVisitor<F> {
@@ -546,8 +1166,13 @@ impl versioned::Change<document::Document> for Change {
 
         assert_eq!(self.generation, document.generation());
 
+        let before = document.datapath.clone();
+
         match self.apply_impl(document) {
-            Ok(()) => Ok((self.clone(), self)),
+            Ok(()) => {
+                datapath::refresh_decompression(&before, &mut document.datapath);
+                Ok((self.clone(), self))
+            },
             Err(ty) => Err(ty.complete(self)),
         }
     }
@@ -574,6 +1199,7 @@ mod tests {
 
     use assert_matches::assert_matches;
 
+    use crate::model::space;
     use crate::model::versioned::Change as VersionedChange;
     
     #[test]
@@ -785,6 +1411,92 @@ mod tests {
         assert_eq!(path, vec![1, 0, 5, 4]);
     }
 
+    #[test]
+    fn test_update_path_through_stack_filter() {
+        let mut path = vec![1, 0, 2];
+
+        assert_eq!(Change {
+            ty: ChangeType::StackFilter { filter: datapath::OverwriteFilter { offset: 0, bytes: vec![0x12] }.to_filter() },
+            generation: 0,
+        }.update_path(&mut path), UpdatePathResult::Unmoved);
+        
+        assert_eq!(path, vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn test_update_path_through_replace_top_filter() {
+        let mut path = vec![1, 0, 2];
+
+        assert_eq!(Change {
+            ty: ChangeType::ReplaceTopFilter { remove: true, filter: None },
+            generation: 0,
+        }.update_path(&mut path), UpdatePathResult::Unmoved);
+        
+        assert_eq!(path, vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn test_update_path_through_insert_data() {
+        let mut path = vec![1, 0, 2];
+
+        assert_eq!(Change {
+            ty: ChangeType::InsertData { path: vec![1], offset: addr::unit::NULL, bytes: vec![0x12] },
+            generation: 0,
+        }.update_path(&mut path), UpdatePathResult::Unmoved);
+        
+        assert_eq!(path, vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn test_update_path_through_filter_changes() {
+        let mut path = vec![1, 0, 2];
+
+        for ty in [
+            ChangeType::AddFilter { index: 0, filter: datapath::OverwriteFilter { offset: 0x10, bytes: vec![0x12] }.to_filter() },
+            ChangeType::RemoveFilter { index: 0 },
+            ChangeType::ReplaceFilter { index: 0, filter: datapath::OverwriteFilter { offset: 0x10, bytes: vec![0x12] }.to_filter() },
+            ChangeType::ReorderFilter { from: 0, to: 1 },
+            ChangeType::ReplaceDatapath { datapath: datapath::DataPath::new() },
+            ChangeType::RewriteDatapath { datapath: datapath::DataPath::new() },
+        ] {
+            assert_eq!(Change {
+                ty,
+                generation: 0,
+            }.update_path(&mut path), UpdatePathResult::Unmoved);
+        }
+        
+        assert_eq!(path, vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn test_update_path_through_remove_inserted_data() {
+        let mut path = vec![1, 0, 2];
+
+        assert_eq!(Change {
+            ty: ChangeType::RemoveInsertedData { path: vec![1], offset: addr::unit::NULL, length: 1, filter: None },
+            generation: 0,
+        }.update_path(&mut path), UpdatePathResult::Unmoved);
+        
+        assert_eq!(path, vec![1, 0, 2]);
+    }
+    
+    #[test]
+    fn test_update_path_through_delete_data() {
+        let mut path = vec![1, 0, 2];
+
+        assert_eq!(Change {
+            ty: ChangeType::DeleteData { path: vec![1], offset: addr::unit::NULL, length: 1 },
+            generation: 0,
+        }.update_path(&mut path), UpdatePathResult::Unmoved);
+
+        assert_eq!(Change {
+            ty: ChangeType::RestoreDeletedData { path: vec![1], offset: addr::unit::NULL, length: 1, filter: None },
+            generation: 0,
+        }.update_path(&mut path), UpdatePathResult::Unmoved);
+
+        assert_eq!(path, vec![1, 0, 2]);
+    }
+
     /* This exists to produce errors if another ChangeType gets added without corresponding tests. */
     fn update_path_exhaustiveness(ty: ChangeType) {
         match ty {
@@ -794,6 +1506,18 @@ mod tests {
             ChangeType::Nest { .. } => test_update_path_through_nest(),
             ChangeType::Destructure { .. } => test_update_path_through_destructure(),
             ChangeType::DeleteRange { .. } => test_update_path_through_delete_range(),
+            ChangeType::StackFilter { .. } => test_update_path_through_stack_filter(),
+            ChangeType::ReplaceTopFilter { .. } => test_update_path_through_replace_top_filter(),
+            ChangeType::InsertData { .. } => test_update_path_through_insert_data(),
+            ChangeType::RemoveInsertedData { .. } => test_update_path_through_remove_inserted_data(),
+            ChangeType::DeleteData { .. } => test_update_path_through_delete_data(),
+            ChangeType::RestoreDeletedData { .. } => test_update_path_through_delete_data(),
+            ChangeType::AddFilter { .. } => test_update_path_through_filter_changes(),
+            ChangeType::RemoveFilter { .. } => test_update_path_through_filter_changes(),
+            ChangeType::ReplaceFilter { .. } => test_update_path_through_filter_changes(),
+            ChangeType::ReorderFilter { .. } => test_update_path_through_filter_changes(),
+            ChangeType::ReplaceDatapath { .. } => test_update_path_through_filter_changes(),
+            ChangeType::RewriteDatapath { .. } => test_update_path_through_filter_changes(),
             /* Make tests for your new ChangeType! */
         }
     }
@@ -1165,6 +1889,506 @@ mod tests {
         assert!(sync::Arc::ptr_eq(&orig_child1.children[3].node, &new_child1.children[1].node));
     }
 
+    fn overwrite_bytes(filter: &datapath::Filter) -> (u64, &[u8]) {
+        match filter {
+            datapath::Filter::Overwrite(of) => (of.offset, &of.bytes[..]),
+            other => panic!("expected overwrite filter, got {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn test_structural_change_stack_filter() {
+        let doc = create_test_document_1();
+
+        /* first filter just gets pushed */
+        let mut new_doc = doc.clone();
+        Change {
+            ty: ChangeType::StackFilter { filter: datapath::OverwriteFilter { offset: 0x10, bytes: vec![0x12] }.to_filter() },
+            generation: doc.generation(),
+        }.apply(&mut new_doc).unwrap();
+
+        assert!(sync::Arc::ptr_eq(&doc.root, &new_doc.root));
+        assert_eq!(new_doc.datapath.len(), 1);
+        assert_eq!(overwrite_bytes(&new_doc.datapath[0]), (0x10, &[0x12][..]));
+
+        /* adjacent filter gets stacked */
+        let doc = new_doc;
+        let mut new_doc = doc.clone();
+        Change {
+            ty: ChangeType::StackFilter { filter: datapath::OverwriteFilter { offset: 0x11, bytes: vec![0x34] }.to_filter() },
+            generation: doc.generation(),
+        }.apply(&mut new_doc).unwrap();
+
+        assert_eq!(new_doc.datapath.len(), 1);
+        assert_eq!(overwrite_bytes(&new_doc.datapath[0]), (0x10, &[0x12, 0x34][..]));
+
+        /* non-adjacent filter gets pushed */
+        let doc = new_doc;
+        let mut new_doc = doc.clone();
+        Change {
+            ty: ChangeType::StackFilter { filter: datapath::OverwriteFilter { offset: 0x20, bytes: vec![0x56] }.to_filter() },
+            generation: doc.generation(),
+        }.apply(&mut new_doc).unwrap();
+
+        assert_eq!(new_doc.datapath.len(), 2);
+        assert_eq!(overwrite_bytes(&new_doc.datapath[0]), (0x10, &[0x12, 0x34][..]));
+        assert_eq!(overwrite_bytes(&new_doc.datapath[1]), (0x20, &[0x56][..]));
+
+        /* insertions and deletions have to resize the structure along with the datapath */
+        assert_matches!(Change {
+            ty: ChangeType::StackFilter { filter: datapath::DeleteFilter { offset: 0x20, size: 0x4 }.to_filter() },
+            generation: new_doc.generation(),
+        }.apply(&mut new_doc.clone()), Err(ApplyError { ty: ApplyErrorType::InvalidParameters("attempted to stack filter that inserts or deletes bytes"), .. }));
+    }
+
+    #[test]
+    fn test_structural_change_replace_top_filter() {
+        let doc = create_test_document_1();
+
+        assert_matches!(Change {
+            ty: ChangeType::ReplaceTopFilter { remove: true, filter: None },
+            generation: doc.generation(),
+        }.apply(&mut doc.clone()), Err(ApplyError { ty: ApplyErrorType::InvalidParameters("attempted to remove filter from empty datapath"), .. }));
+
+        let mut new_doc = doc.clone();
+        Change {
+            ty: ChangeType::ReplaceTopFilter { remove: false, filter: Some(datapath::OverwriteFilter { offset: 0x10, bytes: vec![0x12] }.to_filter()) },
+            generation: doc.generation(),
+        }.apply(&mut new_doc).unwrap();
+
+        assert_eq!(new_doc.datapath.len(), 1);
+        assert_eq!(overwrite_bytes(&new_doc.datapath[0]), (0x10, &[0x12][..]));
+
+        /* the replacement filter should not be stacked, even though it could be */
+        let doc = new_doc;
+        let mut new_doc = doc.clone();
+        Change {
+            ty: ChangeType::ReplaceTopFilter { remove: false, filter: Some(datapath::OverwriteFilter { offset: 0x11, bytes: vec![0x34] }.to_filter()) },
+            generation: doc.generation(),
+        }.apply(&mut new_doc).unwrap();
+
+        assert_eq!(new_doc.datapath.len(), 2);
+
+        let doc = new_doc;
+        let mut new_doc = doc.clone();
+        Change {
+            ty: ChangeType::ReplaceTopFilter { remove: true, filter: Some(datapath::OverwriteFilter { offset: 0x20, bytes: vec![0x56] }.to_filter()) },
+            generation: doc.generation(),
+        }.apply(&mut new_doc).unwrap();
+
+        assert_eq!(new_doc.datapath.len(), 2);
+        assert_eq!(overwrite_bytes(&new_doc.datapath[0]), (0x10, &[0x12][..]));
+        assert_eq!(overwrite_bytes(&new_doc.datapath[1]), (0x20, &[0x56][..]));
+    }
+    
+    #[test]
+    fn test_structural_change_insert_data() {
+        let doc = create_test_document_1();
+
+        let change = Change {
+            ty: ChangeType::InsertData { path: vec![1, 1], offset: addr::Address::from(0x8), bytes: vec![0x12, 0x34] },
+            generation: doc.generation(),
+        };
+
+        let mut new_doc = doc.clone();
+        let change = change.apply(&mut new_doc).unwrap().0;
+
+        /* the node and its ancestors should grow */
+        assert_eq!(new_doc.root.size, addr::Size::from(0x42));
+        assert_eq!(new_doc.root.children[1].node.size, addr::Size::from(0x1e));
+        assert_eq!(new_doc.root.children[1].node.children[1].node.size, addr::Size::from(0x12));
+
+        /* nodes before the insertion point should stay put, and nodes after it should get shifted */
+        assert_eq!(new_doc.root.children[0].offset, addr::Address::from(0x10));
+        assert_eq!(new_doc.root.children[0].node.size, addr::Size::from(0x20));
+        assert_eq!(new_doc.root.children[1].node.children[0].offset, addr::Address::from(0x0));
+        assert_eq!(new_doc.root.children[2].offset, addr::Address::from(0x22));
+
+        /* the bytes should show up in the datapath at the right place */
+        assert_eq!(new_doc.datapath.len(), 1);
+        match &new_doc.datapath[0] {
+            datapath::Filter::Insert(f) => {
+                assert_eq!(f.offset, 0x20);
+                assert_eq!(f.bytes, vec![0x12, 0x34]);
+            },
+            f => panic!("expected insert filter, got {:?}", f),
+        };
+
+        /* offsets inside the affected nodes should get shifted if they were after the insertion point */
+        let mut offset = addr::Address::from(0x21);
+        change.update_offset(&new_doc.root, &[], &mut offset);
+        assert_eq!(offset, addr::Address::from(0x23));
+
+        let mut offset = addr::Address::from(0x20);
+        change.update_offset(&new_doc.root, &[], &mut offset);
+        assert_eq!(offset, addr::Address::from(0x20));
+
+        let mut offset = addr::Address::from(0xd);
+        change.update_offset(&new_doc.root, &[1], &mut offset);
+        assert_eq!(offset, addr::Address::from(0xf));
+
+        let mut offset = addr::Address::from(0x2);
+        change.update_offset(&new_doc.root, &[2], &mut offset);
+        assert_eq!(offset, addr::Address::from(0x2));
+
+        /* can't insert in the middle of a child */
+        assert_matches!(Change {
+            ty: ChangeType::InsertData { path: vec![], offset: addr::Address::from(0x12), bytes: vec![0x12] },
+            generation: doc.generation(),
+        }.apply(&mut doc.clone()), Err(ApplyError { ty: ApplyErrorType::InvalidParameters("attempted to insert data in the middle of a child"), .. }));
+
+        /* can insert right before a child though */
+        let mut new_doc = doc.clone();
+        Change {
+            ty: ChangeType::InsertData { path: vec![], offset: addr::Address::from(0x10), bytes: vec![0x12] },
+            generation: doc.generation(),
+        }.apply(&mut new_doc).unwrap();
+        assert_eq!(new_doc.root.children[0].offset, addr::Address::from(0x11));
+        assert_eq!(new_doc.root.children[0].node.size, addr::Size::from(0x20));
+    }
+
+    #[test]
+    fn test_structural_change_remove_inserted_data() {
+        let doc = create_test_document_1();
+
+        let mut inserted_doc = doc.clone();
+        Change {
+            ty: ChangeType::InsertData { path: vec![1, 1], offset: addr::Address::from(0x8), bytes: vec![0x12, 0x34] },
+            generation: doc.generation(),
+        }.apply(&mut inserted_doc).unwrap();
+
+        let mut new_doc = inserted_doc.clone();
+        Change {
+            ty: ChangeType::RemoveInsertedData { path: vec![1, 1], offset: addr::Address::from(0x8), length: 2, filter: None },
+            generation: inserted_doc.generation(),
+        }.apply(&mut new_doc).unwrap();
+
+        assert_eq!(new_doc.root.size, doc.root.size);
+        assert_eq!(new_doc.root.children[1].node.size, doc.root.children[1].node.size);
+        assert_eq!(new_doc.root.children[1].node.children[1].node.size, doc.root.children[1].node.children[1].node.size);
+        assert_eq!(new_doc.root.children[2].offset, doc.root.children[2].offset);
+        assert_eq!(new_doc.datapath.len(), 0);
+
+        /* can't remove data that a node lives in */
+        assert_matches!(Change {
+            ty: ChangeType::RemoveInsertedData { path: vec![], offset: addr::Address::from(0x1e), length: 4, filter: None },
+            generation: inserted_doc.generation(),
+        }.apply(&mut inserted_doc.clone()), Err(ApplyError { ty: ApplyErrorType::InvalidParameters("attempted to remove data that children still occupy"), .. }));
+    }
+
+    #[test]
+    fn test_structural_change_delete_data() {
+        let doc = create_test_document_1();
+
+        let change = Change {
+            ty: ChangeType::DeleteData { path: vec![1, 1], offset: addr::Address::from(0x4), length: 4 },
+            generation: doc.generation(),
+        };
+
+        let mut new_doc = doc.clone();
+        let change = change.apply(&mut new_doc).unwrap().0;
+
+        /* the node and its ancestors should shrink */
+        assert_eq!(new_doc.root.size, addr::Size::from(0x3c));
+        assert_eq!(new_doc.root.children[1].node.size, addr::Size::from(0x18));
+        assert_eq!(new_doc.root.children[1].node.children[1].node.size, addr::Size::from(0xc));
+
+        /* nodes after the deleted range should get shifted back */
+        assert_eq!(new_doc.root.children[0].offset, addr::Address::from(0x10));
+        assert_eq!(new_doc.root.children[2].offset, addr::Address::from(0x1c));
+
+        assert_eq!(new_doc.datapath.len(), 1);
+        assert_matches!(&new_doc.datapath[0], datapath::Filter::Delete(f) if f.offset == 0x1c && f.size == 4);
+
+        /* offsets inside the deleted range collapse onto its start */
+        let mut offset = addr::Address::from(0x1e);
+        change.update_offset(&new_doc.root, &[], &mut offset);
+        assert_eq!(offset, addr::Address::from(0x1c));
+
+        let mut offset = addr::Address::from(0x24);
+        change.update_offset(&new_doc.root, &[], &mut offset);
+        assert_eq!(offset, addr::Address::from(0x20));
+
+        /* deleting right before the first deletion stacks onto it */
+        let mut stacked_doc = new_doc.clone();
+        Change {
+            ty: ChangeType::DeleteData { path: vec![1, 1], offset: addr::Address::from(0x2), length: 2 },
+            generation: new_doc.generation(),
+        }.apply(&mut stacked_doc).unwrap();
+        assert_eq!(stacked_doc.datapath.len(), 1);
+        assert_matches!(&stacked_doc.datapath[0], datapath::Filter::Delete(f) if f.offset == 0x1a && f.size == 6);
+
+        /* can't delete data that a node lives in */
+        assert_matches!(Change {
+            ty: ChangeType::DeleteData { path: vec![1], offset: addr::Address::from(0x2), length: 4 },
+            generation: doc.generation(),
+        }.apply(&mut doc.clone()), Err(ApplyError { ty: ApplyErrorType::InvalidParameters("attempted to remove data that children still occupy"), .. }));
+
+        /* or past the end of the node */
+        assert_matches!(Change {
+            ty: ChangeType::DeleteData { path: vec![2], offset: addr::Address::from(0x2), length: 4 },
+            generation: doc.generation(),
+        }.apply(&mut doc.clone()), Err(ApplyError { ty: ApplyErrorType::InvalidParameters("attempted to remove data beyond node's size"), .. }));
+    }
+
+    #[test]
+    fn test_structural_change_restore_deleted_data() {
+        let doc = create_test_document_1();
+
+        let mut deleted_doc = doc.clone();
+        Change {
+            ty: ChangeType::DeleteData { path: vec![1, 1], offset: addr::Address::from(0x4), length: 4 },
+            generation: doc.generation(),
+        }.apply(&mut deleted_doc).unwrap();
+
+        let mut stacked_doc = deleted_doc.clone();
+        let stacking = Change {
+            ty: ChangeType::DeleteData { path: vec![1, 1], offset: addr::Address::from(0x2), length: 2 },
+            generation: deleted_doc.generation(),
+        }.apply(&mut stacked_doc).unwrap().0;
+
+        /* undoing the stacked deletion should put the first one back the way it was */
+        let mut restored_doc = stacked_doc.clone();
+        stacking.invert(&deleted_doc, &stacked_doc).unwrap().apply(&mut restored_doc).unwrap();
+        assert_eq!(restored_doc.root.children[1].node.children[1].node.size, addr::Size::from(0xc));
+        assert_eq!(restored_doc.datapath.len(), 1);
+        assert_matches!(&restored_doc.datapath[0], datapath::Filter::Delete(f) if f.offset == 0x1c && f.size == 4);
+
+        let mut new_doc = restored_doc.clone();
+        Change {
+            ty: ChangeType::RestoreDeletedData { path: vec![1, 1], offset: addr::Address::from(0x4), length: 4, filter: None },
+            generation: restored_doc.generation(),
+        }.apply(&mut new_doc).unwrap();
+
+        assert_eq!(new_doc.root.size, doc.root.size);
+        assert_eq!(new_doc.root.children[1].node.size, doc.root.children[1].node.size);
+        assert_eq!(new_doc.root.children[1].node.children[1].node.size, doc.root.children[1].node.children[1].node.size);
+        assert_eq!(new_doc.root.children[2].offset, doc.root.children[2].offset);
+        assert_eq!(new_doc.datapath.len(), 0);
+    }
+
+    fn create_test_document_with_filters() -> document::Document {
+        let mut doc = create_test_document_1();
+        doc.datapath = imbl::vector![
+            datapath::OverwriteFilter { offset: 0x10, bytes: vec![0x10] }.to_filter(),
+            datapath::OverwriteFilter { offset: 0x20, bytes: vec![0x20] }.to_filter(),
+            datapath::OverwriteFilter { offset: 0x30, bytes: vec![0x30] }.to_filter(),
+        ].into();
+        doc
+    }
+    
+    #[test]
+    fn test_structural_change_add_filter() {
+        let doc = create_test_document_with_filters();
+
+        let mut new_doc = doc.clone();
+        Change {
+            ty: ChangeType::AddFilter { index: 1, filter: datapath::OverwriteFilter { offset: 0x11, bytes: vec![0x11] }.to_filter() },
+            generation: doc.generation(),
+        }.apply(&mut new_doc).unwrap();
+
+        /* shouldn't get stacked, even though it could be */
+        assert!(sync::Arc::ptr_eq(&doc.root, &new_doc.root));
+        assert_eq!(new_doc.datapath.iter().map(overwrite_bytes).collect::<Vec<_>>(), vec![(0x10, &[0x10][..]), (0x11, &[0x11][..]), (0x20, &[0x20][..]), (0x30, &[0x30][..])]);
+
+        let mut new_doc = doc.clone();
+        Change {
+            ty: ChangeType::AddFilter { index: 3, filter: datapath::OverwriteFilter { offset: 0x40, bytes: vec![0x40] }.to_filter() },
+            generation: doc.generation(),
+        }.apply(&mut new_doc).unwrap();
+        assert_eq!(overwrite_bytes(&new_doc.datapath[3]), (0x40, &[0x40][..]));
+
+        assert_matches!(Change {
+            ty: ChangeType::AddFilter { index: 4, filter: datapath::OverwriteFilter { offset: 0x40, bytes: vec![0x40] }.to_filter() },
+            generation: doc.generation(),
+        }.apply(&mut doc.clone()), Err(ApplyError { ty: ApplyErrorType::InvalidParameters("attempted to add filter at out-of-bounds index"), .. }));
+
+        assert_matches!(Change {
+            ty: ChangeType::AddFilter { index: 1, filter: datapath::InsertFilter { offset: 0x18, bytes: vec![0x18] }.to_filter() },
+            generation: doc.generation(),
+        }.apply(&mut doc.clone()), Err(ApplyError { ty: ApplyErrorType::InvalidParameters("attempted to add filter that inserts or deletes bytes"), .. }));
+    }
+
+    #[test]
+    fn test_structural_change_remove_filter() {
+        let doc = create_test_document_with_filters();
+
+        let mut new_doc = doc.clone();
+        Change {
+            ty: ChangeType::RemoveFilter { index: 1 },
+            generation: doc.generation(),
+        }.apply(&mut new_doc).unwrap();
+
+        assert!(sync::Arc::ptr_eq(&doc.root, &new_doc.root));
+        assert_eq!(new_doc.datapath.iter().map(overwrite_bytes).collect::<Vec<_>>(), vec![(0x10, &[0x10][..]), (0x30, &[0x30][..])]);
+
+        assert_matches!(Change {
+            ty: ChangeType::RemoveFilter { index: 3 },
+            generation: doc.generation(),
+        }.apply(&mut doc.clone()), Err(ApplyError { ty: ApplyErrorType::InvalidParameters("attempted to remove filter at out-of-bounds index"), .. }));
+
+        /* the structure was resized around the insertion, so it can't be taken out on its own */
+        let mut doc = doc;
+        doc.datapath.insert(1, datapath::InsertFilter { offset: 0x18, bytes: vec![0x18] }.to_filter());
+        assert_matches!(Change {
+            ty: ChangeType::RemoveFilter { index: 1 },
+            generation: doc.generation(),
+        }.apply(&mut doc.clone()), Err(ApplyError { ty: ApplyErrorType::InvalidParameters("attempted to remove filter that inserts or deletes bytes"), .. }));
+    }
+
+    #[test]
+    fn test_structural_change_replace_filter() {
+        let doc = create_test_document_with_filters();
+
+        let mut new_doc = doc.clone();
+        Change {
+            ty: ChangeType::ReplaceFilter { index: 1, filter: datapath::OverwriteFilter { offset: 0x21, bytes: vec![0x21] }.to_filter() },
+            generation: doc.generation(),
+        }.apply(&mut new_doc).unwrap();
+
+        assert!(sync::Arc::ptr_eq(&doc.root, &new_doc.root));
+        assert_eq!(new_doc.datapath.iter().map(overwrite_bytes).collect::<Vec<_>>(), vec![(0x10, &[0x10][..]), (0x21, &[0x21][..]), (0x30, &[0x30][..])]);
+
+        assert_matches!(Change {
+            ty: ChangeType::ReplaceFilter { index: 3, filter: datapath::OverwriteFilter { offset: 0x21, bytes: vec![0x21] }.to_filter() },
+            generation: doc.generation(),
+        }.apply(&mut doc.clone()), Err(ApplyError { ty: ApplyErrorType::InvalidParameters("attempted to replace filter at out-of-bounds index"), .. }));
+
+        /* insertions can only be replaced by ones that fit the structure the same way */
+        let mut doc = doc;
+        doc.datapath.insert(1, datapath::InsertFilter { offset: 0x18, bytes: vec![0x18] }.to_filter());
+        assert_matches!(Change {
+            ty: ChangeType::ReplaceFilter { index: 1, filter: datapath::InsertFilter { offset: 0x18, bytes: vec![0x81] }.to_filter() },
+            generation: doc.generation(),
+        }.apply(&mut doc.clone()), Ok(_));
+        assert_matches!(Change {
+            ty: ChangeType::ReplaceFilter { index: 1, filter: doc.datapath[1].toggled() },
+            generation: doc.generation(),
+        }.apply(&mut doc.clone()), Err(ApplyError { ty: ApplyErrorType::InvalidParameters("attempted to replace filter with one that inserts or deletes different bytes"), .. }));
+        assert_matches!(Change {
+            ty: ChangeType::ReplaceFilter { index: 1, filter: datapath::InsertFilter { offset: 0x18, bytes: vec![0x18; 2] }.to_filter() },
+            generation: doc.generation(),
+        }.apply(&mut doc.clone()), Err(ApplyError { ty: ApplyErrorType::InvalidParameters("attempted to replace filter with one that inserts or deletes different bytes"), .. }));
+        assert_matches!(Change {
+            ty: ChangeType::ReplaceFilter { index: 0, filter: datapath::DeleteFilter { offset: 0x10, size: 0x1 }.to_filter() },
+            generation: doc.generation(),
+        }.apply(&mut doc.clone()), Err(ApplyError { ty: ApplyErrorType::InvalidParameters("attempted to replace filter with one that inserts or deletes different bytes"), .. }));
+    }
+
+    #[test]
+    fn test_structural_change_reorder_filter() {
+        let doc = create_test_document_with_filters();
+
+        let mut new_doc = doc.clone();
+        let change = Change {
+            ty: ChangeType::ReorderFilter { from: 0, to: 2 },
+            generation: doc.generation(),
+        };
+        change.clone().apply(&mut new_doc).unwrap();
+
+        assert!(sync::Arc::ptr_eq(&doc.root, &new_doc.root));
+        assert_eq!(new_doc.datapath.iter().map(overwrite_bytes).collect::<Vec<_>>(), vec![(0x20, &[0x20][..]), (0x30, &[0x30][..]), (0x10, &[0x10][..])]);
+
+        let mut restored_doc = new_doc.clone();
+        change.invert(&doc, &new_doc).unwrap().apply(&mut restored_doc).unwrap();
+        assert_eq!(restored_doc.datapath.iter().map(overwrite_bytes).collect::<Vec<_>>(), vec![(0x10, &[0x10][..]), (0x20, &[0x20][..]), (0x30, &[0x30][..])]);
+
+        assert_matches!(Change {
+            ty: ChangeType::ReorderFilter { from: 0, to: 3 },
+            generation: doc.generation(),
+        }.apply(&mut doc.clone()), Err(ApplyError { ty: ApplyErrorType::InvalidParameters("attempted to move filter to or from out-of-bounds index"), .. }));
+
+        /* can't move edits past an insertion */
+        let mut doc = doc;
+        doc.datapath.insert(1, datapath::InsertFilter { offset: 0x18, bytes: vec![0x18] }.to_filter());
+        assert_matches!(Change {
+            ty: ChangeType::ReorderFilter { from: 0, to: 2 },
+            generation: doc.generation(),
+        }.apply(&mut doc.clone()), Err(ApplyError { ty: ApplyErrorType::InvalidParameters("attempted to move filter past one that shifts addresses"), .. }));
+        assert_matches!(Change {
+            ty: ChangeType::ReorderFilter { from: 2, to: 3 },
+            generation: doc.generation(),
+        }.apply(&mut doc.clone()), Ok(_));
+
+        /* or underneath the address space */
+        let mut doc = create_test_document_with_filters();
+        let space = sync::Arc::new(space::AddressSpace::from(space::buffer::BufferAddressSpace::new(vec![0; 0x40], "test")));
+        doc.datapath.push_front(datapath::LoadSpaceFilter::new_defaults(space, 0, 0).to_filter());
+        assert_matches!(Change {
+            ty: ChangeType::ReorderFilter { from: 1, to: 0 },
+            generation: doc.generation(),
+        }.apply(&mut doc.clone()), Err(ApplyError { ty: ApplyErrorType::InvalidParameters("attempted to move filter underneath the address space it's loaded from"), .. }));
+        assert_matches!(Change {
+            ty: ChangeType::ReorderFilter { from: 0, to: 1 },
+            generation: doc.generation(),
+        }.apply(&mut doc.clone()), Err(ApplyError { ty: ApplyErrorType::InvalidParameters("attempted to move filter underneath the address space it's loaded from"), .. }));
+    }
+
+    #[test]
+    fn test_structural_change_replace_datapath() {
+        let doc = create_test_document_with_filters();
+
+        let mut new_doc = doc.clone();
+        let change = Change {
+            ty: ChangeType::ReplaceDatapath { datapath: imbl::vector![datapath::OverwriteFilter { offset: 0x40, bytes: vec![0x40] }.to_filter()].into() },
+            generation: doc.generation(),
+        };
+        change.clone().apply(&mut new_doc).unwrap();
+
+        assert!(sync::Arc::ptr_eq(&doc.root, &new_doc.root));
+        assert_eq!(new_doc.datapath.iter().map(overwrite_bytes).collect::<Vec<_>>(), vec![(0x40, &[0x40][..])]);
+        assert!(change.invert(&doc, &new_doc).is_none());
+    }
+
+    #[test]
+    fn test_structural_change_rewrite_datapath() {
+        let mut doc = create_test_document_with_filters();
+        doc.datapath.push_back(datapath::OverwriteFilter { offset: 0x11, bytes: vec![0x11] }.to_filter());
+
+        let mut new_doc = doc.clone();
+        let change = doc.compact_datapath();
+        change.clone().apply(&mut new_doc).unwrap();
+
+        assert!(sync::Arc::ptr_eq(&doc.root, &new_doc.root));
+        assert_eq!(new_doc.datapath.iter().map(overwrite_bytes).collect::<Vec<_>>(), vec![(0x10, &[0x10, 0x11][..]), (0x20, &[0x20][..]), (0x30, &[0x30][..])]);
+
+        let mut restored_doc = new_doc.clone();
+        change.invert(&doc, &new_doc).unwrap().apply(&mut restored_doc).unwrap();
+        assert_eq!(restored_doc.datapath.len(), 4);
+    }
+
+    #[test]
+    fn test_rebase_replace_datapath() {
+        let host = document::DocumentHost::new(create_test_document_with_filters());
+        let original = host.get();
+
+        /* someone else edits the datapath first */
+        host.change(original.remove_filter(0)).unwrap();
+
+        assert_matches!(host.change(original.replace_datapath(datapath::DataPath::new())), Err((ApplyError { ty: ApplyErrorType::UpdateFailed { error: UpdateError::NotUpdatable, .. }, .. }, _)));
+    }
+
+    #[test]
+    fn test_rebase_filter_changes() {
+        let host = document::DocumentHost::new(create_test_document_with_filters());
+        let original = host.get();
+
+        /* someone else removes the first filter and adds one on top */
+        host.change(original.remove_filter(0)).unwrap();
+        host.change(host.get().add_filter(2, datapath::OverwriteFilter { offset: 0x40, bytes: vec![0x40] }.to_filter())).unwrap();
+
+        /* changes made against the original document should get their indices fixed up */
+        let doc = host.change(original.replace_filter(2, datapath::OverwriteFilter { offset: 0x31, bytes: vec![0x31] }.to_filter())).unwrap();
+        assert_eq!(doc.datapath.iter().map(overwrite_bytes).collect::<Vec<_>>(), vec![(0x20, &[0x20][..]), (0x31, &[0x31][..]), (0x40, &[0x40][..])]);
+
+        let doc = host.change(original.add_filter(1, datapath::OverwriteFilter { offset: 0x11, bytes: vec![0x11] }.to_filter())).unwrap();
+        assert_eq!(doc.datapath.iter().map(overwrite_bytes).collect::<Vec<_>>(), vec![(0x11, &[0x11][..]), (0x20, &[0x20][..]), (0x31, &[0x31][..]), (0x40, &[0x40][..])]);
+
+        /* but they can't refer to filters that were removed or modified in the meantime */
+        assert_matches!(host.change(original.remove_filter(0)), Err((ApplyError { ty: ApplyErrorType::UpdateFailed { error: UpdateError::FilterRemoved, .. }, .. }, _)));
+        assert_matches!(host.change(original.remove_filter(2)), Err((ApplyError { ty: ApplyErrorType::UpdateFailed { error: UpdateError::FilterReplaced, .. }, .. }, _)));
+    }
+
     /* This exists to produce errors if another ChangeType gets added without corresponding tests. */
     fn structural_change_exhaustiveness(ty: ChangeType) {
         match ty {
@@ -1174,6 +2398,18 @@ mod tests {
             ChangeType::Nest { .. } => test_structural_change_nest(),
             ChangeType::Destructure { .. } => test_structural_change_destructure(),
             ChangeType::DeleteRange { .. } => test_structural_change_delete_range(),
+            ChangeType::StackFilter { .. } => test_structural_change_stack_filter(),
+            ChangeType::ReplaceTopFilter { .. } => test_structural_change_replace_top_filter(),
+            ChangeType::InsertData { .. } => test_structural_change_insert_data(),
+            ChangeType::RemoveInsertedData { .. } => test_structural_change_remove_inserted_data(),
+            ChangeType::DeleteData { .. } => test_structural_change_delete_data(),
+            ChangeType::RestoreDeletedData { .. } => test_structural_change_restore_deleted_data(),
+            ChangeType::AddFilter { .. } => test_structural_change_add_filter(),
+            ChangeType::RemoveFilter { .. } => test_structural_change_remove_filter(),
+            ChangeType::ReplaceFilter { .. } => test_structural_change_replace_filter(),
+            ChangeType::ReorderFilter { .. } => test_structural_change_reorder_filter(),
+            ChangeType::ReplaceDatapath { .. } => test_structural_change_replace_datapath(),
+            ChangeType::RewriteDatapath { .. } => test_structural_change_rewrite_datapath(),
             /* Make tests for your new ChangeType! */
         }
     }
@@ -0,0 +1,304 @@
+use std::sync;
+use std::vec;
+
+use crate::model::addr;
+use crate::model::document;
+use crate::model::document::change;
+use crate::model::versioned::Versioned;
+
+/// Tracks which changes to a document can be undone or redone. The history catches up with the document lazily (see
+/// [History::sync]), so changes don't need to be registered with it as they are made.
+///
+/// Undo and redo changes are stamped with the generation of the document they're applied to instead of being rebased
+/// from an older version. This is fine because any change that was made after an entry was pushed onto the undo stack
+/// has to have been undone already before that entry comes back to the top.
+#[derive(Debug, Clone)]
+pub struct History {
+    undo: vec::Vec<change::ChangeType>,
+    redo: vec::Vec<change::ChangeType>,
+
+    /// The most recent version of the document that we've accounted for.
+    document: sync::Arc<document::Document>,
+
+    /// Generation of the document produced by the change that created the top entry of the undo stack. Used to decide
+    /// whether a change should be merged into that entry instead of getting its own.
+    top_generation: Option<u64>,
+}
+
+impl History {
+    pub fn new(document: sync::Arc<document::Document>) -> History {
+        History {
+            undo: vec::Vec::new(),
+            redo: vec::Vec::new(),
+            document,
+            top_generation: None,
+        }
+    }
+
+    /// Accounts for any changes that have been made to the document since we last looked at it.
+    pub fn sync(&mut self, document: &sync::Arc<document::Document>) {
+        if !self.document.is_outdated(document) {
+            return;
+        }
+
+        document.changes_since(&self.document.clone(), &mut |after, change| {
+            let (before, _) = after.previous().expect("changes_since should only give us documents with previous versions");
+            self.record(before, after, change);
+        });
+
+        self.document = document.clone();
+    }
+
+    fn record(&mut self, before: &sync::Arc<document::Document>, after: &sync::Arc<document::Document>, change: &change::Change) {
+        /* Any new change invalidates everything we could have redone. */
+        self.redo.clear();
+
+        /* Consecutive stacked filters are undone as a group. The entry we pushed for the first one in the group already
+         * restores the top of the datapath to how it was before the group started. */
+        let was_stacked = after.datapath.len() == before.datapath.len();
+        let continues_group = was_stacked && self.top_generation == Some(before.generation());
+
+        match (&change.ty, self.undo.last_mut()) {
+            (change::ChangeType::StackFilter { .. }, Some(change::ChangeType::ReplaceTopFilter { remove: true, .. } | change::ChangeType::RemoveInsertedData { .. })) if continues_group => {
+                self.top_generation = Some(after.generation());
+                return;
+            },
+
+            /* Inserted data also needs to be removed from the structure, so grow the entry to cover it. */
+            (change::ChangeType::InsertData { path, offset, bytes }, Some(change::ChangeType::RemoveInsertedData { path: top_path, offset: top_offset, length, .. }))
+                if continues_group && path == top_path && *offset == *top_offset + addr::Size::from(*length) => {
+                    *length+= bytes.len() as u64;
+                    self.top_generation = Some(after.generation());
+                    return;
+                },
+
+            _ => {},
+        }
+
+        match change.invert(before, after) {
+            Some(inverse) => {
+                self.undo.push(inverse.ty);
+                self.top_generation = Some(after.generation());
+            },
+            None => {
+                /* We can't undo past this change. */
+                self.undo.clear();
+                self.top_generation = None;
+            },
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Undoes the most recent undoable change. Returns the new document, or None if there was nothing to undo.
+    pub fn undo(&mut self, host: &document::DocumentHost) -> Result<Option<sync::Arc<document::Document>>, (change::ApplyError, sync::Arc<document::Document>)> {
+        self.sync(&host.get());
+
+        let Some(ty) = self.undo.pop() else { return Ok(None) };
+
+        match self.apply(host, ty) {
+            Ok((new_document, inverse)) => {
+                self.redo.push(inverse);
+                self.top_generation = None;
+                Ok(Some(new_document))
+            },
+            Err((error, attempted_version)) => {
+                self.undo.push(error.change.ty.clone());
+                Err((error, attempted_version))
+            },
+        }
+    }
+
+    /// Redoes the most recently undone change. Returns the new document, or None if there was nothing to redo.
+    pub fn redo(&mut self, host: &document::DocumentHost) -> Result<Option<sync::Arc<document::Document>>, (change::ApplyError, sync::Arc<document::Document>)> {
+        self.sync(&host.get());
+
+        let Some(ty) = self.redo.pop() else { return Ok(None) };
+
+        match self.apply(host, ty) {
+            Ok((new_document, inverse)) => {
+                self.undo.push(inverse);
+                self.top_generation = Some(new_document.generation());
+                Ok(Some(new_document))
+            },
+            Err((error, attempted_version)) => {
+                self.redo.push(error.change.ty.clone());
+                Err((error, attempted_version))
+            },
+        }
+    }
+
+    /// Applies a change from one of our stacks and returns the new document along with the change that would reverse it.
+    fn apply(&mut self, host: &document::DocumentHost, ty: change::ChangeType) -> Result<(sync::Arc<document::Document>, change::ChangeType), (change::ApplyError, sync::Arc<document::Document>)> {
+        let new_document = host.change(change::Change {
+            ty,
+            generation: self.document.generation(),
+        })?;
+
+        let (before, applied) = new_document.previous().expect("document we just changed should have a previous version");
+
+        /* Everything we put on the undo and redo stacks should be invertible. */
+        let inverse = applied.invert(before, &new_document).expect("undo and redo changes should be invertible");
+
+        /* Skip over our own change so sync doesn't record it. */
+        self.document = new_document.clone();
+
+        Ok((new_document, inverse.ty))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::task;
+
+    use crate::model::addr;
+    use crate::model::datapath;
+    use crate::model::datapath::DataPathExt;
+    use crate::model::document::structure;
+
+    fn create_test_document() -> document::Document {
+        let root = structure::Node::builder()
+            .name("root")
+            .size(0x40)
+            .child(0x10, |b| b
+                   .name("child0")
+                   .size(0x20))
+            .child(0x30, |b| b
+                   .name("child1")
+                   .size(0x4))
+            .build();
+
+        document::Builder::new(root)
+            .datapath(imbl::vector![datapath::OverwriteFilter { offset: 0, bytes: vec![0; 0x40] }.to_filter()].into())
+            .build()
+    }
+
+    fn read_bytes(document: &document::Document, addr: u64, len: usize) -> vec::Vec<u8> {
+        let mut records = vec![datapath::ByteRecord::default(); len];
+        document.datapath.fetch(datapath::ByteRecordRange::new(addr, &mut records), &mut task::Context::from_waker(futures::task::noop_waker_ref()));
+        records.iter().map(|br| br.value).collect()
+    }
+
+    fn assert_same_structure(a: &structure::Node, b: &structure::Node) {
+        assert_eq!(a.props, b.props);
+        assert_eq!(a.size, b.size);
+        assert_eq!(a.children.len(), b.children.len());
+
+        for (ac, bc) in a.children.iter().zip(b.children.iter()) {
+            assert_eq!(ac.offset, bc.offset);
+            assert_same_structure(&ac.node, &bc.node);
+        }
+    }
+
+    fn overwrite(document: &document::Document, offset: u64, bytes: &[u8]) -> change::Change {
+        document.stack_filter(datapath::OverwriteFilter { offset, bytes: bytes.to_vec() }.to_filter())
+    }
+
+    #[test]
+    fn consecutive_filters_undo_together() {
+        let host = document::DocumentHost::new(create_test_document());
+        let mut history = History::new(host.get());
+
+        /* A structure change in between should split up the groups. */
+        host.change(overwrite(&host.get(), 0x100, &[0xaa])).unwrap();
+        host.change(host.get().alter_node(vec![0], structure::Properties { name: "renamed".to_string(), ..host.get().root.children[0].node.props.clone() })).unwrap();
+        host.change(overwrite(&host.get(), 0x10, &[0x12])).unwrap();
+        host.change(overwrite(&host.get(), 0x11, &[0x34])).unwrap();
+        host.change(overwrite(&host.get(), 0x12, &[0x56])).unwrap();
+
+        assert_eq!(read_bytes(&host.get(), 0x10, 4), vec![0x12, 0x34, 0x56, 0x00]);
+        assert_eq!(host.get().datapath.len(), 3);
+
+        /* all three bytes should get undone at once */
+        history.undo(&host).unwrap().unwrap();
+        assert_eq!(read_bytes(&host.get(), 0x10, 4), vec![0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(host.get().datapath.len(), 2);
+        assert_eq!(host.get().root.children[0].node.props.name, "renamed");
+
+        history.undo(&host).unwrap().unwrap();
+        assert_eq!(host.get().root.children[0].node.props.name, "child0");
+        assert_eq!(read_bytes(&host.get(), 0x100, 1), vec![0xaa]);
+
+        history.undo(&host).unwrap().unwrap();
+        assert_eq!(read_bytes(&host.get(), 0x100, 1), vec![0x00]);
+        assert_eq!(host.get().datapath.len(), 1);
+
+        assert!(!history.can_undo());
+        assert_matches::assert_matches!(history.undo(&host), Ok(None));
+    }
+
+    #[test]
+    fn inserted_bytes_undo_together() {
+        let host = document::DocumentHost::new(create_test_document());
+        let mut history = History::new(host.get());
+        let original = host.get();
+
+        /* this is what typing "1234" in insert mode looks like */
+        host.change(host.get().insert_data(vec![0], addr::Address::from(0x4), vec![0x10])).unwrap();
+        host.change(overwrite(&host.get(), 0x14, &[0x12])).unwrap();
+        host.change(host.get().insert_data(vec![0], addr::Address::from(0x5), vec![0x30])).unwrap();
+        host.change(overwrite(&host.get(), 0x15, &[0x34])).unwrap();
+        let modified = host.get();
+
+        assert_eq!(read_bytes(&modified, 0x13, 4), vec![0x00, 0x12, 0x34, 0x00]);
+        assert_eq!(modified.datapath.len(), 2);
+        assert_eq!(modified.root.children[0].node.size, addr::Size::from(0x22));
+        assert_eq!(modified.root.children[1].offset, addr::Address::from(0x32));
+
+        history.undo(&host).unwrap().unwrap();
+        assert!(!history.can_undo());
+        assert_same_structure(&host.get().root, &original.root);
+        assert_eq!(read_bytes(&host.get(), 0, 0x40), read_bytes(&original, 0, 0x40));
+        assert_eq!(host.get().datapath.len(), original.datapath.len());
+
+        history.redo(&host).unwrap().unwrap();
+        assert_same_structure(&host.get().root, &modified.root);
+        assert_eq!(read_bytes(&host.get(), 0, 0x42), read_bytes(&modified, 0, 0x42));
+        assert_eq!(host.get().datapath.len(), modified.datapath.len());
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let host = document::DocumentHost::new(create_test_document());
+        let mut history = History::new(host.get());
+        let original = host.get();
+
+        host.change(overwrite(&host.get(), 0x10, &[0x12])).unwrap();
+        host.change(overwrite(&host.get(), 0x10, &[0x34])).unwrap();
+        host.change(host.get().insert_node(vec![], 1, structure::Node::builder().name("new").size(0x4).build_child(0x2c.into()))).unwrap();
+        host.change(host.get().nest(structure::SiblingRange::new(vec![], 0, 1), addr::Extent::sized_u64(0x10, 0x24), structure::Properties::default())).unwrap();
+        let modified = host.get();
+
+        history.sync(&modified);
+        while history.can_undo() {
+            history.undo(&host).unwrap().unwrap();
+        }
+
+        assert_same_structure(&host.get().root, &original.root);
+        assert_eq!(read_bytes(&host.get(), 0, 0x40), read_bytes(&original, 0, 0x40));
+        assert_eq!(host.get().datapath.len(), original.datapath.len());
+
+        while history.can_redo() {
+            history.redo(&host).unwrap().unwrap();
+        }
+
+        assert_same_structure(&host.get().root, &modified.root);
+        assert_eq!(read_bytes(&host.get(), 0, 0x40), read_bytes(&modified, 0, 0x40));
+        assert_eq!(host.get().datapath.len(), modified.datapath.len());
+
+        /* making a new change should throw away the redo stack */
+        history.undo(&host).unwrap().unwrap();
+        assert!(history.can_redo());
+        host.change(overwrite(&host.get(), 0x20, &[0x56])).unwrap();
+        history.sync(&host.get());
+        assert!(!history.can_redo());
+    }
+}
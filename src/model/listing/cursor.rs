@@ -34,6 +34,7 @@ pub enum PlacementFailure {
     HitTopOfAddressSpace,
 }
 
+#[derive(Debug)]
 pub enum EntryError {
     DataNotLoaded,
     KeyNotRecognized,
@@ -41,6 +42,11 @@ pub enum EntryError {
     InvalidForPosition,
     /// key was recognized, but invalid for entry for this type of cursor
     InvalidForType,
+    /// the edit was rejected by the document
+    DocumentUpdateFailure {
+        error: document::change::ApplyError,
+        attempted_version: sync::Arc<document::Document>,
+    },
 }
 
 #[enum_dispatch]
@@ -60,7 +66,10 @@ pub trait CursorClassExt {
     fn move_left_large(&mut self) -> MovementResult;
     fn move_right_large(&mut self) -> MovementResult;
 
-    //fn enter_standard(&mut self, document_host: &document::DocumentHost, insert: bool, key: &key::Key) -> Result<MovementResult, EntryError>;
+    /// Produces the change that entering the key at the cursor's position should make to the document. The cursor is
+    /// advanced afterwards by [Cursor::enter_standard]. If `insert` is set, new data should be inserted instead of
+    /// overwriting what's already there.
+    fn enter_standard(&self, document: &document::Document, insert: bool, key: &key::Key) -> Result<document::change::Change, EntryError>;
     //fn enter_utf8    (&mut self, document_host: &document::DocumentHost, insert: bool, key: &key::Key) -> Result<MovementResult, EntryError>;
 }
 
@@ -429,11 +438,20 @@ impl Cursor {
         todo!();
     }
 
-    /*
     pub fn enter_standard(&mut self, document_host: &document::DocumentHost, insert: bool, key: &key::Key) -> Result<MovementResult, EntryError> {
-        self.class.enter_standard(document_host, insert, key).map(|mr| self.movement(|_| mr, TransitionHint::EntryStandard))
+        let change = self.class.enter_standard(&self.document, insert, key)?;
+        
+        let new_document = document_host.change(change).map_err(|(error, attempted_version)| EntryError::DocumentUpdateFailure {
+            error,
+            attempted_version
+        })?;
+
+        self.update_internal(&new_document, UpdateMode::Default);
+        
+        Ok(self.movement(|c| c.move_right(), TransitionHint::EntryStandard))
     }
 
+    /*
     pub fn enter_utf8(&mut self, document_host: &document::DocumentHost, insert: bool, key: &key::Key) -> Result<MovementResult, EntryError> {
         self.class.enter_utf8(document_host, insert, key).map(|mr| self.movement(|_| mr, TransitionHint::EntryUTF8))
     }
@@ -900,6 +918,61 @@ mod tests {
         assert_matches!(&cursor.class, CursorClass::Hexdump(hxc) if hxc.offset == 0x0.into() && hxc.low_nybble == false);
     }
 
+    #[test]
+    fn hex_entry() {
+        let document_host = sync::Arc::new(document::Builder::default()
+            .datapath(imbl::vector![crate::model::datapath::OverwriteFilter { offset: 0, bytes: vec![0xff; 0x20] }.to_filter()].into())
+            .host());
+        let mut cursor = Cursor::new(document_host.get());
+
+        let mut keys = [key::Key::_1, key::Key::a, key::Key::_3].into_iter();
+
+        /* high nybble should keep the low nybble from the original data */
+        assert_matches!(cursor.enter_standard(&document_host, false, &keys.next().unwrap()), Ok(MovementResult::Ok));
+        assert_matches!(&cursor.class, CursorClass::Hexdump(hxc) if hxc.offset == addr::unit::ZERO && hxc.low_nybble);
+
+        for key in keys {
+            assert_matches!(cursor.enter_standard(&document_host, false, &key), Ok(MovementResult::Ok));
+        }
+        assert_matches!(&cursor.class, CursorClass::Hexdump(hxc) if hxc.offset == addr::unit::BYTE && hxc.low_nybble);
+
+        /* unrecognized keys shouldn't do anything */
+        assert_matches!(cursor.enter_standard(&document_host, false, &key::Key::Unrecognized), Err(EntryError::KeyNotRecognized));
+
+        /* all the edits should have been stacked onto one filter */
+        let document = document_host.get();
+        assert_eq!(document.datapath.len(), 1);
+        assert_matches!(&document.datapath[0], crate::model::datapath::Filter::Overwrite(of) if of.offset == 0 && of.bytes[0..3] == [0x1a, 0x3f, 0xff]);
+    }
+
+    #[test]
+    fn hex_insert() {
+        let root = structure::Node::builder()
+            .name("root")
+            .size(0x20)
+            .child(0x10, |b| b
+                   .name("child")
+                   .size(0x8))
+            .build();
+        
+        let document_host = sync::Arc::new(document::Builder::new(root)
+            .datapath(imbl::vector![crate::model::datapath::OverwriteFilter { offset: 0, bytes: vec![0xff; 0x20] }.to_filter()].into())
+            .host());
+        let mut cursor = Cursor::new(document_host.get());
+
+        for key in [key::Key::_1, key::Key::_2, key::Key::_3, key::Key::_4] {
+            assert_matches!(cursor.enter_standard(&document_host, true, &key), Ok(MovementResult::Ok));
+        }
+        assert_matches!(&cursor.class, CursorClass::Hexdump(hxc) if hxc.offset == addr::Size::from(2) && !hxc.low_nybble);
+
+        /* the new bytes should all be in one insert filter, and the structure should have grown to fit them */
+        let document = document_host.get();
+        assert_eq!(document.datapath.len(), 2);
+        assert_matches!(&document.datapath[1], crate::model::datapath::Filter::Insert(inf) if inf.offset == 0 && inf.bytes == [0x12, 0x34]);
+        assert_eq!(document.root.size, addr::Size::from(0x22));
+        assert_eq!(document.root.children[0].offset, addr::Address::from(0x12));
+    }
+    
     #[test]
     fn can_place_with_no_content() {
         let root = structure::Node::builder()
@@ -1,4 +1,9 @@
+use std::task;
+
 use crate::model::addr;
+use crate::model::datapath;
+use crate::model::datapath::DataPathExt;
+use crate::model::document;
 use crate::model::listing::cursor;
 use crate::model::listing::line;
 use crate::model::listing::token;
@@ -162,61 +167,42 @@ impl cursor::CursorClassExt for Cursor {
         }
     }
 
-    /*
-    fn enter_standard(&mut self, document_host: &document::DocumentHost, insert: bool, key: &cursor::key::Key) -> Result<cursor::MovementResult, cursor::EntryError> {
-        let nybble = match key {
-            cursor::key::Key::_0 => 0,
-            cursor::key::Key::_1 => 1,
-            cursor::key::Key::_2 => 2,
-            cursor::key::Key::_3 => 3,
-            cursor::key::Key::_4 => 4,
-            cursor::key::Key::_5 => 5,
-            cursor::key::Key::_6 => 6,
-            cursor::key::Key::_7 => 7,
-            cursor::key::Key::_8 => 8,
-            cursor::key::Key::_9 => 9,
-            cursor::key::Key::a => 0xa,
-            cursor::key::Key::b => 0xb,
-            cursor::key::Key::c => 0xc,
-            cursor::key::Key::d => 0xd,
-            cursor::key::Key::e => 0xe,
-            cursor::key::Key::f => 0xf,
-            _ => return Err(cursor::EntryError::KeyNotRecognized)
-        };
+    fn enter_standard(&self, document: &document::Document, insert: bool, key: &cursor::key::Key) -> Result<document::change::Change, cursor::EntryError> {
+        let nybble = key.to_nybble().ok_or(cursor::EntryError::KeyNotRecognized)?;
+        let addr = self.get_addr();
 
-        let i = self.offset.bytes as usize;
-        let extent = self.lg.as_hex_line().extent;
-        let loc = extent.begin.byte + self.offset.bytes;
-        let shift = extent.begin.bit;
-
-        /*
-         * +-----------------+-----------------+
-         * | 0 1 2 3 4 5 6 7 | 0 1 2 3 4 5 6 7 |
-         * +-----------------+-----------------+
-         * 
-         */
-
-        if self.low_nybble && shift <= 4 {
-            let raw = self.lg.as_hex_line().bytes[i].get_loaded().ok_or(cursor::EntryError::DataNotLoaded)?;
-            let mask = 0xF << shift;
-            
-            document_host.patch_byte(loc, (raw & !mask) | (nybble << shift));
-        } else if !self.low_nybble && shift == 0 {
-            let raw = self.lg.as_hex_line().bytes[i].get_loaded().ok_or(cursor::EntryError::DataNotLoaded)?;
-            let mask = 0xF << 4;
-            
-            if insert {
-                document_host.insert_byte(loc, (raw & !mask) | (nybble << 4));
-            } else {
-                document_host.patch_byte(loc, (raw & !mask) | (nybble << 4));
-            }
-        } else {
-            todo!(); // TODO
+        // TODO: support entry in nodes that aren't byte-aligned
+        if addr.bit != 0 {
+            return Err(cursor::EntryError::InvalidForPosition);
         }
 
-        Ok(self.move_right())
+        /* Typing the high nybble inserts a new byte. The low nybble gets overwritten into it afterwards like normal. */
+        if insert && !self.low_nybble {
+            return Ok(document.insert_data(self.token.common.node_path.clone(), self.extent.begin + self.offset, vec![nybble << 4]));
+        }
+
+        /* Need to know the current value of the byte so we can keep the nybble we're not replacing. */
+        let mut record = [datapath::ByteRecord::default()];
+        document.datapath.fetch(datapath::ByteRecordRange::new(addr.byte, &mut record), &mut task::Context::from_waker(futures::task::noop_waker_ref()));
+        let record = record[0];
+
+        if record.pending || record.error.is_some() || !record.has_any_value() {
+            return Err(cursor::EntryError::DataNotLoaded);
+        }
+
+        let value = if self.low_nybble {
+            (record.value & 0xf0) | nybble
+        } else {
+            (record.value & 0x0f) | (nybble << 4)
+        };
+
+        Ok(document.stack_filter(datapath::OverwriteFilter {
+            offset: addr.byte,
+            bytes: vec![value],
+        }.to_filter()))
     }
 
+    /*
     fn enter_utf8(&mut self, _document_host: &document::DocumentHost, _insert: bool, _key: &cursor::key::Key) -> Result<cursor::MovementResult, cursor::EntryError> {
         Err(cursor::EntryError::KeyNotRecognized) // TODO
 }
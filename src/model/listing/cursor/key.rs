@@ -8,10 +8,14 @@ pub enum Key {
 }
 
 #[cfg(feature = "gtk")]
-impl From<&gdk::KeyEvent> for Key {
-    fn from(key: &gdk::KeyEvent) -> Key {
-        match key.keyval() {
-            _ if !key.modifier_state().is_empty() => Key::Unrecognized,
+impl Key {
+    pub fn new(keyval: gdk::Key, modifier: gdk::ModifierType) -> Key {
+        /* Shift and caps lock are fine; they just change the case of the letter. */
+        if !(modifier - gdk::ModifierType::SHIFT_MASK - gdk::ModifierType::LOCK_MASK).is_empty() {
+            return Key::Unrecognized;
+        }
+        
+        match keyval.to_lower() {
             gdk::Key::_0 => Key::_0,
             gdk::Key::_1 => Key::_1,
             gdk::Key::_2 => Key::_2,
@@ -32,3 +36,35 @@ impl From<&gdk::KeyEvent> for Key {
         }
     }
 }
+
+#[cfg(feature = "gtk")]
+impl From<&gdk::KeyEvent> for Key {
+    fn from(key: &gdk::KeyEvent) -> Key {
+        Key::new(key.keyval(), key.modifier_state())
+    }
+}
+
+impl Key {
+    /// Returns the value of the hex digit this key represents, if any.
+    pub fn to_nybble(&self) -> Option<u8> {
+        match self {
+            Key::_0 => Some(0),
+            Key::_1 => Some(1),
+            Key::_2 => Some(2),
+            Key::_3 => Some(3),
+            Key::_4 => Some(4),
+            Key::_5 => Some(5),
+            Key::_6 => Some(6),
+            Key::_7 => Some(7),
+            Key::_8 => Some(8),
+            Key::_9 => Some(9),
+            Key::a => Some(0xa),
+            Key::b => Some(0xb),
+            Key::c => Some(0xc),
+            Key::d => Some(0xd),
+            Key::e => Some(0xe),
+            Key::f => Some(0xf),
+            Key::Unrecognized => None,
+        }
+    }
+}
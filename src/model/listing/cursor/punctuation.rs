@@ -1,4 +1,5 @@
 use crate::model::addr;
+use crate::model::document;
 use crate::model::listing::cursor;
 use crate::model::listing::line;
 use crate::model::listing::token;
@@ -75,4 +76,8 @@ impl cursor::CursorClassExt for Cursor {
     fn move_right_large(&mut self) -> cursor::MovementResult {
         cursor::MovementResult::HitEnd
     }
+
+    fn enter_standard(&self, _document: &document::Document, _insert: bool, _key: &cursor::key::Key) -> Result<document::change::Change, cursor::EntryError> {
+        Err(cursor::EntryError::KeyNotRecognized)
+    }
 }
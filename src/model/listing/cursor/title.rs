@@ -1,4 +1,5 @@
 use crate::model::addr;
+use crate::model::document;
 use crate::model::listing::cursor;
 use crate::model::listing::line;
 use crate::model::listing::token;
@@ -81,11 +82,11 @@ impl cursor::CursorClassExt for Cursor {
         cursor::MovementResult::HitEnd
     }
 
-    /*
-    fn enter_standard(&mut self, _document_host: &document::DocumentHost, _insert: bool, _key: &cursor::key::Key) -> Result<cursor::MovementResult, cursor::EntryError> {
+    fn enter_standard(&self, _document: &document::Document, _insert: bool, _key: &cursor::key::Key) -> Result<document::change::Change, cursor::EntryError> {
         Err(cursor::EntryError::KeyNotRecognized)
     }
 
+    /*
     fn enter_utf8(&mut self, _document_host: &document::DocumentHost, _insert: bool, _key: &cursor::key::Key) -> Result<cursor::MovementResult, cursor::EntryError> {
         Err(cursor::EntryError::KeyNotRecognized)
 }
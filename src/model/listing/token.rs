@@ -201,6 +201,14 @@ impl<'a> TokenRef<'a> {
     pub fn node_addr(&self) -> addr::Address {
         self.common().node_addr
     }
+
+    pub fn absolute_extent(&self) -> addr::Extent {
+        match self {
+            TokenRef::Hexdump(token) => token.absolute_extent(),
+            TokenRef::Hexstring(token) => token.extent.rebase(token.common.node_addr),
+            _ => addr::unit::EMPTY
+        }
+    }
 }
 
 impl fmt::Debug for Token {
@@ -106,7 +106,11 @@ impl Selection {
                 StructureMode::Range(sr) => sr.port_doc_change(new_doc, change),
                 StructureMode::All => StructureMode::All,
             }),
-            Mode::Address(extent) => Mode::Address(extent),
+            Mode::Address(mut extent) => {
+                change.update_offset(&new_doc.root, &[], &mut extent.begin);
+                change.update_offset(&new_doc.root, &[], &mut extent.end);
+                Mode::Address(extent)
+            },
         };
     }
 
@@ -152,6 +156,11 @@ impl versioned::Change<Selection> for Change {
                 ChangeRecord {}
             },
 
+            Change::AssignAddress(extent) => {
+                selection.mode = Mode::Address(*extent);
+                ChangeRecord {}
+            },
+
             Change::Clear => {
                 selection.mode = match selection.mode {
                     Mode::Structure(_) => Mode::Structure(StructureMode::Empty),
@@ -304,6 +313,22 @@ impl StructureRange {
                     }
                 },
                 doc_change::ChangeType::DeleteRange { .. } => self,
+                doc_change::ChangeType::StackFilter { .. } => self,
+                doc_change::ChangeType::ReplaceTopFilter { .. } => self,
+                doc_change::ChangeType::AddFilter { .. } => self,
+                doc_change::ChangeType::RemoveFilter { .. } => self,
+                doc_change::ChangeType::ReplaceFilter { .. } => self,
+                doc_change::ChangeType::ReorderFilter { .. } => self,
+                doc_change::ChangeType::ReplaceDatapath { .. } => self,
+                doc_change::ChangeType::RewriteDatapath { .. } => self,
+                doc_change::ChangeType::InsertData { .. }
+                    | doc_change::ChangeType::RemoveInsertedData { .. }
+                    | doc_change::ChangeType::DeleteData { .. }
+                    | doc_change::ChangeType::RestoreDeletedData { .. } => {
+                    change.update_offset(&new_doc.root, &self.path, &mut self.begin.0);
+                    change.update_offset(&new_doc.root, &self.path, &mut self.end.0);
+                    self
+                },
             }),
             doc_change::UpdatePathResult::Destructured => match &change.ty {
                 /* We had selected a range within to a node that got
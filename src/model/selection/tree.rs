@@ -215,6 +215,18 @@ impl Selection {
         match change.ty {
             doc_change::ChangeType::AlterNode { .. } => false,
             doc_change::ChangeType::AlterNodesBulk { .. } => false,
+            doc_change::ChangeType::StackFilter { .. } => false,
+            doc_change::ChangeType::ReplaceTopFilter { .. } => false,
+            doc_change::ChangeType::InsertData { .. } => false,
+            doc_change::ChangeType::RemoveInsertedData { .. } => false,
+            doc_change::ChangeType::DeleteData { .. } => false,
+            doc_change::ChangeType::RestoreDeletedData { .. } => false,
+            doc_change::ChangeType::AddFilter { .. } => false,
+            doc_change::ChangeType::RemoveFilter { .. } => false,
+            doc_change::ChangeType::ReplaceFilter { .. } => false,
+            doc_change::ChangeType::ReorderFilter { .. } => false,
+            doc_change::ChangeType::ReplaceDatapath { .. } => false,
+            doc_change::ChangeType::RewriteDatapath { .. } => false,
             
             _ => {
                 // TODO: actually handle structural changes
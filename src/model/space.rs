@@ -1,9 +1,15 @@
 use std::vec;
 
+pub mod buffer;
 pub mod cache;
+pub mod concat;
 
 pub mod file;
+pub mod gdb;
+pub mod mmap;
+pub mod process;
 
+#[derive(Debug)]
 pub enum FetchResult {
     Ok(vec::Vec<u8>),
     Partial(vec::Vec<u8>),
@@ -14,22 +20,117 @@ pub enum FetchResult {
 pub trait AddressSpaceExt {
     fn get_label(&self) -> &str;
     fn fetch(&self, extent: (u64, u64)) -> impl std::future::Future<Output = FetchResult>;
+
+    /// How many bytes the space holds, counting from offset zero. None if we can't tell.
+    fn size(&self) -> Option<u64>;
+
+    /// The range of offsets that hold data, as (offset, length). None if we can't tell.
+    fn extents(&self) -> Option<(u64, u64)> {
+        self.size().map(|size| (0, size))
+    }
+
+    /// Bumped whenever the data behind the space might have changed out from under us, so that caches know to throw
+    /// away anything they fetched before.
+    fn generation(&self) -> u64 {
+        0
+    }
+
+    /// The files on disk that this space reads from, so that they can be watched for changes.
+    fn backing_files(&self) -> vec::Vec<&std::path::Path> {
+        vec::Vec::new()
+    }
+
+    /// Checks whether any of the backing files changed since we opened them. If `reopen` is set, changed files get
+    /// reopened and the generation gets bumped. Returns whether anything changed.
+    fn check_for_changes(&self, _reopen: bool) -> bool {
+        false
+    }
 }
 
 pub enum AddressSpace {
-    File(file::FileAddressSpace)
+    File(file::FileAddressSpace),
+    Mmap(mmap::MmapAddressSpace),
+    Process(process::ProcessAddressSpace),
+    Buffer(buffer::BufferAddressSpace),
+    Gdb(gdb::GdbAddressSpace),
+    Concat(concat::ConcatAddressSpace),
 }
 
 impl AddressSpaceExt for AddressSpace {
     fn get_label(&self) -> &str {
         match self {
             AddressSpace::File(fas) => fas.get_label(),
+            AddressSpace::Mmap(mas) => mas.get_label(),
+            AddressSpace::Process(pas) => pas.get_label(),
+            AddressSpace::Buffer(bas) => bas.get_label(),
+            AddressSpace::Gdb(gas) => gas.get_label(),
+            AddressSpace::Concat(cas) => cas.get_label(),
         }
     }
 
     async fn fetch(&self, extent: (u64, u64)) -> FetchResult {
         match self {
             AddressSpace::File(fas) => fas.fetch(extent).await,
+            AddressSpace::Mmap(mas) => mas.fetch(extent).await,
+            AddressSpace::Process(pas) => pas.fetch(extent).await,
+            AddressSpace::Buffer(bas) => bas.fetch(extent).await,
+            AddressSpace::Gdb(gas) => gas.fetch(extent).await,
+            AddressSpace::Concat(cas) => cas.fetch(extent).await,
+        }
+    }
+
+    fn size(&self) -> Option<u64> {
+        match self {
+            AddressSpace::File(fas) => fas.size(),
+            AddressSpace::Mmap(mas) => mas.size(),
+            AddressSpace::Process(pas) => pas.size(),
+            AddressSpace::Buffer(bas) => bas.size(),
+            AddressSpace::Gdb(gas) => gas.size(),
+            AddressSpace::Concat(cas) => cas.size(),
+        }
+    }
+
+    fn extents(&self) -> Option<(u64, u64)> {
+        match self {
+            AddressSpace::File(fas) => fas.extents(),
+            AddressSpace::Mmap(mas) => mas.extents(),
+            AddressSpace::Process(pas) => pas.extents(),
+            AddressSpace::Buffer(bas) => bas.extents(),
+            AddressSpace::Gdb(gas) => gas.extents(),
+            AddressSpace::Concat(cas) => cas.extents(),
+        }
+    }
+
+    fn generation(&self) -> u64 {
+        match self {
+            AddressSpace::File(fas) => fas.generation(),
+            AddressSpace::Mmap(mas) => mas.generation(),
+            AddressSpace::Process(pas) => pas.generation(),
+            AddressSpace::Buffer(bas) => bas.generation(),
+            AddressSpace::Gdb(gas) => gas.generation(),
+            AddressSpace::Concat(cas) => cas.generation(),
+        }
+    }
+
+    fn backing_files(&self) -> vec::Vec<&std::path::Path> {
+        match self {
+            AddressSpace::File(fas) => fas.backing_files(),
+            AddressSpace::Mmap(mas) => mas.backing_files(),
+            AddressSpace::Process(pas) => pas.backing_files(),
+            AddressSpace::Buffer(bas) => bas.backing_files(),
+            AddressSpace::Gdb(gas) => gas.backing_files(),
+            AddressSpace::Concat(cas) => cas.backing_files(),
+        }
+    }
+
+    fn check_for_changes(&self, reopen: bool) -> bool {
+        match self {
+            AddressSpace::File(fas) => fas.check_for_changes(reopen),
+            AddressSpace::Mmap(mas) => mas.check_for_changes(reopen),
+            AddressSpace::Process(pas) => pas.check_for_changes(reopen),
+            AddressSpace::Buffer(bas) => bas.check_for_changes(reopen),
+            AddressSpace::Gdb(gas) => gas.check_for_changes(reopen),
+            AddressSpace::Concat(cas) => cas.check_for_changes(reopen),
         }
     }
 }
@@ -39,3 +140,33 @@ impl From<file::FileAddressSpace> for AddressSpace {
         AddressSpace::File(fas)
     }
 }
+
+impl From<mmap::MmapAddressSpace> for AddressSpace {
+    fn from(mas: mmap::MmapAddressSpace) -> AddressSpace {
+        AddressSpace::Mmap(mas)
+    }
+}
+
+impl From<process::ProcessAddressSpace> for AddressSpace {
+    fn from(pas: process::ProcessAddressSpace) -> AddressSpace {
+        AddressSpace::Process(pas)
+    }
+}
+
+impl From<buffer::BufferAddressSpace> for AddressSpace {
+    fn from(bas: buffer::BufferAddressSpace) -> AddressSpace {
+        AddressSpace::Buffer(bas)
+    }
+}
+
+impl From<gdb::GdbAddressSpace> for AddressSpace {
+    fn from(gas: gdb::GdbAddressSpace) -> AddressSpace {
+        AddressSpace::Gdb(gas)
+    }
+}
+
+impl From<concat::ConcatAddressSpace> for AddressSpace {
+    fn from(cas: concat::ConcatAddressSpace) -> AddressSpace {
+        AddressSpace::Concat(cas)
+    }
+}
@@ -0,0 +1,111 @@
+use std::sync;
+use std::vec;
+use std::string;
+
+use crate::model::space;
+
+/// An address space whose contents live in memory. Useful for scratch documents, pasted data, and small blobs that get
+/// saved inside the project file itself.
+pub struct BufferAddressSpace {
+    pub bytes: sync::Arc<vec::Vec<u8>>,
+    pub label: string::String,
+}
+
+impl BufferAddressSpace {
+    pub fn new(bytes: vec::Vec<u8>, label: &str) -> BufferAddressSpace {
+        Self::new_shared(sync::Arc::new(bytes), label)
+    }
+
+    pub fn new_shared(bytes: sync::Arc<vec::Vec<u8>>, label: &str) -> BufferAddressSpace {
+        BufferAddressSpace {
+            bytes,
+            label: label.to_string(),
+        }
+    }
+
+    fn read_sync(&self, offset: u64, length: u64) -> space::FetchResult {
+        if offset >= self.bytes.len() as u64 {
+            return space::FetchResult::Unreadable;
+        }
+
+        let end = std::cmp::min(offset.saturating_add(length), self.bytes.len() as u64);
+        let out = self.bytes[offset as usize..end as usize].to_vec();
+
+        if out.len() as u64 == length {
+            space::FetchResult::Ok(out)
+        } else {
+            space::FetchResult::Partial(out)
+        }
+    }
+}
+
+impl space::AddressSpaceExt for BufferAddressSpace {
+    fn get_label(&self) -> &str {
+        &self.label
+    }
+
+    async fn fetch(&self, extent: (u64, u64)) -> space::FetchResult {
+        self.read_sync(extent.0, extent.1)
+    }
+
+    fn size(&self) -> Option<u64> {
+        Some(self.bytes.len() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::task;
+
+    use assert_matches::assert_matches;
+
+    use crate::model::datapath;
+    use crate::model::datapath::DataPathExt;
+
+    #[test]
+    fn fetch() {
+        let space = BufferAddressSpace::new((0..0x100).map(|i| i as u8).collect(), "test");
+
+        assert_matches!(futures::executor::block_on(space::AddressSpaceExt::fetch(&space, (0x10, 4))), space::FetchResult::Ok(v) if v == vec![0x10, 0x11, 0x12, 0x13]);
+        assert_matches!(futures::executor::block_on(space::AddressSpaceExt::fetch(&space, (0xfe, 4))), space::FetchResult::Partial(v) if v == vec![0xfe, 0xff]);
+        assert_matches!(futures::executor::block_on(space::AddressSpaceExt::fetch(&space, (0x100, 4))), space::FetchResult::Unreadable);
+        assert_matches!(futures::executor::block_on(space::AddressSpaceExt::fetch(&space, (u64::MAX, 4))), space::FetchResult::Unreadable);
+
+        assert_eq!(space::AddressSpaceExt::size(&space), Some(0x100));
+        assert_eq!(space::AddressSpaceExt::extents(&space), Some((0, 0x100)));
+    }
+
+    #[test]
+    fn through_load_space_filter() {
+        let space = sync::Arc::new(space::AddressSpace::from(BufferAddressSpace::new((0..0x100).map(|i| i as u8).collect(), "test")));
+
+        /* small cache blocks so that the read straddles a few of them */
+        let datapath: datapath::DataPath = imbl::vector![
+            datapath::test_load_space(space, 0x1000, 0x20, Some(0x40)),
+        ].into();
+
+        let mut records = vec![datapath::ByteRecord::default(); 0x48];
+        datapath.fetch(datapath::ByteRecordRange::new(0xffc, &mut records), &mut task::Context::from_waker(futures::task::noop_waker_ref()));
+
+        assert!(records[..4].iter().all(|br| !br.loaded));
+        assert!(records[4..0x44].iter().all(|br| br.loaded && !br.pending && br.error.is_none()));
+        assert_eq!(records[4..0x44].iter().map(|br| br.value).collect::<vec::Vec<u8>>(), (0x20..0x60).collect::<vec::Vec<u8>>());
+        assert!(records[0x44..].iter().all(|br| !br.loaded));
+    }
+
+    #[test]
+    fn past_end() {
+        let datapath: datapath::DataPath = imbl::vector![
+            datapath::test_load(vec![0xaa; 0x18]),
+        ].into();
+
+        let mut records = vec![datapath::ByteRecord::default(); 0x30];
+        datapath.fetch(datapath::ByteRecordRange::new(0, &mut records), &mut task::Context::from_waker(futures::task::noop_waker_ref()));
+
+        assert!(records[..0x18].iter().all(|br| br.loaded && br.value == 0xaa && br.error.is_none()));
+        assert!(records[0x18..].iter().all(|br| br.error == Some(datapath::ByteError::EndOfSpace)));
+    }
+}
+
@@ -1,3 +1,4 @@
+use std::collections;
 use std::future::Future;
 use std::pin::Pin;
 use std::sync;
@@ -7,6 +8,8 @@ use rental::rental;
 
 use crate::model::space;
 use crate::model::space::AddressSpaceExt;
+#[cfg(feature = "gtk")]
+use crate::view::config;
 
 rental! {
     mod rentals {
@@ -28,75 +31,250 @@ pub enum SpaceCacheEntry {
     Finished(space::FetchResult),
 }
 
+/// Which way the user is moving through the address space, so we know which side to read ahead on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Direction {
+    #[default]
+    Forward,
+    Backward,
+}
+
+/// How many fetches we're willing to have going at once before we stop starting new prefetches. Fetches that somebody
+/// actually asked for are never held back.
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 8;
+
+pub const DEFAULT_BLOCK_SIZE: u64 = 0x1000;
+pub const DEFAULT_BLOCK_COUNT: usize = 1024; /* 4 MiB cache is plenty */
+
+/// Counters for how well a cache is working out, so that its geometry can be tuned.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Statistics {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// Prefetches that got scrolled past before they finished.
+    pub cancellations: u64,
+    /// Blocks that are still being fetched right now.
+    pub pending: u64,
+    pub bytes_fetched: u64,
+}
+
+/// The block size and block count that new caches get, unless a project says otherwise.
+#[cfg(feature = "gtk")]
+pub fn default_geometry() -> (u64, std::num::NonZeroUsize) {
+    let config = config::INSTANCE.borrow();
+    
+    (std::cmp::max(config.cache_block_size, 1), std::num::NonZeroUsize::new(config.cache_block_count).unwrap_or(std::num::NonZeroUsize::new(DEFAULT_BLOCK_COUNT).unwrap()))
+}
+
+#[cfg(not(feature = "gtk"))]
+pub fn default_geometry() -> (u64, std::num::NonZeroUsize) {
+    (DEFAULT_BLOCK_SIZE, std::num::NonZeroUsize::new(DEFAULT_BLOCK_COUNT).unwrap())
+}
+
+#[cfg(feature = "gtk")]
+fn get_max_in_flight() -> usize {
+    config::INSTANCE.borrow().cache_max_in_flight
+}
+
+#[cfg(not(feature = "gtk"))]
+fn get_max_in_flight() -> usize {
+    DEFAULT_MAX_IN_FLIGHT
+}
+
 pub struct SpaceCache {
     pub block_size: u64,
     pub block_count: std::num::NonZeroUsize,
+    pub max_in_flight: usize,
     pub space: sync::Arc<space::AddressSpace>,
     /* can't use RwLock here; LruCache mutates on read */
-    lru: parking_lot::Mutex<lru::LruCache<u64, SpaceCacheEntry>>,
+    blocks: parking_lot::Mutex<Blocks>,
+    /// The space's generation as of when the blocks in the cache were fetched.
+    generation: sync::atomic::AtomicU64,
+}
+
+struct Blocks {
+    lru: lru::LruCache<u64, SpaceCacheEntry>,
+    /* blocks that we started fetching on speculation, which nobody has asked for yet and which are still pending */
+    prefetching: collections::HashSet<u64>,
+    /* these only change while the lock is held, so they may as well live under it */
+    statistics: Statistics,
 }
 
-pub struct SpaceCacheLock<'a>(parking_lot::MutexGuard<'a, lru::LruCache<u64, SpaceCacheEntry>>);
+pub struct SpaceCacheLock<'a>(parking_lot::MutexGuard<'a, Blocks>);
 
 impl SpaceCache {
     pub fn new(space: sync::Arc<space::AddressSpace>, block_size: u64, block_count: std::num::NonZeroUsize) -> SpaceCache {
         SpaceCache {
             block_size,
             block_count: block_count,
+            max_in_flight: get_max_in_flight(),
+            generation: sync::atomic::AtomicU64::new(space.generation()),
             space,
-            lru: parking_lot::Mutex::new(lru::LruCache::new(block_count)),
+            blocks: parking_lot::Mutex::new(Blocks {
+                lru: lru::LruCache::new(block_count),
+                prefetching: collections::HashSet::new(),
+                statistics: Statistics::default(),
+            }),
         }
     }
     
     pub fn fetch_block<'a>(&'a self, addr: u64, cx: &mut task::Context) -> parking_lot::MappedMutexGuard<'a, SpaceCacheEntry> {
-        parking_lot::MutexGuard::map(self.lock().0, |lru| self.fetch_block_impl(addr, lru, cx))
+        parking_lot::MutexGuard::map(self.lock().0, |blocks| self.fetch_block_impl(addr, blocks, cx))
     }
 
+    /// If the space changed since we fetched the blocks we have, they're all thrown away here.
     pub fn lock(&self) -> SpaceCacheLock {
-        SpaceCacheLock(self.lru.lock())
+        let mut blocks = self.blocks.lock();
+
+        let generation = self.space.generation();
+        if self.generation.swap(generation, sync::atomic::Ordering::AcqRel) != generation {
+            blocks.lru.clear();
+            blocks.prefetching.clear();
+        }
+
+        SpaceCacheLock(blocks)
     }
     
     pub fn fetch_block_with_lock<'a, 'b>(&'a self, addr: u64, lru_guard: &'b mut SpaceCacheLock, cx: &mut task::Context) -> &'b mut SpaceCacheEntry {
         self.fetch_block_impl(addr, &mut *lru_guard.0, cx)
     }
 
-    pub fn fetch_block_impl<'a, 'b>(&'a self, addr: u64, lru: &'b mut lru::LruCache<u64, SpaceCacheEntry>, cx: &mut task::Context) -> &'b mut SpaceCacheEntry {
-
+    fn fetch_block_impl<'a, 'b>(&'a self, addr: u64, blocks: &'b mut Blocks, cx: &mut task::Context) -> &'b mut SpaceCacheEntry {
         assert!(addr % self.block_size == 0, "misaligned address");
 
-        if !lru.contains(&addr) {
-            let future = SpaceCacheFuture::new(self.space.clone(), |space| Box::pin(space.fetch((addr, self.block_size))));
-            let mut entry = SpaceCacheEntry::Pending(future);
-            entry.poll(cx);
-            
-            lru.put(addr, entry);
+        /* somebody wants this block now, so it's no longer up for cancellation */
+        blocks.prefetching.remove(&addr);
+
+        if blocks.lru.contains(&addr) {
+            blocks.statistics.hits+= 1;
+        } else {
+            blocks.statistics.misses+= 1;
+            self.start_fetch(addr, blocks, cx);
         }
                 
-        lru.get_mut(&addr).unwrap()
+        blocks.lru.get_mut(&addr).unwrap()
+    }
+
+    fn start_fetch(&self, addr: u64, blocks: &mut Blocks, cx: &mut task::Context) {
+        let future = SpaceCacheFuture::new(self.space.clone(), |space| Box::pin(space.fetch((addr, self.block_size))));
+        let mut entry = SpaceCacheEntry::Pending(future);
+        
+        if entry.poll(cx) {
+            blocks.statistics.bytes_fetched+= entry.bytes_fetched();
+        }
+
+        /* we only get here if the block isn't already cached, so anything pushed back out is an eviction */
+        if let Some((evicted, _)) = blocks.lru.push(addr, entry) {
+            blocks.statistics.evictions+= 1;
+            blocks.prefetching.remove(&evicted);
+        }
+    }
+
+    /// Starts fetching the blocks covering `ahead` (a `(begin, end)` range of space addresses), nearest ones first in
+    /// the given direction, without letting more than `max_in_flight` fetches be pending at once. Any prefetches still
+    /// pending outside of `keep` have been scrolled past, so they get cancelled. Either range can be `None` if there's
+    /// nothing for us there.
+    pub fn prefetch(&self, ahead: Option<(u64, u64)>, keep: Option<(u64, u64)>, direction: Direction, cx: &mut task::Context) {
+        let mut guard = self.lock();
+        let blocks = &mut *guard.0;
+
+        let keep = keep.map(|(begin, end)| ((begin / self.block_size) * self.block_size, end));
+        blocks.prefetching.retain(|addr| {
+            let wanted = blocks.lru.contains(addr) && keep.map_or(false, |(begin, end)| *addr >= begin && *addr < end);
+            if !wanted && blocks.lru.pop(addr).is_some() {
+                /* dropping the future cancels the fetch */
+                blocks.statistics.cancellations+= 1;
+            }
+            wanted
+        });
+
+        let Some((begin, end)) = ahead.filter(|(begin, end)| begin < end) else { return };
+
+        let first_block = begin / self.block_size;
+        let last_block = (end - 1) / self.block_size;
+        
+        /* don't prefetch so much that we evict the blocks that are on screen */
+        let limit = self.block_count.get() / 2;
+
+        let order: Box<dyn Iterator<Item = u64>> = match direction {
+            Direction::Forward => Box::new((first_block..=last_block).take(limit)),
+            Direction::Backward => Box::new((first_block..=last_block).rev().take(limit)),
+        };
+
+        let mut in_flight = blocks.lru.iter().filter(|(_, entry)| !entry.is_finished()).count();
+        
+        for block in order {
+            if in_flight >= self.max_in_flight {
+                break;
+            }
+            
+            let addr = block * self.block_size;
+
+            if blocks.lru.contains(&addr) {
+                continue;
+            }
+
+            self.start_fetch(addr, blocks, cx);
+
+            if blocks.lru.peek(&addr).is_some_and(|entry| !entry.is_finished()) {
+                blocks.prefetching.insert(addr);
+                in_flight+= 1;
+            }
+        }
     }
 
     pub fn poll_blocks(&self, cx: &mut task::Context) -> bool {
         let mut work_needed = false;
-        let mut lru_guard = self.lru.lock();
+        let mut guard = self.lock();
+        let blocks = &mut *guard.0;
         
-        for (_, entry) in lru_guard.iter_mut() {
-            entry.poll(cx);
-            work_needed|= !entry.is_finished();
+        for (addr, entry) in blocks.lru.iter_mut() {
+            if entry.poll(cx) {
+                blocks.statistics.bytes_fetched+= entry.bytes_fetched();
+            }
+
+            if entry.is_finished() {
+                blocks.prefetching.remove(addr);
+            } else {
+                work_needed = true;
+            }
         }
 
         work_needed
     }
+
+    pub fn statistics(&self) -> Statistics {
+        let blocks = self.lock();
+
+        Statistics {
+            pending: blocks.0.lru.iter().filter(|(_, entry)| !entry.is_finished()).count() as u64,
+            ..blocks.0.statistics
+        }
+    }
 }
 
 impl SpaceCacheEntry {
-    fn poll(&mut self, cx: &mut task::Context) {
+    /// Returns whether the entry just finished.
+    fn poll(&mut self, cx: &mut task::Context) -> bool {
+        let mut finished = false;
+        
         take_mut::take(self, |entry| match entry {
             SpaceCacheEntry::Pending(mut future) => match future.rent_mut(|f| f.as_mut().poll(cx)) {
-                task::Poll::Ready(result) => SpaceCacheEntry::Finished(result),
+                task::Poll::Ready(result) => { finished = true; SpaceCacheEntry::Finished(result) },
                 task::Poll::Pending => SpaceCacheEntry::Pending(future),
             },
             SpaceCacheEntry::Finished(result) => SpaceCacheEntry::Finished(result),
         });
+
+        finished
+    }
+
+    fn bytes_fetched(&self) -> u64 {
+        match self {
+            SpaceCacheEntry::Finished(space::FetchResult::Ok(bytes) | space::FetchResult::Partial(bytes)) => bytes.len() as u64,
+            _ => 0,
+        }
     }
 
     fn is_finished(&self) -> bool {
@@ -112,3 +290,119 @@ impl std::fmt::Debug for SpaceCache {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::model::space::buffer;
+    use crate::model::space::gdb;
+
+    fn cached_blocks(cache: &SpaceCache) -> Vec<u64> {
+        let mut blocks: Vec<u64> = cache.lock().0.lru.iter().map(|(addr, _)| *addr).collect();
+        blocks.sort();
+        blocks
+    }
+
+    #[test]
+    fn prefetch_direction() {
+        let space = sync::Arc::new(space::AddressSpace::from(buffer::BufferAddressSpace::new(vec![0; 0x100], "test")));
+        let cache = SpaceCache::new(space, 0x10, std::num::NonZeroUsize::new(16).unwrap());
+        let mut cx = task::Context::from_waker(futures::task::noop_waker_ref());
+
+        cache.prefetch(Some((0x48, 0x70)), Some((0x20, 0x70)), Direction::Forward, &mut cx);
+        assert_eq!(cached_blocks(&cache), vec![0x40, 0x50, 0x60]);
+
+        cache.prefetch(Some((0x08, 0x20)), Some((0x08, 0x48)), Direction::Backward, &mut cx);
+        assert_eq!(cached_blocks(&cache), vec![0x00, 0x10, 0x40, 0x50, 0x60]);
+
+        /* buffer fetches finish right away, so there's nothing pending to cancel */
+        cache.prefetch(None, None, Direction::Forward, &mut cx);
+        assert_eq!(cached_blocks(&cache), vec![0x00, 0x10, 0x40, 0x50, 0x60]);
+        assert!(cache.lock().0.prefetching.is_empty());
+    }
+
+    #[test]
+    fn prefetch_limit() {
+        let space = sync::Arc::new(space::AddressSpace::from(buffer::BufferAddressSpace::new(vec![0; 0x1000], "test")));
+        let cache = SpaceCache::new(space, 0x10, std::num::NonZeroUsize::new(8).unwrap());
+        let mut cx = task::Context::from_waker(futures::task::noop_waker_ref());
+
+        /* only ever fill up half the cache, nearest blocks first */
+        cache.prefetch(Some((0x800, 0x1000)), Some((0x800, 0x1000)), Direction::Backward, &mut cx);
+        assert_eq!(cached_blocks(&cache), vec![0xfc0, 0xfd0, 0xfe0, 0xff0]);
+    }
+
+    #[test]
+    fn prefetch_cancellation() {
+        let rt = tokio::runtime::Builder::new_multi_thread().worker_threads(1).enable_all().build().unwrap();
+        let _guard = rt.enter();
+
+        /* the kernel accepts the connection for us, but nobody ever answers, so fetches stay pending until we hang up */
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let gas = gdb::GdbAddressSpace::new(gdb::Target::Tcp(listener.local_addr().unwrap().to_string()), "silent");
+        gas.try_open().unwrap();
+
+        let mut cache = SpaceCache::new(sync::Arc::new(gas.into()), 0x10, std::num::NonZeroUsize::new(16).unwrap());
+        cache.max_in_flight = 3;
+        let mut cx = task::Context::from_waker(futures::task::noop_waker_ref());
+
+        cache.prefetch(Some((0x40, 0x100)), Some((0x40, 0x100)), Direction::Forward, &mut cx);
+        assert_eq!(cached_blocks(&cache), vec![0x40, 0x50, 0x60]);
+        assert_eq!(cache.statistics().pending, 3);
+
+        /* nothing more gets started while those are still going */
+        cache.prefetch(Some((0x40, 0x100)), Some((0x40, 0x100)), Direction::Forward, &mut cx);
+        assert_eq!(cached_blocks(&cache), vec![0x40, 0x50, 0x60]);
+
+        /* scrolling past the first two drops them, which makes room for more */
+        cache.prefetch(Some((0x68, 0x100)), Some((0x68, 0x100)), Direction::Forward, &mut cx);
+        assert_eq!(cached_blocks(&cache), vec![0x60, 0x70, 0x80]);
+        assert_eq!(cache.statistics().cancellations, 2);
+        assert_eq!(cache.statistics().pending, 3);
+
+        /* blocks that somebody asked for don't get cancelled, and aren't held back by the limit */
+        drop(cache.fetch_block(0x70, &mut cx));
+        drop(cache.fetch_block(0x00, &mut cx));
+        assert_eq!(cache.statistics().pending, 4);
+
+        cache.prefetch(None, None, Direction::Forward, &mut cx);
+        assert_eq!(cached_blocks(&cache), vec![0x00, 0x70]);
+        assert_eq!(cache.statistics().cancellations, 4);
+        assert_eq!(cache.statistics().pending, 2);
+
+        /* hanging up finishes whatever is left */
+        drop(listener);
+
+        let start = std::time::Instant::now();
+        while cache.poll_blocks(&mut cx) {
+            assert!(start.elapsed() < std::time::Duration::from_secs(30), "fetch never finished");
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        assert_eq!(cached_blocks(&cache), vec![0x00, 0x70]);
+        assert_eq!(cache.statistics().pending, 0);
+        assert!(cache.lock().0.prefetching.is_empty());
+    }
+
+    #[test]
+    fn statistics() {
+        let space = sync::Arc::new(space::AddressSpace::from(buffer::BufferAddressSpace::new(vec![0; 0x28], "test")));
+        let cache = SpaceCache::new(space, 0x10, std::num::NonZeroUsize::new(2).unwrap());
+        let mut cx = task::Context::from_waker(futures::task::noop_waker_ref());
+
+        for addr in [0x00, 0x10, 0x00, 0x20, 0x10] {
+            drop(cache.fetch_block(addr, &mut cx));
+        }
+
+        assert_eq!(cache.statistics(), Statistics {
+            hits: 1,
+            misses: 4,
+            evictions: 2,
+            cancellations: 0,
+            pending: 0,
+            /* the last block is short */
+            bytes_fetched: 0x10 + 0x10 + 0x8 + 0x10,
+        });
+    }
+}
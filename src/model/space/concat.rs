@@ -0,0 +1,192 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync;
+use std::vec;
+use std::string;
+
+use crate::model::space;
+use crate::model::space::AddressSpaceExt;
+
+/// One of the spaces that make up a [ConcatAddressSpace].
+pub struct Piece {
+    pub space: sync::Arc<space::AddressSpace>,
+    /// Where this piece starts in the concatenated space.
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Stitches an ordered list of spaces together end-to-end, e.g. for flash dumps that were taken one chip or partition
+/// at a time.
+pub struct ConcatAddressSpace {
+    pieces: vec::Vec<Piece>,
+    pub label: string::String,
+}
+
+impl ConcatAddressSpace {
+    /// Each space is paired with how many bytes of it to include.
+    pub fn new(spaces: vec::Vec<(sync::Arc<space::AddressSpace>, u64)>, label: &str) -> ConcatAddressSpace {
+        let mut offset = 0;
+
+        ConcatAddressSpace {
+            pieces: spaces.into_iter().map(|(space, size)| {
+                let piece = Piece { space, offset, size };
+                offset+= size;
+                piece
+            }).collect(),
+            label: label.to_string(),
+        }
+    }
+
+    pub fn pieces(&self) -> &[Piece] {
+        &self.pieces
+    }
+
+    /// Finds which piece the given offset falls in.
+    pub fn piece_at(&self, offset: u64) -> Option<&Piece> {
+        let index = self.pieces.partition_point(|p| p.offset + p.size <= offset);
+        self.pieces.get(index).filter(|p| p.offset <= offset)
+    }
+
+    /// Describes where the byte at the given offset came from, like "flash1.bin+0x10".
+    pub fn describe_offset(&self, offset: u64) -> Option<string::String> {
+        self.piece_at(offset).map(|p| format!("{}+{:#x}", p.space.get_label(), offset - p.offset))
+    }
+
+    async fn read(&self, offset: u64, length: u64) -> space::FetchResult {
+        let mut out = vec::Vec::with_capacity(length as usize);
+
+        while (out.len() as u64) < length {
+            let addr = offset + out.len() as u64;
+            let Some(piece) = self.piece_at(addr) else { break };
+            let chunk = std::cmp::min(length - out.len() as u64, piece.offset + piece.size - addr);
+
+            match piece.space.fetch((addr - piece.offset, chunk)).await {
+                space::FetchResult::Ok(bytes) => out.extend(bytes),
+                space::FetchResult::Partial(bytes) => {
+                    out.extend(bytes);
+                    break;
+                },
+                space::FetchResult::Unreadable => break,
+                space::FetchResult::IoError(e) if out.is_empty() => return space::FetchResult::IoError(e),
+                space::FetchResult::IoError(_) => break,
+            }
+        }
+
+        match out.len() {
+            0 => space::FetchResult::Unreadable,
+            n if n as u64 == length => space::FetchResult::Ok(out),
+            _ => space::FetchResult::Partial(out),
+        }
+    }
+}
+
+impl space::AddressSpaceExt for ConcatAddressSpace {
+    fn get_label(&self) -> &str {
+        &self.label
+    }
+
+    /* Pieces can be concatenated spaces too, so this needs to be boxed to keep the future from containing itself. The
+     * concrete return type has to be spelled out so the compiler can see it's Send + Sync without going in circles. */
+    #[allow(refining_impl_trait)]
+    fn fetch(&self, extent: (u64, u64)) -> Pin<Box<dyn Future<Output = space::FetchResult> + Send + Sync + '_>> {
+        Box::pin(self.read(extent.0, extent.1))
+    }
+
+    fn size(&self) -> Option<u64> {
+        Some(self.pieces.last().map(|p| p.offset + p.size).unwrap_or(0))
+    }
+
+    /// Generations only ever go up, so the sum changes whenever any of the pieces' does.
+    fn generation(&self) -> u64 {
+        self.pieces.iter().map(|p| p.space.generation()).sum()
+    }
+
+    fn backing_files(&self) -> vec::Vec<&std::path::Path> {
+        self.pieces.iter().flat_map(|p| p.space.backing_files()).collect()
+    }
+
+    fn check_for_changes(&self, reopen: bool) -> bool {
+        /* check every piece, even after finding one that changed */
+        self.pieces.iter().fold(false, |changed, p| p.space.check_for_changes(reopen) || changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_matches::assert_matches;
+
+    use crate::model::space::buffer;
+
+    fn buffer_space(label: &str, bytes: vec::Vec<u8>) -> sync::Arc<space::AddressSpace> {
+        sync::Arc::new(buffer::BufferAddressSpace::new(bytes, label).into())
+    }
+
+    fn fetch(space: &ConcatAddressSpace, extent: (u64, u64)) -> space::FetchResult {
+        futures::executor::block_on(space.fetch(extent))
+    }
+
+    fn create_test_space() -> ConcatAddressSpace {
+        ConcatAddressSpace::new(vec![
+            (buffer_space("a", vec![0xa0, 0xa1, 0xa2, 0xa3]), 4),
+            (buffer_space("b", vec![0xb0, 0xb1]), 2),
+            (buffer_space("c", vec![0xc0, 0xc1, 0xc2, 0xc3]), 4),
+        ], "a + b + c")
+    }
+
+    #[test]
+    fn pieces() {
+        let space = create_test_space();
+
+        assert_eq!(space.size(), Some(10));
+        assert_eq!(space.extents(), Some((0, 10)));
+        assert_eq!(space.piece_at(0).unwrap().space.get_label(), "a");
+        assert_eq!(space.piece_at(4).unwrap().space.get_label(), "b");
+        assert_eq!(space.piece_at(5).unwrap().space.get_label(), "b");
+        assert_eq!(space.piece_at(9).unwrap().space.get_label(), "c");
+        assert!(space.piece_at(10).is_none());
+
+        assert_eq!(space.describe_offset(7).as_deref(), Some("c+0x1"));
+    }
+
+    #[test]
+    fn fetch_across_boundaries() {
+        let space = create_test_space();
+
+        assert_matches!(fetch(&space, (1, 2)), space::FetchResult::Ok(v) if v == vec![0xa1, 0xa2]);
+        assert_matches!(fetch(&space, (2, 6)), space::FetchResult::Ok(v) if v == vec![0xa2, 0xa3, 0xb0, 0xb1, 0xc0, 0xc1]);
+        assert_matches!(fetch(&space, (0, 10)), space::FetchResult::Ok(v) if v == vec![0xa0, 0xa1, 0xa2, 0xa3, 0xb0, 0xb1, 0xc0, 0xc1, 0xc2, 0xc3]);
+        assert_matches!(fetch(&space, (8, 4)), space::FetchResult::Partial(v) if v == vec![0xc2, 0xc3]);
+        assert_matches!(fetch(&space, (10, 4)), space::FetchResult::Unreadable);
+    }
+
+    #[test]
+    fn short_piece() {
+        /* the middle piece claims to be bigger than it actually is */
+        let space = ConcatAddressSpace::new(vec![
+            (buffer_space("a", vec![0xa0, 0xa1]), 2),
+            (buffer_space("b", vec![0xb0]), 2),
+            (buffer_space("c", vec![0xc0, 0xc1]), 2),
+        ], "a + b + c");
+
+        assert_matches!(fetch(&space, (0, 6)), space::FetchResult::Partial(v) if v == vec![0xa0, 0xa1, 0xb0]);
+        assert_matches!(fetch(&space, (3, 2)), space::FetchResult::Unreadable);
+        assert_matches!(fetch(&space, (4, 2)), space::FetchResult::Ok(v) if v == vec![0xc0, 0xc1]);
+    }
+
+    #[test]
+    fn nested() {
+        let inner = ConcatAddressSpace::new(vec![
+            (buffer_space("a", vec![0xa0, 0xa1]), 2),
+            (buffer_space("b", vec![0xb0, 0xb1]), 2),
+        ], "a + b");
+
+        let outer = ConcatAddressSpace::new(vec![
+            (sync::Arc::new(inner.into()), 4),
+            (buffer_space("c", vec![0xc0, 0xc1]), 2),
+        ], "(a + b) + c");
+
+        assert_matches!(fetch(&outer, (1, 4)), space::FetchResult::Ok(v) if v == vec![0xa1, 0xb0, 0xb1, 0xc0]);
+    }
+}
@@ -1,29 +1,94 @@
 use std::sync;
 use std::vec;
 use std::string;
-use std::io::Read;
-use std::io::Seek;
+use std::os::unix::fs::FileExt;
+use std::os::unix::fs::MetadataExt;
 
 use crate::model::space;
 #[cfg(feature = "gtk")]
 use crate::view::config;
 
+/// Enough about a file to tell whether it has been replaced or rewritten since we last looked at it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    dev: u64,
+    ino: u64,
+    len: u64,
+    mtime: (i64, i64),
+}
+
+impl Signature {
+    pub fn of(metadata: &std::fs::Metadata) -> Signature {
+        Signature {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            len: metadata.len(),
+            mtime: (metadata.mtime(), metadata.mtime_nsec()),
+        }
+    }
+
+    pub fn of_path(path: &std::path::Path) -> Result<Signature, std::io::Error> {
+        std::fs::metadata(path).map(|metadata| Signature::of(&metadata))
+    }
+}
+
 enum State {
-    Open(std::fs::File),
+    Open {
+        /* shared so that fetches can read from it on the blocking pool without holding the lock */
+        file: sync::Arc<std::fs::File>,
+        /* what we opened, which is what writes to the file have to line up with */
+        opened: Signature,
+        /* the last version that check_for_changes reported, so that each change only gets reported once */
+        seen: Signature,
+    },
     Closed,
     Error(std::io::Error),
 }
 
 pub struct FileAddressSpace {
     inner: sync::RwLock<State>,
+    generation: sync::atomic::AtomicU64,
     pub path: std::path::PathBuf,
     pub label: string::String,
 }
 
+fn open(path: &std::path::Path) -> Result<State, std::io::Error> {
+    let file = std::fs::File::open(path)?;
+    let signature = Signature::of(&file.metadata()?);
+
+    Ok(State::Open { file: sync::Arc::new(file), opened: signature, seen: signature })
+}
+
+/// Reads from the file with positional reads, which don't touch the file's cursor, so any number of these can run at
+/// the same time on the same handle.
+pub fn read_at(file: &std::fs::File, offset: u64, mut out: vec::Vec<u8>) -> space::FetchResult {
+    let mut filled = 0;
+
+    /* pread is allowed to come up short even when there's more file left, especially on network filesystems */
+    while filled < out.len() {
+        match file.read_at(&mut out[filled..], offset + filled as u64) {
+            Ok(0) => break,
+            Ok(i) => filled+= i,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return space::FetchResult::IoError(e),
+        }
+    }
+
+    match filled {
+        i if i == out.len() => space::FetchResult::Ok(out),
+        0 => space::FetchResult::Unreadable,
+        i => {
+            out.truncate(i);
+            space::FetchResult::Partial(out)
+        }
+    }
+}
+
 impl FileAddressSpace {
     pub fn new(path: std::path::PathBuf, label: &str) -> FileAddressSpace {
         FileAddressSpace {
             inner: sync::RwLock::<State>::new(State::Closed),
+            generation: sync::atomic::AtomicU64::new(0),
             path,
             label: label.to_string(),
         }
@@ -34,10 +99,10 @@ impl FileAddressSpace {
         let mut guard = self.inner.write().unwrap();
 
         match &*guard {
-            State::Open(_) => Ok(self),
-            State::Closed | State::Error(_) => match std::fs::File::open(&self.path) {
-                Ok(f) => { *guard = State::Open(f); Ok(self) },
-                Err(e) => Err(e),
+            State::Open { .. } => Ok(self),
+            State::Closed | State::Error(_) => {
+                *guard = open(&self.path)?;
+                Ok(self)
             },
         }
     }
@@ -47,33 +112,26 @@ impl FileAddressSpace {
         let mut guard = self.inner.write().unwrap();
 
         match &*guard {
-            State::Open(_) => {},
-            State::Closed | State::Error(_) => *guard = match std::fs::File::open(&self.path) {
-                Ok(f) => State::Open(f),
-                Err(e) => State::Error(e),
-            },
+            State::Open { .. } => {},
+            State::Closed | State::Error(_) => *guard = open(&self.path).unwrap_or_else(State::Error),
         }
     }
-    
-    fn read_sync(&self, offset: u64, mut out: vec::Vec<u8>) -> space::FetchResult {
-        let mut inner = self.inner.write().unwrap();
-
-        match &mut *inner {
-            State::Open(file) => file.seek(std::io::SeekFrom::Start(offset))
-                .and_then(|_| file.read(&mut out[..]))
-                .map(|r| {
-                    match r {
-                        i if i == out.len() => space::FetchResult::Ok(out),
-                        0 => space::FetchResult::Unreadable,
-                        i => {
-                            out.truncate(i);
-                            space::FetchResult::Partial(out)
-                        }
-                    }}).unwrap_or_else(space::FetchResult::IoError),
-            
-            _ => space::FetchResult::Unreadable,
+
+    /// The signature of the file as it was when we opened it, or None if it isn't open.
+    pub fn signature(&self) -> Option<Signature> {
+        match &*self.inner.read().unwrap() {
+            State::Open { opened, .. } => Some(*opened),
+            _ => None,
         }
-   }
+    }
+
+    /// Reopens the file and bumps the generation whether or not the file looks like it changed. This is for when we
+    /// changed it ourselves, since a rewrite that doesn't change the length can land within the mtime's granularity.
+    pub fn reload(&self) {
+        let mut guard = self.inner.write().unwrap();
+        *guard = open(&self.path).unwrap_or_else(State::Error);
+        self.generation.fetch_add(1, sync::atomic::Ordering::AcqRel);
+    }
 }
 
 #[cfg(feature = "gtk")]
@@ -94,6 +152,180 @@ impl space::AddressSpaceExt for FileAddressSpace {
     async fn fetch(&self, extent: (u64, u64)) -> space::FetchResult {
         tokio::time::sleep(tokio::time::Duration::from_millis(get_file_access_delay())).await;
 
-        self.read_sync(extent.0, vec![0; extent.1 as usize])
+        let file = match &*self.inner.read().unwrap() {
+            State::Open { file, .. } => file.clone(),
+            _ => return space::FetchResult::Unreadable,
+        };
+
+        /* a slow disk shouldn't hold up whoever is polling us */
+        match tokio::task::spawn_blocking(move || read_at(&file, extent.0, vec![0; extent.1 as usize])).await {
+            Ok(result) => result,
+            Err(e) => space::FetchResult::IoError(std::io::Error::other(e)),
+        }
+    }
+
+    fn size(&self) -> Option<u64> {
+        match &*self.inner.read().unwrap() {
+            State::Open { file, .. } => file.metadata(),
+            _ => std::fs::metadata(&self.path),
+        }.ok().map(|metadata| metadata.len())
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation.load(sync::atomic::Ordering::Acquire)
+    }
+
+    fn backing_files(&self) -> vec::Vec<&std::path::Path> {
+        vec![&self.path]
+    }
+
+    /// Checks whether the file at our path is still the one we opened. If it's been replaced or rewritten, remembers the
+    /// new version so we only report each change once, and if `reopen` is set, reopens the file and bumps the generation
+    /// so that caches throw away anything they read from the old one. Returns whether the file changed.
+    fn check_for_changes(&self, reopen: bool) -> bool {
+        /* if the file went away, keep reading from the handle we already have */
+        let Ok(current) = Signature::of_path(&self.path) else { return false };
+
+        let mut guard = self.inner.write().unwrap();
+
+        match &mut *guard {
+            State::Open { seen, .. } if *seen == current => false,
+            State::Open { seen, .. } => {
+                if reopen {
+                    *guard = open(&self.path).unwrap_or_else(State::Error);
+                    self.generation.fetch_add(1, sync::atomic::Ordering::AcqRel);
+                } else {
+                    *seen = current;
+                }
+                true
+            },
+            State::Closed | State::Error(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+    use std::task;
+
+    use assert_matches::assert_matches;
+
+    use crate::model::datapath;
+    use crate::model::datapath::DataPathExt;
+    use crate::model::space::AddressSpaceExt;
+
+    #[test]
+    fn size() {
+        let path = std::env::temp_dir().join(format!("charm-file-test-{}-size", std::process::id()));
+        std::fs::File::create(&path).unwrap().write_all(&[0; 0x123]).unwrap();
+
+        let space = FileAddressSpace::new(path.clone(), "test");
+        assert_eq!(space.size(), Some(0x123));
+
+        space.try_open().unwrap();
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(&[0; 0x10]).unwrap();
+        assert_eq!(space.size(), Some(0x133));
+        assert_eq!(space.extents(), Some((0, 0x133)));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn replaced_file() {
+        let path = std::env::temp_dir().join(format!("charm-file-test-{}-replaced", std::process::id()));
+        let new_path = path.with_extension("new");
+        std::fs::File::create(&path).unwrap().write_all(&[0xaa; 0x20]).unwrap();
+
+        let space = sync::Arc::new(space::AddressSpace::from(FileAddressSpace::new(path.clone(), "test")));
+        let space::AddressSpace::File(fas) = &*space else { unreachable!() };
+        fas.try_open().unwrap();
+
+        assert_eq!(space.backing_files(), vec![path.as_path()]);
+        assert!(!space.check_for_changes(true));
+
+        let datapath: datapath::DataPath = imbl::vector![
+            datapath::test_load_space(space.clone(), 0, 0, Some(0x20)),
+        ].into();
+
+        let fetch = || {
+            let rt = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+            let _guard = rt.enter();
+            let mut records = vec![datapath::ByteRecord::default(); 0x20];
+
+            loop {
+                let mut cx = task::Context::from_waker(futures::task::noop_waker_ref());
+                /* reads happen on the blocking pool, so they need polling to finish */
+                datapath.poll(&mut cx);
+                datapath.fetch(datapath::ByteRecordRange::new(0, &mut records), &mut cx);
+
+                if !records.iter().any(|br| br.pending) {
+                    return records.iter().map(|br| br.value).collect::<vec::Vec<u8>>();
+                }
+
+                rt.block_on(tokio::time::sleep(std::time::Duration::from_millis(1)));
+            }
+        };
+
+        assert_eq!(fetch(), vec![0xaa; 0x20]);
+
+        /* the way a linker or an editor would: write a new file and move it over the old one */
+        std::fs::File::create(&new_path).unwrap().write_all(&[0xbb; 0x20]).unwrap();
+        std::fs::rename(&new_path, &path).unwrap();
+
+        /* the old handle and the cache both still have the old contents until we notice */
+        assert_eq!(fetch(), vec![0xaa; 0x20]);
+        assert!(space.check_for_changes(true));
+        assert!(!space.check_for_changes(true));
+        assert_eq!(space.generation(), 1);
+        assert_eq!(fetch(), vec![0xbb; 0x20]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn locked_snapshot() {
+        let path = std::env::temp_dir().join(format!("charm-file-test-{}-locked", std::process::id()));
+        let new_path = path.with_extension("new");
+        std::fs::File::create(&path).unwrap().write_all(&[0xaa; 0x20]).unwrap();
+
+        let space = FileAddressSpace::new(path.clone(), "test");
+        space.try_open().unwrap();
+
+        std::fs::File::create(&new_path).unwrap().write_all(&[0xbb; 0x10]).unwrap();
+        std::fs::rename(&new_path, &path).unwrap();
+
+        /* changes are only reported once, and we keep reading the file we opened */
+        assert!(space.check_for_changes(false));
+        assert!(!space.check_for_changes(false));
+        assert_eq!(space.generation(), 0);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        assert_matches!(rt.block_on(space.fetch((0x18, 4))), space::FetchResult::Ok(v) if v == vec![0xaa; 4]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn concurrent_fetches() {
+        let path = std::env::temp_dir().join(format!("charm-file-test-{}-concurrent", std::process::id()));
+        std::fs::File::create(&path).unwrap().write_all(&(0..=0xff).collect::<vec::Vec<u8>>()).unwrap();
+
+        let space = FileAddressSpace::new(path.clone(), "test");
+        space.try_open().unwrap();
+
+        /* if these shared a file cursor, they would read each other's bytes */
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let results = rt.block_on(futures::future::join_all((0..0x10).map(|i| space.fetch((i * 0x10, 0x10)))));
+
+        for (i, result) in results.into_iter().enumerate() {
+            assert_matches!(result, space::FetchResult::Ok(v) if v == (i * 0x10..(i + 1) * 0x10).map(|b| b as u8).collect::<vec::Vec<u8>>());
+        }
+
+        assert_matches!(rt.block_on(space.fetch((0xf8, 0x10))), space::FetchResult::Partial(v) if v == (0xf8..=0xff).collect::<vec::Vec<u8>>());
+        assert_matches!(rt.block_on(space.fetch((0x100, 0x10))), space::FetchResult::Unreadable);
+
+        std::fs::remove_file(path).unwrap();
     }
 }
@@ -0,0 +1,553 @@
+use std::string;
+use std::vec;
+
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+
+use crate::model::space;
+
+/// Largest number of bytes we ask for in a single `m` packet. gdbserver and OpenOCD both advertise packet sizes well
+/// above twice this, so we don't bother negotiating it with qSupported.
+const MAX_READ_SIZE: u64 = 0x800;
+
+/// How long we wait on the target before giving up on a packet.
+const TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(5);
+
+/// Where to find the remote stub.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// "host:port"
+    Tcp(string::String),
+    Unix(std::path::PathBuf),
+}
+
+trait Stream: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + Sync {}
+impl<T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + Sync> Stream for T {}
+
+enum StdStream {
+    Tcp(std::net::TcpStream),
+    Unix(std::os::unix::net::UnixStream),
+}
+
+struct Connection {
+    stream: tokio::io::BufReader<Box<dyn Stream>>,
+}
+
+enum State {
+    /// Connected, but we haven't been polled from inside a tokio runtime yet so we can't register the socket with it.
+    Connected(StdStream),
+    Open(Connection),
+    /// Connects on the first fetch, on the blocking pool.
+    Deferred,
+    Closed,
+    Error(std::io::Error),
+}
+
+/// Reads memory from a debug target over the GDB remote serial protocol (gdbserver, OpenOCD, QEMU's gdbstub, ...).
+pub struct GdbAddressSpace {
+    inner: tokio::sync::Mutex<State>,
+    pub target: Target,
+    pub label: string::String,
+}
+
+#[derive(Debug)]
+enum ReadError {
+    /// The target replied with an error or an empty (unsupported) packet.
+    Refused,
+    Io(std::io::Error),
+}
+
+impl From<std::io::Error> for ReadError {
+    fn from(e: std::io::Error) -> ReadError {
+        ReadError::Io(e)
+    }
+}
+
+fn protocol_error(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |acc, b| acc.wrapping_add(*b))
+}
+
+fn parse_hex_nybble(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|d| d as u8)
+}
+
+fn parse_hex_bytes(data: &[u8]) -> Option<vec::Vec<u8>> {
+    if !data.len().is_multiple_of(2) {
+        return None;
+    }
+
+    data.chunks(2).map(|pair| Some(parse_hex_nybble(pair[0])? << 4 | parse_hex_nybble(pair[1])?)).collect()
+}
+
+/// Expands run-length encoding ("X*n" repeats X another n-29 times).
+fn decode_rle(data: &[u8]) -> Result<vec::Vec<u8>, std::io::Error> {
+    let mut out = vec::Vec::with_capacity(data.len());
+    let mut iter = data.iter();
+
+    while let Some(&b) = iter.next() {
+        if b == b'*' {
+            let (Some(&prev), Some(&count)) = (out.last(), iter.next()) else {
+                return Err(protocol_error("malformed run-length encoding"));
+            };
+
+            if count < 29 {
+                return Err(protocol_error("malformed run-length encoding"));
+            }
+
+            out.extend(std::iter::repeat_n(prev, (count - 29) as usize));
+        } else {
+            out.push(b);
+        }
+    }
+
+    Ok(out)
+}
+
+impl Target {
+    /// Anything that looks like a path is treated as a Unix socket. Everything else should be "host:port".
+    pub fn parse(s: &str) -> Target {
+        if s.contains('/') {
+            Target::Unix(std::path::PathBuf::from(s))
+        } else {
+            Target::Tcp(s.to_string())
+        }
+    }
+
+    fn connect(&self) -> Result<StdStream, std::io::Error> {
+        match self {
+            Target::Tcp(addr) => {
+                /* A board that isn't there shouldn't leave us waiting for the OS to give up on it, which takes minutes. */
+                let mut last_error = None;
+
+                for addr in std::net::ToSocketAddrs::to_socket_addrs(addr.as_str())? {
+                    match std::net::TcpStream::connect_timeout(&addr, TIMEOUT) {
+                        Ok(stream) => {
+                            stream.set_nodelay(true)?;
+                            return Ok(StdStream::Tcp(stream));
+                        },
+                        Err(e) => last_error = Some(e),
+                    }
+                }
+
+                Err(last_error.unwrap_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "host has no addresses")))
+            },
+            Target::Unix(path) => Ok(StdStream::Unix(std::os::unix::net::UnixStream::connect(path)?)),
+        }
+    }
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Tcp(addr) => write!(f, "{}", addr),
+            Target::Unix(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl StdStream {
+    /// Needs to be called from inside a tokio runtime.
+    fn into_connection(self) -> Result<Connection, std::io::Error> {
+        let stream: Box<dyn Stream> = match self {
+            StdStream::Tcp(s) => {
+                s.set_nonblocking(true)?;
+                Box::new(tokio::net::TcpStream::from_std(s)?)
+            },
+            StdStream::Unix(s) => {
+                s.set_nonblocking(true)?;
+                Box::new(tokio::net::UnixStream::from_std(s)?)
+            },
+        };
+
+        Ok(Connection {
+            stream: tokio::io::BufReader::new(stream),
+        })
+    }
+}
+
+impl Connection {
+    async fn send_packet(&mut self, data: &[u8]) -> Result<(), std::io::Error> {
+        let mut packet = vec::Vec::with_capacity(data.len() + 4);
+        packet.push(b'$');
+        packet.extend_from_slice(data);
+        packet.extend_from_slice(format!("#{:02x}", checksum(data)).as_bytes());
+
+        /* The stub acks with '+', or asks for a retransmission with '-'. */
+        for _ in 0..3 {
+            self.stream.write_all(&packet).await?;
+            self.stream.flush().await?;
+
+            match self.stream.read_u8().await? {
+                b'+' => return Ok(()),
+                b'-' => continue,
+                _ => return Err(protocol_error("expected acknowledgement")),
+            }
+        }
+
+        Err(protocol_error("target kept rejecting our packet"))
+    }
+
+    async fn receive_packet(&mut self) -> Result<vec::Vec<u8>, std::io::Error> {
+        for _ in 0..3 {
+            /* skip over anything that isn't the start of a packet, like stray acks */
+            let mut garbage = vec::Vec::new();
+            self.stream.read_until(b'$', &mut garbage).await?;
+            if garbage.last() != Some(&b'$') {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+
+            let mut data = vec::Vec::new();
+            self.stream.read_until(b'#', &mut data).await?;
+            if data.pop() != Some(b'#') {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+
+            let mut sum = [0u8; 2];
+            self.stream.read_exact(&mut sum).await?;
+
+            if parse_hex_bytes(&sum) == Some(vec![checksum(&data)]) {
+                self.stream.write_all(b"+").await?;
+                self.stream.flush().await?;
+                return decode_rle(&data);
+            } else {
+                self.stream.write_all(b"-").await?;
+                self.stream.flush().await?;
+            }
+        }
+
+        Err(protocol_error("target kept sending corrupted packets"))
+    }
+
+    async fn read_memory(&mut self, addr: u64, length: u64) -> Result<vec::Vec<u8>, ReadError> {
+        self.send_packet(format!("m{:x},{:x}", addr, length).as_bytes()).await?;
+        let reply = self.receive_packet().await?;
+
+        if reply.is_empty() || (reply[0] == b'E' && reply.len() == 3) {
+            return Err(ReadError::Refused);
+        }
+
+        parse_hex_bytes(&reply).ok_or_else(|| ReadError::Io(protocol_error("malformed memory read reply")))
+    }
+}
+
+impl GdbAddressSpace {
+    pub fn new(target: Target, label: &str) -> GdbAddressSpace {
+        GdbAddressSpace {
+            inner: tokio::sync::Mutex::new(State::Closed),
+            target,
+            label: label.to_string(),
+        }
+    }
+
+    /// In case of error, the error is returned instead of being stored in this object as an error state.
+    pub fn try_open(&self) -> Result<&Self, std::io::Error> {
+        /* if someone is holding the lock, they're in the middle of a fetch, so we're already open */
+        let Ok(mut guard) = self.inner.try_lock() else { return Ok(self) };
+
+        match &*guard {
+            State::Connected(_) | State::Open(_) => Ok(self),
+            State::Deferred | State::Closed | State::Error(_) => {
+                *guard = State::Connected(self.target.connect()?);
+                Ok(self)
+            },
+        }
+    }
+
+    /// In case of error, the error is stored in this object as an error state instead of being returned.
+    pub fn open(&self) {
+        let Ok(mut guard) = self.inner.try_lock() else { return };
+
+        match &*guard {
+            State::Connected(_) | State::Open(_) => {},
+            State::Deferred | State::Closed | State::Error(_) => *guard = match self.target.connect() {
+                Ok(s) => State::Connected(s),
+                Err(e) => State::Error(e),
+            },
+        }
+    }
+
+    /// Waits until the first fetch to connect. Resolving the target's address and connecting to it can take several
+    /// seconds when it isn't there, which is too long to do on the main thread.
+    pub fn open_deferred(&self) {
+        let Ok(mut guard) = self.inner.try_lock() else { return };
+
+        if matches!(&*guard, State::Closed | State::Error(_)) {
+            *guard = State::Deferred;
+        }
+    }
+
+    async fn read(&self, offset: u64, length: u64) -> space::FetchResult {
+        let mut guard = self.inner.lock().await;
+
+        if let State::Deferred = &*guard {
+            let target = self.target.clone();
+
+            match tokio::task::spawn_blocking(move || target.connect()).await {
+                Ok(Ok(s)) => *guard = State::Connected(s),
+                Ok(Err(e)) => {
+                    *guard = State::Closed;
+                    return space::FetchResult::IoError(e);
+                },
+                Err(e) => {
+                    *guard = State::Closed;
+                    return space::FetchResult::IoError(std::io::Error::other(e));
+                },
+            }
+        }
+
+        take_mut::take(&mut *guard, |state| match state {
+            State::Connected(s) => match s.into_connection() {
+                Ok(c) => State::Open(c),
+                Err(e) => State::Error(e),
+            },
+            state => state,
+        });
+
+        let State::Open(connection) = &mut *guard else { return space::FetchResult::Unreadable };
+
+        let mut out = vec::Vec::with_capacity(length as usize);
+
+        while (out.len() as u64) < length {
+            let addr = offset.wrapping_add(out.len() as u64);
+            let chunk = std::cmp::min(length - out.len() as u64, MAX_READ_SIZE);
+
+            match tokio::time::timeout(TIMEOUT, connection.read_memory(addr, chunk)).await {
+                Ok(Ok(bytes)) if bytes.is_empty() => break,
+                Ok(Ok(bytes)) => {
+                    let short = (bytes.len() as u64) < chunk;
+                    out.extend(bytes.into_iter().take(chunk as usize));
+                    if short {
+                        break;
+                    }
+                },
+                Ok(Err(ReadError::Refused)) => break,
+                Ok(Err(ReadError::Io(e))) => {
+                    /* the connection is probably in a weird state now, so don't try to reuse it */
+                    *guard = State::Closed;
+                    return space::FetchResult::IoError(e);
+                },
+                Err(_) => {
+                    *guard = State::Closed;
+                    return space::FetchResult::IoError(std::io::ErrorKind::TimedOut.into());
+                },
+            }
+        }
+
+        match out.len() {
+            0 => space::FetchResult::Unreadable,
+            n if n as u64 == length => space::FetchResult::Ok(out),
+            _ => space::FetchResult::Partial(out),
+        }
+    }
+}
+
+impl space::AddressSpaceExt for GdbAddressSpace {
+    fn get_label(&self) -> &str {
+        &self.label
+    }
+
+    async fn fetch(&self, extent: (u64, u64)) -> space::FetchResult {
+        self.read(extent.0, extent.1).await
+    }
+
+    /// The stub doesn't tell us anything about what memory the target has.
+    fn size(&self) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync;
+    use std::task;
+
+    use assert_matches::assert_matches;
+
+    use crate::model::datapath;
+    use crate::model::datapath::DataPathExt;
+
+    const MEMORY_BASE: u64 = 0x1000;
+
+    /// A very small gdbserver that only knows how to read from a 0x1000 byte block of memory at 0x1000.
+    async fn serve_stub<S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin>(stream: S) {
+        let memory: vec::Vec<u8> = (0..0x1000).map(|i| (i * 7) as u8).collect();
+        let mut stream = tokio::io::BufReader::new(stream);
+
+        loop {
+            let mut garbage = vec::Vec::new();
+            if stream.read_until(b'$', &mut garbage).await.unwrap() == 0 {
+                return;
+            }
+
+            let mut data = vec::Vec::new();
+            stream.read_until(b'#', &mut data).await.unwrap();
+            data.pop();
+            let mut sum = [0u8; 2];
+            stream.read_exact(&mut sum).await.unwrap();
+            assert_eq!(parse_hex_bytes(&sum), Some(vec![checksum(&data)]));
+            stream.write_all(b"+").await.unwrap();
+
+            let request = string::String::from_utf8(data).unwrap();
+            let reply = match request.strip_prefix('m').and_then(|args| args.split_once(',')) {
+                Some((addr, len)) => {
+                    let addr = u64::from_str_radix(addr, 16).unwrap();
+                    let len = u64::from_str_radix(len, 16).unwrap();
+
+                    if addr < MEMORY_BASE || addr >= MEMORY_BASE + memory.len() as u64 {
+                        "E01".to_string()
+                    } else {
+                        let begin = (addr - MEMORY_BASE) as usize;
+                        let end = std::cmp::min(begin + len as usize, memory.len());
+                        memory[begin..end].iter().map(|b| format!("{:02x}", b)).collect()
+                    }
+                },
+                None => string::String::new(),
+            };
+
+            stream.write_all(format!("${}#{:02x}", reply, checksum(reply.as_bytes())).as_bytes()).await.unwrap();
+            stream.flush().await.unwrap();
+
+            let mut ack = [0u8; 1];
+            stream.read_exact(&mut ack).await.unwrap();
+            assert_eq!(&ack, b"+");
+        }
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    fn expected(addr: u64, len: u64) -> vec::Vec<u8> {
+        (addr..addr+len).map(|a| ((a - MEMORY_BASE) * 7) as u8).collect()
+    }
+
+    fn start_tcp_stub(rt: &tokio::runtime::Runtime) -> Target {
+        let listener = rt.block_on(tokio::net::TcpListener::bind("127.0.0.1:0")).unwrap();
+        let target = Target::Tcp(listener.local_addr().unwrap().to_string());
+
+        rt.spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            serve_stub(stream).await;
+        });
+
+        target
+    }
+
+    #[test]
+    fn parse_target() {
+        assert_eq!(Target::parse("localhost:3333"), Target::Tcp("localhost:3333".to_string()));
+        assert_eq!(Target::parse("/tmp/gdb.sock"), Target::Unix(std::path::PathBuf::from("/tmp/gdb.sock")));
+    }
+
+    #[test]
+    fn rle() {
+        assert_eq!(decode_rle(b"0* ").unwrap(), b"0000");
+        assert_eq!(decode_rle(b"ab*\"c").unwrap(), b"abbbbbbc");
+        assert!(decode_rle(b"*!").is_err());
+    }
+
+    #[test]
+    fn fetch_over_tcp() {
+        let rt = runtime();
+        let space = GdbAddressSpace::new(start_tcp_stub(&rt), "stub");
+        space.try_open().unwrap();
+
+        assert_matches!(rt.block_on(space.read(0x1010, 4)), space::FetchResult::Ok(v) if v == expected(0x1010, 4));
+
+        /* bigger than a single packet */
+        assert_matches!(rt.block_on(space.read(0x1000, 0x1000)), space::FetchResult::Ok(v) if v == expected(0x1000, 0x1000));
+
+        /* error replies */
+        assert_matches!(rt.block_on(space.read(0x0, 0x10)), space::FetchResult::Unreadable);
+        assert_matches!(rt.block_on(space.read(0x1ff0, 0x20)), space::FetchResult::Partial(v) if v == expected(0x1ff0, 0x10));
+        assert_matches!(rt.block_on(space.read(0x17f0, 0x1000)), space::FetchResult::Partial(v) if v == expected(0x17f0, 0x810));
+
+        /* connection should still be usable after errors */
+        assert_matches!(rt.block_on(space.read(0x1020, 2)), space::FetchResult::Ok(v) if v == expected(0x1020, 2));
+    }
+
+    #[test]
+    fn open_deferred() {
+        let rt = runtime();
+        let space = GdbAddressSpace::new(start_tcp_stub(&rt), "stub");
+        space.open_deferred();
+
+        assert_matches!(rt.block_on(space.read(0x1010, 4)), space::FetchResult::Ok(v) if v == expected(0x1010, 4));
+
+        /* nothing listening */
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let space = GdbAddressSpace::new(Target::Tcp(listener.local_addr().unwrap().to_string()), "nothing");
+        drop(listener);
+        space.open_deferred();
+
+        assert_matches!(rt.block_on(space.read(0x1010, 4)), space::FetchResult::IoError(_));
+        assert_matches!(rt.block_on(space.read(0x1010, 4)), space::FetchResult::Unreadable);
+    }
+
+    #[test]
+    fn fetch_over_unix_socket() {
+        let rt = runtime();
+        let path = std::env::temp_dir().join(format!("charm-gdb-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let listener = {
+            let _guard = rt.enter();
+            tokio::net::UnixListener::bind(&path).unwrap()
+        };
+
+        rt.spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            serve_stub(stream).await;
+        });
+
+        let space = GdbAddressSpace::new(Target::Unix(path.clone()), "stub");
+        space.try_open().unwrap();
+
+        assert_matches!(rt.block_on(space.read(0x1100, 0x10)), space::FetchResult::Ok(v) if v == expected(0x1100, 0x10));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn through_space_cache() {
+        let rt = runtime();
+        let space = GdbAddressSpace::new(start_tcp_stub(&rt), "stub");
+        space.try_open().unwrap();
+
+        let datapath: datapath::DataPath = imbl::vector![
+            datapath::LoadSpaceFilter::new_complete(sync::Arc::new(space.into()), 0, 0, None, 0x100, std::num::NonZeroUsize::new(16).unwrap()).to_filter(),
+        ].into();
+
+        /* the cache needs to poll the futures from inside the runtime, like the listing widget does */
+        let _guard = rt.enter();
+        let waker = futures::task::noop_waker();
+        let mut cx = task::Context::from_waker(&waker);
+
+        let mut records = vec![datapath::ByteRecord::default(); 0x20];
+        datapath.fetch(datapath::ByteRecordRange::new(0xff0, &mut records), &mut cx);
+
+        let start = std::time::Instant::now();
+        while datapath.poll(&mut cx) {
+            assert!(start.elapsed() < TIMEOUT, "fetch never finished");
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        records.fill(datapath::ByteRecord::default());
+        datapath.fetch(datapath::ByteRecordRange::new(0xff0, &mut records), &mut cx);
+
+        assert!(records[..0x10].iter().all(|br| br.loaded && br.error.is_some()));
+        assert!(records[0x10..].iter().all(|br| br.loaded && br.error.is_none() && !br.pending));
+        assert_eq!(records[0x10..].iter().map(|br| br.value).collect::<vec::Vec<u8>>(), expected(0x1000, 0x10));
+    }
+}
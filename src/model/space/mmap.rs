@@ -0,0 +1,287 @@
+use std::sync;
+use std::vec;
+use std::string;
+
+use crate::model::space;
+use crate::model::space::file;
+#[cfg(feature = "gtk")]
+use crate::view::config;
+
+enum State {
+    Open {
+        file: std::fs::File,
+        map: memmap2::Mmap,
+        signature: file::Signature,
+    },
+    Closed,
+    Error(std::io::Error),
+}
+
+/// Like [space::file::FileAddressSpace], but reads out of a read-only memory map of the file instead of seeking and
+/// reading. Fetches only need to take the lock for reading, so they don't serialize behind each other.
+pub struct MmapAddressSpace {
+    inner: sync::RwLock<State>,
+    generation: sync::atomic::AtomicU64,
+    pub path: std::path::PathBuf,
+    pub label: string::String,
+}
+
+fn map(path: &std::path::Path) -> Result<State, std::io::Error> {
+    let file = std::fs::File::open(path)?;
+    let signature = file::Signature::of(&file.metadata()?);
+
+    /* Safety: the mapping is read-only, and we never hand out references into it. We can't stop other processes from
+     * modifying or truncating the file underneath us, so we never touch the mapping directly. See copy_out. */
+    let map = unsafe { memmap2::Mmap::map(&file)? };
+
+    Ok(State::Open { file, map, signature })
+}
+
+/// Most iovecs that process_vm_readv takes at once.
+const MAX_IOVECS: usize = 1024;
+
+/// Copies bytes out of the mapping without touching it ourselves. If the file gets truncated after we check its length,
+/// touching pages past the new end would raise SIGBUS and kill us, but when the kernel does the reading for us it stops
+/// at those pages and tells us how far it got.
+fn copy_out(bytes: &[u8]) -> Result<vec::Vec<u8>, std::io::Error> {
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let mut out = vec::Vec::<u8>::with_capacity(bytes.len());
+
+    while out.len() < bytes.len() {
+        let rest = &bytes[out.len()..];
+
+        /* one iovec per page, since the kernel only promises to stop partway through at iovec boundaries */
+        let mut remote = vec::Vec::new();
+        let mut split = 0;
+        while split < rest.len() && remote.len() < MAX_IOVECS {
+            let page_end = std::cmp::min(rest.len(), split + page_size - (rest.as_ptr() as usize + split) % page_size);
+            remote.push(libc::iovec { iov_base: rest[split..].as_ptr() as *mut libc::c_void, iov_len: page_end - split });
+            split = page_end;
+        }
+
+        let local = libc::iovec { iov_base: out.spare_capacity_mut().as_mut_ptr() as *mut libc::c_void, iov_len: split };
+
+        /* Safety: local is spare capacity that's at least as long as everything in remote, and the kernel checks remote
+         * for us. */
+        match unsafe { libc::process_vm_readv(libc::getpid(), &local, 1, remote.as_ptr(), remote.len() as libc::c_ulong, 0) } {
+            -1 => match std::io::Error::last_os_error() {
+                e if e.raw_os_error() == Some(libc::EINTR) => continue,
+                e if e.raw_os_error() == Some(libc::EFAULT) => break,
+                e => return Err(e),
+            },
+            copied => {
+                /* Safety: the kernel filled in this many bytes */
+                unsafe { out.set_len(out.len() + copied as usize) };
+
+                if (copied as usize) < split {
+                    break;
+                }
+            },
+        }
+    }
+
+    Ok(out)
+}
+
+impl MmapAddressSpace {
+    pub fn new(path: std::path::PathBuf, label: &str) -> MmapAddressSpace {
+        MmapAddressSpace {
+            inner: sync::RwLock::<State>::new(State::Closed),
+            generation: sync::atomic::AtomicU64::new(0),
+            path,
+            label: label.to_string(),
+        }
+    }
+
+    /// In case of error, the error is returned instead of being stored in this object as an error state.
+    pub fn try_open(&self) -> Result<&Self, std::io::Error> {
+        let mut guard = self.inner.write().unwrap();
+
+        match &*guard {
+            State::Open { .. } => Ok(self),
+            State::Closed | State::Error(_) => {
+                *guard = map(&self.path)?;
+                Ok(self)
+            },
+        }
+    }
+
+    /// In case of error, the error is stored in this object as an error state instead of being returned.
+    pub fn open(&self) {
+        let mut guard = self.inner.write().unwrap();
+
+        match &*guard {
+            State::Open { .. } => {},
+            State::Closed | State::Error(_) => *guard = map(&self.path).unwrap_or_else(State::Error),
+        }
+    }
+
+    fn read_sync(&self, offset: u64, length: u64) -> space::FetchResult {
+        let inner = self.inner.read().unwrap();
+
+        match &*inner {
+            State::Open { file, map, .. } => {
+                /* If the file got truncated since we mapped it, the pages past the new end are no longer backed. */
+                let file_length = match file.metadata() {
+                    Ok(metadata) => metadata.len(),
+                    Err(e) => return space::FetchResult::IoError(e),
+                };
+
+                let valid_length = std::cmp::min(map.len() as u64, file_length);
+
+                if offset >= valid_length {
+                    return space::FetchResult::Unreadable;
+                }
+
+                let end = std::cmp::min(offset.saturating_add(length), valid_length);
+
+                /* the file can still get truncated between checking its length and copying */
+                let out = match copy_out(&map[offset as usize..end as usize]) {
+                    Ok(out) => out,
+                    /* probably a sandbox that doesn't allow process_vm_readv, so read it the slow way */
+                    Err(_) => return match file::read_at(file, offset, vec![0; (end - offset) as usize]) {
+                        space::FetchResult::Ok(out) if (out.len() as u64) < length => space::FetchResult::Partial(out),
+                        result => result,
+                    },
+                };
+
+                match out.len() {
+                    0 => space::FetchResult::Unreadable,
+                    n if n as u64 == length => space::FetchResult::Ok(out),
+                    _ => space::FetchResult::Partial(out),
+                }
+            },
+
+            _ => space::FetchResult::Unreadable,
+        }
+    }
+}
+
+#[cfg(feature = "gtk")]
+fn get_file_access_delay() -> u64 {
+    config::INSTANCE.borrow().file_access_delay
+}
+
+#[cfg(not(feature = "gtk"))]
+fn get_file_access_delay() -> u64 {
+    0
+}
+
+impl space::AddressSpaceExt for MmapAddressSpace {
+    fn get_label(&self) -> &str {
+        &self.label
+    }
+
+    async fn fetch(&self, extent: (u64, u64)) -> space::FetchResult {
+        tokio::time::sleep(tokio::time::Duration::from_millis(get_file_access_delay())).await;
+
+        self.read_sync(extent.0, extent.1)
+    }
+
+    fn size(&self) -> Option<u64> {
+        match &*self.inner.read().unwrap() {
+            State::Open { file, map, .. } => file.metadata().ok().map(|metadata| std::cmp::min(metadata.len(), map.len() as u64)),
+            _ => std::fs::metadata(&self.path).ok().map(|metadata| metadata.len()),
+        }
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation.load(sync::atomic::Ordering::Acquire)
+    }
+
+    fn backing_files(&self) -> vec::Vec<&std::path::Path> {
+        vec![&self.path]
+    }
+
+    /// Same as [file::FileAddressSpace], except that reopening also means mapping the new file. Note that the old
+    /// mapping doesn't stay frozen if the file gets rewritten in place instead of replaced, so locking the snapshot
+    /// can't stop pages we haven't cached yet from showing the new contents.
+    fn check_for_changes(&self, reopen: bool) -> bool {
+        let Ok(current) = file::Signature::of_path(&self.path) else { return false };
+
+        let mut guard = self.inner.write().unwrap();
+
+        match &mut *guard {
+            State::Open { signature, .. } if *signature == current => false,
+            State::Open { signature, .. } => {
+                if reopen {
+                    *guard = map(&self.path).unwrap_or_else(State::Error);
+                    self.generation.fetch_add(1, sync::atomic::Ordering::AcqRel);
+                } else {
+                    *signature = current;
+                }
+                true
+            },
+            State::Closed | State::Error(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use assert_matches::assert_matches;
+
+    fn create_test_file(name: &str, contents: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("charm-mmap-test-{}-{}", std::process::id(), name));
+        std::fs::File::create(&path).unwrap().write_all(contents).unwrap();
+        path
+    }
+
+    fn fetch(space: &MmapAddressSpace, extent: (u64, u64)) -> space::FetchResult {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(space::AddressSpaceExt::fetch(space, extent))
+    }
+
+    #[test]
+    fn fetch_within_and_past_end() {
+        let contents: vec::Vec<u8> = (0..=255).collect();
+        let path = create_test_file("fetch", &contents);
+        let space = MmapAddressSpace::new(path.clone(), "test");
+
+        assert_matches!(fetch(&space, (0, 4)), space::FetchResult::Unreadable);
+
+        space.try_open().unwrap();
+
+        assert_matches!(fetch(&space, (0x10, 4)), space::FetchResult::Ok(v) if v == vec![0x10, 0x11, 0x12, 0x13]);
+        assert_matches!(fetch(&space, (0xfe, 4)), space::FetchResult::Partial(v) if v == vec![0xfe, 0xff]);
+        assert_matches!(fetch(&space, (0x100, 4)), space::FetchResult::Unreadable);
+        assert_matches!(fetch(&space, (u64::MAX, 4)), space::FetchResult::Unreadable);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn truncated_after_mapping() {
+        /* span a few pages so that the truncation actually leaves some of the mapping unbacked */
+        let contents: vec::Vec<u8> = (0..0x3000).map(|i| i as u8).collect();
+        let path = create_test_file("truncate", &contents);
+        let space = MmapAddressSpace::new(path.clone(), "test");
+        space.try_open().unwrap();
+
+        assert_eq!(space::AddressSpaceExt::size(&space), Some(0x3000));
+        std::fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(0x1080).unwrap();
+        assert_eq!(space::AddressSpaceExt::size(&space), Some(0x1080));
+
+        /* these would raise SIGBUS if we touched the mapping past the new end of the file */
+        assert_matches!(fetch(&space, (0x107e, 4)), space::FetchResult::Partial(v) if v == vec![0x7e, 0x7f]);
+        assert_matches!(fetch(&space, (0x2000, 4)), space::FetchResult::Unreadable);
+        assert_matches!(fetch(&space, (0x10, 2)), space::FetchResult::Ok(v) if v == vec![0x10, 0x11]);
+
+        /* as if it got truncated between checking the length and copying */
+        let State::Open { map, .. } = &*space.inner.read().unwrap() else { unreachable!() };
+        let copied = copy_out(&map[0x1000..0x3000]).unwrap();
+        /* the rest of the page that the new end falls in reads as zeroes, but the pages after it are gone */
+        assert!(copied.len() < 0x2000);
+        assert_eq!(copied[..0x80], contents[0x1000..0x1080]);
+        assert_eq!(copy_out(&map[0x2000..0x2004]).unwrap(), vec![]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
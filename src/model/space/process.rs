@@ -0,0 +1,325 @@
+use std::sync;
+use std::vec;
+use std::string;
+use std::os::unix::fs::FileExt;
+
+use crate::model::space;
+
+/// One line of `/proc/<pid>/maps`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    pub start: u64,
+    pub end: u64,
+    pub readable: bool,
+    pub writable: bool,
+    pub executable: bool,
+    pub shared: bool,
+    pub offset: u64,
+    pub pathname: Option<string::String>,
+}
+
+/// A process that we could attach to, as listed in `/proc`.
+#[derive(Debug, Clone)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub name: string::String,
+}
+
+enum State {
+    Open {
+        mem: std::fs::File,
+        maps: vec::Vec<Mapping>,
+    },
+    Closed,
+    Error(std::io::Error),
+}
+
+/// Reads the memory of a live process through `/proc/<pid>/mem`. `/proc/<pid>/maps` decides which addresses are
+/// readable, and anything outside of a readable mapping is reported as unreadable instead of as an I/O error.
+pub struct ProcessAddressSpace {
+    inner: sync::RwLock<State>,
+    pub pid: u32,
+    pub label: string::String,
+}
+
+impl Mapping {
+    /// Formats the permissions the same way `/proc/<pid>/maps` does (e.g. "r-xp").
+    pub fn permissions(&self) -> string::String {
+        [
+            if self.readable { 'r' } else { '-' },
+            if self.writable { 'w' } else { '-' },
+            if self.executable { 'x' } else { '-' },
+            if self.shared { 's' } else { 'p' },
+        ].iter().collect()
+    }
+
+    fn parse(line: &str) -> Option<Mapping> {
+        let mut fields = line.split_ascii_whitespace();
+
+        let (start, end) = fields.next()?.split_once('-')?;
+        let perms = fields.next()?.as_bytes();
+        let offset = fields.next()?;
+        let _dev = fields.next()?;
+        let _inode = fields.next()?;
+
+        if perms.len() != 4 {
+            return None;
+        }
+
+        /* pathnames can contain spaces, so take the rest of the line instead of the next field */
+        let pathname = fields.next().map(|first| {
+            let index = line.find(first).unwrap();
+            line[index..].trim_end().to_string()
+        });
+
+        Some(Mapping {
+            start: u64::from_str_radix(start, 16).ok()?,
+            end: u64::from_str_radix(end, 16).ok()?,
+            readable: perms[0] == b'r',
+            writable: perms[1] == b'w',
+            executable: perms[2] == b'x',
+            shared: perms[3] == b's',
+            offset: u64::from_str_radix(offset, 16).ok()?,
+            pathname,
+        })
+    }
+}
+
+/// Parses the contents of `/proc/<pid>/maps`, skipping any lines we don't understand.
+pub fn parse_maps(text: &str) -> vec::Vec<Mapping> {
+    text.lines().filter_map(Mapping::parse).collect()
+}
+
+/// Lists the processes visible to us in `/proc`, sorted by pid.
+pub fn list_processes() -> Result<vec::Vec<ProcessInfo>, std::io::Error> {
+    let mut processes: vec::Vec<ProcessInfo> = std::fs::read_dir("/proc")?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()))
+        .filter_map(|pid| {
+            /* processes can exit while we're looking at them */
+            let name = std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+            Some(ProcessInfo { pid, name: name.trim_end().to_string() })
+        })
+        .collect();
+
+    processes.sort_by_key(|p| p.pid);
+
+    Ok(processes)
+}
+
+fn read_maps(pid: u32) -> Result<vec::Vec<Mapping>, std::io::Error> {
+    Ok(parse_maps(&std::fs::read_to_string(format!("/proc/{}/maps", pid))?))
+}
+
+fn attach(pid: u32) -> Result<State, std::io::Error> {
+    let mem = std::fs::File::open(format!("/proc/{}/mem", pid))?;
+    let maps = read_maps(pid)?;
+
+    Ok(State::Open { mem, maps })
+}
+
+/// Finds how far we can read starting at the given address without leaving readable memory, or None if the address
+/// isn't readable at all.
+fn readable_end(maps: &[Mapping], addr: u64) -> Option<u64> {
+    let index = maps.partition_point(|m| m.end <= addr);
+    let first = maps.get(index).filter(|m| m.start <= addr && m.readable)?;
+
+    let mut end = first.end;
+    for m in &maps[index+1..] {
+        if m.start != end || !m.readable {
+            break;
+        }
+        end = m.end;
+    }
+
+    Some(end)
+}
+
+impl ProcessAddressSpace {
+    pub fn new(pid: u32, label: &str) -> ProcessAddressSpace {
+        ProcessAddressSpace {
+            inner: sync::RwLock::<State>::new(State::Closed),
+            pid,
+            label: label.to_string(),
+        }
+    }
+
+    /// In case of error, the error is returned instead of being stored in this object as an error state.
+    pub fn try_open(&self) -> Result<&Self, std::io::Error> {
+        let mut guard = self.inner.write().unwrap();
+
+        match &*guard {
+            State::Open { .. } => Ok(self),
+            State::Closed | State::Error(_) => {
+                *guard = attach(self.pid)?;
+                Ok(self)
+            },
+        }
+    }
+
+    /// In case of error, the error is stored in this object as an error state instead of being returned.
+    pub fn open(&self) {
+        let mut guard = self.inner.write().unwrap();
+
+        match &*guard {
+            State::Open { .. } => {},
+            State::Closed | State::Error(_) => *guard = attach(self.pid).unwrap_or_else(State::Error),
+        }
+    }
+
+    /// Re-reads the process's memory map and returns it.
+    pub fn mappings(&self) -> Result<vec::Vec<Mapping>, std::io::Error> {
+        let maps = read_maps(self.pid)?;
+
+        if let State::Open { maps: cached, .. } = &mut *self.inner.write().unwrap() {
+            *cached = maps.clone();
+        }
+
+        Ok(maps)
+    }
+
+    fn read_sync(&self, offset: u64, length: u64) -> space::FetchResult {
+        let end = {
+            let inner = self.inner.read().unwrap();
+            let State::Open { maps, .. } = &*inner else { return space::FetchResult::Unreadable };
+            readable_end(maps, offset)
+        };
+
+        /* The process might have mapped something new since we last looked. */
+        let end = match end {
+            Some(end) => end,
+            None => match self.mappings() {
+                Ok(maps) => match readable_end(&maps, offset) {
+                    Some(end) => end,
+                    None => return space::FetchResult::Unreadable,
+                },
+                Err(e) => return space::FetchResult::IoError(e),
+            },
+        };
+
+        let inner = self.inner.read().unwrap();
+        let State::Open { mem, .. } = &*inner else { return space::FetchResult::Unreadable };
+
+        let mut out = vec![0; std::cmp::min(length, end - offset) as usize];
+        let mut done = 0;
+
+        while done < out.len() {
+            match mem.read_at(&mut out[done..], offset + done as u64) {
+                Ok(0) => break,
+                Ok(n) => done+= n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                /* The mapping went away or isn't backed by anything. Treat it like a hole. */
+                Err(e) if e.raw_os_error() == Some(libc::EIO) || e.raw_os_error() == Some(libc::EFAULT) => break,
+                Err(e) => return space::FetchResult::IoError(e),
+            }
+        }
+
+        out.truncate(done);
+
+        match done {
+            0 => space::FetchResult::Unreadable,
+            n if n as u64 == length => space::FetchResult::Ok(out),
+            _ => space::FetchResult::Partial(out),
+        }
+    }
+}
+
+impl space::AddressSpaceExt for ProcessAddressSpace {
+    fn get_label(&self) -> &str {
+        &self.label
+    }
+
+    async fn fetch(&self, extent: (u64, u64)) -> space::FetchResult {
+        self.read_sync(extent.0, extent.1)
+    }
+
+    /// Where the last mapping ends, as of the last time we looked.
+    fn size(&self) -> Option<u64> {
+        self.extents().map(|(begin, length)| begin + length)
+    }
+
+    /// The range between the start of the first mapping and the end of the last one.
+    fn extents(&self) -> Option<(u64, u64)> {
+        match &*self.inner.read().unwrap() {
+            State::Open { maps, .. } => Some((maps.first()?.start, maps.last()?.end - maps.first()?.start)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_matches::assert_matches;
+
+    #[test]
+    fn parse() {
+        let maps = parse_maps("\
+55d4c0a00000-55d4c0a02000 r--p 00000000 fd:01 1234                       /usr/bin/cat
+55d4c0a02000-55d4c0a07000 r-xp 00002000 fd:01 1234                       /usr/bin/cat
+7f0000000000-7f0000021000 rw-p 00000000 00:00 0
+7f0000100000-7f0000101000 rw-s 00000000 00:05 77                         /memfd:has spaces (deleted)
+garbage
+7ffd00000000-7ffd00021000 rw-p 00000000 00:00 0                          [stack]
+");
+
+        assert_eq!(maps.len(), 5);
+        assert_eq!(maps[1], Mapping {
+            start: 0x55d4c0a02000,
+            end: 0x55d4c0a07000,
+            readable: true,
+            writable: false,
+            executable: true,
+            shared: false,
+            offset: 0x2000,
+            pathname: Some("/usr/bin/cat".to_string()),
+        });
+        assert_eq!(maps[1].permissions(), "r-xp");
+        assert_eq!(maps[2].pathname, None);
+        assert_eq!(maps[3].pathname.as_deref(), Some("/memfd:has spaces (deleted)"));
+        assert_eq!(maps[3].permissions(), "rw-s");
+        assert_eq!(maps[4].pathname.as_deref(), Some("[stack]"));
+    }
+
+    #[test]
+    fn readable_ranges() {
+        let maps = parse_maps("\
+1000-2000 r--p 00000000 00:00 0
+2000-3000 rw-p 00000000 00:00 0
+3000-4000 ---p 00000000 00:00 0
+5000-6000 r--p 00000000 00:00 0
+");
+
+        assert_eq!(readable_end(&maps, 0x0), None);
+        assert_eq!(readable_end(&maps, 0x1800), Some(0x3000));
+        assert_eq!(readable_end(&maps, 0x2000), Some(0x3000));
+        assert_eq!(readable_end(&maps, 0x3000), None);
+        assert_eq!(readable_end(&maps, 0x4800), None);
+        assert_eq!(readable_end(&maps, 0x5fff), Some(0x6000));
+        assert_eq!(readable_end(&maps, 0x6000), None);
+    }
+
+    #[test]
+    fn read_own_memory() {
+        let bytes: vec::Vec<u8> = (0..0x100).map(|i| i as u8).collect();
+        let addr = bytes.as_ptr() as u64;
+
+        let space = ProcessAddressSpace::new(std::process::id(), "self");
+        space.try_open().unwrap();
+
+        assert_matches!(space.read_sync(addr + 0x10, 4), space::FetchResult::Ok(v) if v == vec![0x10, 0x11, 0x12, 0x13]);
+
+        /* nothing should ever be mapped at the null page */
+        assert_matches!(space.read_sync(0, 0x10), space::FetchResult::Unreadable);
+
+        /* reading off the end of the last mapping should be partial instead of an error */
+        let (begin, length) = space::AddressSpaceExt::extents(&space).unwrap();
+        assert!(begin <= addr && addr + 0x100 <= begin + length);
+
+        let last = space.mappings().unwrap().into_iter().rfind(|m| m.readable).unwrap();
+        if let space::FetchResult::Ok(_) = space.read_sync(last.end - 4, 4) {
+            assert_matches!(space.read_sync(last.end - 4, 8), space::FetchResult::Partial(v) if v.len() == 4);
+        }
+    }
+}
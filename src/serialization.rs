@@ -38,3 +38,78 @@ pub fn deserialize_project(bytes: &[u8]) -> Result<document::Document, Deseriali
 
     bincode_options().deserialize::<v1::Document>(&bytes[7..]).map_err(DeserializationError::BincodeError).map(v1::Document::into)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync;
+    use std::task;
+
+    use crate::model::datapath;
+    use crate::model::datapath::DataPathExt;
+    use crate::model::document::structure;
+    use crate::model::space;
+
+    #[test]
+    fn buffer_roundtrip() {
+        let bytes: Vec<u8> = (0..0x40).collect();
+        let space = space::buffer::BufferAddressSpace::new(bytes.clone(), "blob");
+
+        let document = document::Builder::new(structure::Node::builder().name("root").size(0x40).build())
+            .load_space(sync::Arc::new(space.into()))
+            .build();
+
+        let restored = deserialize_project(&serialize_project(&document).unwrap()).unwrap();
+
+        let datapath::Filter::LoadSpace(lsf) = &restored.datapath[0] else { panic!("expected load space filter") };
+        assert_eq!(space::AddressSpaceExt::get_label(&**lsf.space()), "blob");
+
+        let mut records = vec![datapath::ByteRecord::default(); 0x40];
+        restored.datapath.fetch(datapath::ByteRecordRange::new(0, &mut records), &mut task::Context::from_waker(futures::task::noop_waker_ref()));
+        assert_eq!(records.iter().map(|br| br.value).collect::<Vec<u8>>(), bytes);
+    }
+
+    #[test]
+    fn concat_roundtrip() {
+        let a: sync::Arc<space::AddressSpace> = sync::Arc::new(space::buffer::BufferAddressSpace::new(vec![0xa0, 0xa1], "a").into());
+        let b: sync::Arc<space::AddressSpace> = sync::Arc::new(space::buffer::BufferAddressSpace::new(vec![0xb0, 0xb1, 0xb2], "b").into());
+        let space = space::concat::ConcatAddressSpace::new(vec![(a, 2), (b, 3)], "a + b");
+
+        let document = document::Builder::new(structure::Node::builder().name("root").size(0x5).build())
+            .load_space(sync::Arc::new(space.into()))
+            .build();
+
+        let restored = deserialize_project(&serialize_project(&document).unwrap()).unwrap();
+
+        let datapath::Filter::LoadSpace(lsf) = &restored.datapath[0] else { panic!("expected load space filter") };
+        let space::AddressSpace::Concat(cas) = &**lsf.space() else { panic!("expected concatenated space") };
+        assert_eq!(cas.label, "a + b");
+        assert_eq!(cas.pieces().iter().map(|p| (space::AddressSpaceExt::get_label(&*p.space), p.offset, p.size)).collect::<Vec<_>>(), vec![("a", 0, 2), ("b", 2, 3)]);
+
+        let mut records = vec![datapath::ByteRecord::default(); 5];
+        restored.datapath.fetch(datapath::ByteRecordRange::new(0, &mut records), &mut task::Context::from_waker(futures::task::noop_waker_ref()));
+        assert_eq!(records.iter().map(|br| br.value).collect::<Vec<u8>>(), vec![0xa0, 0xa1, 0xb0, 0xb1, 0xb2]);
+    }
+
+    #[test]
+    fn transform_roundtrip() {
+        let space = space::buffer::BufferAddressSpace::new(vec![0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc], "blob");
+
+        let mut document = document::Builder::new(structure::Node::builder().name("root").size(0x6).build())
+            .load_space(sync::Arc::new(space.into()))
+            .build();
+        document.datapath.push_back(datapath::TransformFilter { offset: 1, size: 4, op: datapath::TransformOp::Xor, key: vec![0xff, 0x0f] }.to_filter());
+        document.datapath.push_back(datapath::TransformFilter { offset: 0, size: 2, op: datapath::TransformOp::RotateLeft, key: vec![4] }.to_filter());
+
+        let restored = deserialize_project(&serialize_project(&document).unwrap()).unwrap();
+
+        let datapath::Filter::Transform(tf) = &restored.datapath[1] else { panic!("expected transform filter") };
+        assert_eq!((tf.offset, tf.size, tf.op, &tf.key[..]), (1, 4, datapath::TransformOp::Xor, &[0xff, 0x0f][..]));
+
+        let mut records = vec![datapath::ByteRecord::default(); 6];
+        restored.datapath.fetch(datapath::ByteRecordRange::new(0, &mut records), &mut task::Context::from_waker(futures::task::noop_waker_ref()));
+        assert_eq!(records.iter().map(|br| br.value).collect::<Vec<u8>>(), vec![0x21, 0xbc, 0x59, 0x87, 0x95, 0xbc]);
+        assert_eq!(records.iter().map(|br| br.transformed).collect::<Vec<bool>>(), vec![true, true, true, true, true, false]);
+    }
+}
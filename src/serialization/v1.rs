@@ -79,6 +79,10 @@ enum Filter {
     Overwrite(OverwriteFilter),
     Move(MoveFilter),
     Insert(InsertFilter),
+    Transform(TransformFilter),
+    Decompress(DecompressFilter),
+    Delete(DeleteFilter),
+    Disabled(Box<Filter>),
 }
 
 #[derive(Serialize, Deserialize)]
@@ -112,9 +116,52 @@ struct InsertFilter {
     bytes: vec::Vec<u8>,
 }
 
+#[derive(Serialize, Deserialize)]
+struct TransformFilter {
+    offset: u64,
+    size: u64,
+    op: TransformOp,
+    key: vec::Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum TransformOp {
+    Xor,
+    Add,
+    Subtract,
+    RotateLeft,
+    RotateRight,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DecompressFilter {
+    offset: u64,
+    source_offset: u64,
+    source_size: u64,
+    format: CompressionFormat,
+}
+
+#[derive(Serialize, Deserialize)]
+enum CompressionFormat {
+    Zlib,
+    Gzip,
+    Lz4,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DeleteFilter {
+    offset: u64,
+    size: u64,
+}
+
 #[derive(Serialize, Deserialize)]
 enum Space {
-    File(FileAddressSpace)
+    File(FileAddressSpace),
+    Mmap(FileAddressSpace),
+    Process(ProcessAddressSpace),
+    Buffer(BufferAddressSpace),
+    Gdb(GdbAddressSpace),
+    Concat(ConcatAddressSpace),
 }
 
 #[derive(Serialize, Deserialize)]
@@ -123,6 +170,42 @@ struct FileAddressSpace {
     label: std::string::String,
 }
 
+#[derive(Serialize, Deserialize)]
+struct ProcessAddressSpace {
+    pid: u32,
+    label: std::string::String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BufferAddressSpace {
+    label: std::string::String,
+    bytes: vec::Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum GdbTarget {
+    Tcp(std::string::String),
+    Unix(std::path::PathBuf),
+}
+
+#[derive(Serialize, Deserialize)]
+struct GdbAddressSpace {
+    target: GdbTarget,
+    label: std::string::String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ConcatAddressSpace {
+    label: std::string::String,
+    pieces: vec::Vec<ConcatPiece>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ConcatPiece {
+    space: Space,
+    size: u64,
+}
+
 /* /===============================\   
  * | Conversions.                  |   
  * \===============================/
@@ -253,6 +336,10 @@ impl From<&datapath::Filter> for Filter {
             datapath::Filter::Overwrite(f) => Filter::Overwrite(OverwriteFilter::from(f)),
             datapath::Filter::Move(f) => Filter::Move(MoveFilter::from(f)),
             datapath::Filter::Insert(f) => Filter::Insert(InsertFilter::from(f)),
+            datapath::Filter::Transform(f) => Filter::Transform(TransformFilter::from(f)),
+            datapath::Filter::Decompress(f) => Filter::Decompress(DecompressFilter::from(f)),
+            datapath::Filter::Delete(f) => Filter::Delete(DeleteFilter::from(f)),
+            datapath::Filter::Disabled(f) => Filter::Disabled(Box::new(Filter::from(&*f.filter))),
         }
     }
 }
@@ -264,6 +351,10 @@ impl Into<datapath::Filter> for Filter {
             Filter::Overwrite(f) => datapath::Filter::Overwrite(f.into()),
             Filter::Move(f) => datapath::Filter::Move(f.into()),
             Filter::Insert(f) => datapath::Filter::Insert(f.into()),
+            Filter::Transform(f) => datapath::Filter::Transform(f.into()),
+            Filter::Decompress(f) => datapath::Filter::Decompress(f.into()),
+            Filter::Delete(f) => datapath::Filter::Delete(f.into()),
+            Filter::Disabled(f) => datapath::DisabledFilter { filter: Box::new((*f).into()) }.to_filter(),
         }
     }
 }
@@ -351,10 +442,116 @@ impl Into<datapath::InsertFilter> for InsertFilter {
     }
 }
 
+impl From<&datapath::TransformFilter> for TransformFilter {
+    fn from(f: &datapath::TransformFilter) -> TransformFilter {
+        TransformFilter {
+            offset: f.offset,
+            size: f.size,
+            op: f.op.into(),
+            key: f.key.clone(),
+        }
+    }
+}
+
+impl Into<datapath::TransformFilter> for TransformFilter {
+    fn into(self) -> datapath::TransformFilter {
+        datapath::TransformFilter {
+            offset: self.offset,
+            size: self.size,
+            op: self.op.into(),
+            key: self.key,
+        }
+    }
+}
+
+impl From<datapath::TransformOp> for TransformOp {
+    fn from(op: datapath::TransformOp) -> TransformOp {
+        match op {
+            datapath::TransformOp::Xor => TransformOp::Xor,
+            datapath::TransformOp::Add => TransformOp::Add,
+            datapath::TransformOp::Subtract => TransformOp::Subtract,
+            datapath::TransformOp::RotateLeft => TransformOp::RotateLeft,
+            datapath::TransformOp::RotateRight => TransformOp::RotateRight,
+        }
+    }
+}
+
+impl Into<datapath::TransformOp> for TransformOp {
+    fn into(self) -> datapath::TransformOp {
+        match self {
+            TransformOp::Xor => datapath::TransformOp::Xor,
+            TransformOp::Add => datapath::TransformOp::Add,
+            TransformOp::Subtract => datapath::TransformOp::Subtract,
+            TransformOp::RotateLeft => datapath::TransformOp::RotateLeft,
+            TransformOp::RotateRight => datapath::TransformOp::RotateRight,
+        }
+    }
+}
+
+impl From<&datapath::DecompressFilter> for DecompressFilter {
+    fn from(f: &datapath::DecompressFilter) -> DecompressFilter {
+        DecompressFilter {
+            offset: f.offset,
+            source_offset: f.source_offset,
+            source_size: f.source_size,
+            format: f.format.into(),
+        }
+    }
+}
+
+impl Into<datapath::DecompressFilter> for DecompressFilter {
+    fn into(self) -> datapath::DecompressFilter {
+        datapath::DecompressFilter::new(self.format.into(), self.source_offset, self.source_size, self.offset)
+    }
+}
+
+impl From<&datapath::DeleteFilter> for DeleteFilter {
+    fn from(f: &datapath::DeleteFilter) -> DeleteFilter {
+        DeleteFilter {
+            offset: f.offset,
+            size: f.size,
+        }
+    }
+}
+
+impl Into<datapath::DeleteFilter> for DeleteFilter {
+    fn into(self) -> datapath::DeleteFilter {
+        datapath::DeleteFilter {
+            offset: self.offset,
+            size: self.size,
+        }
+    }
+}
+
+impl From<datapath::decompress::Format> for CompressionFormat {
+    fn from(format: datapath::decompress::Format) -> CompressionFormat {
+        match format {
+            datapath::decompress::Format::Zlib => CompressionFormat::Zlib,
+            datapath::decompress::Format::Gzip => CompressionFormat::Gzip,
+            datapath::decompress::Format::Lz4 => CompressionFormat::Lz4,
+        }
+    }
+}
+
+impl Into<datapath::decompress::Format> for CompressionFormat {
+    fn into(self) -> datapath::decompress::Format {
+        match self {
+            CompressionFormat::Zlib => datapath::decompress::Format::Zlib,
+            CompressionFormat::Gzip => datapath::decompress::Format::Gzip,
+            CompressionFormat::Lz4 => datapath::decompress::Format::Lz4,
+        }
+    }
+}
+
 impl From<&space::AddressSpace> for Space {
     fn from(s: &space::AddressSpace) -> Space {
         match s {
             space::AddressSpace::File(f) => Space::File(f.into()),
+            space::AddressSpace::Mmap(f) => Space::Mmap(f.into()),
+            space::AddressSpace::Process(p) => Space::Process(p.into()),
+            space::AddressSpace::Buffer(b) => Space::Buffer(b.into()),
+            space::AddressSpace::Gdb(g) => Space::Gdb(g.into()),
+            space::AddressSpace::Concat(c) => Space::Concat(c.into()),
         }
     }
 }
@@ -362,7 +559,12 @@ impl From<&space::AddressSpace> for Space {
 impl Into<space::AddressSpace> for Space {
     fn into(self) -> space::AddressSpace {
         match self {
-            Space::File(f) => space::AddressSpace::File(f.into())
+            Space::File(f) => space::AddressSpace::File(f.into()),
+            Space::Mmap(f) => space::AddressSpace::Mmap(f.into()),
+            Space::Process(p) => space::AddressSpace::Process(p.into()),
+            Space::Buffer(b) => space::AddressSpace::Buffer(b.into()),
+            Space::Gdb(g) => space::AddressSpace::Gdb(g.into()),
+            Space::Concat(c) => space::AddressSpace::Concat(c.into()),
         }
     }
 }
@@ -382,6 +584,93 @@ impl Into<space::file::FileAddressSpace> for FileAddressSpace {
     }
 }
 
+impl From<&space::mmap::MmapAddressSpace> for FileAddressSpace {
+    fn from(f: &space::mmap::MmapAddressSpace) -> FileAddressSpace {
+        FileAddressSpace {
+            path: f.path.clone(),
+            label: f.label.clone(),
+        }
+    }
+}
+
+impl Into<space::mmap::MmapAddressSpace> for FileAddressSpace {
+    fn into(self) -> space::mmap::MmapAddressSpace {
+        space::mmap::MmapAddressSpace::new(self.path, &self.label)
+    }
+}
+
+impl From<&space::process::ProcessAddressSpace> for ProcessAddressSpace {
+    fn from(p: &space::process::ProcessAddressSpace) -> ProcessAddressSpace {
+        ProcessAddressSpace {
+            pid: p.pid,
+            label: p.label.clone(),
+        }
+    }
+}
+
+impl Into<space::process::ProcessAddressSpace> for ProcessAddressSpace {
+    fn into(self) -> space::process::ProcessAddressSpace {
+        space::process::ProcessAddressSpace::new(self.pid, &self.label)
+    }
+}
+
+impl From<&space::buffer::BufferAddressSpace> for BufferAddressSpace {
+    fn from(b: &space::buffer::BufferAddressSpace) -> BufferAddressSpace {
+        BufferAddressSpace {
+            label: b.label.clone(),
+            bytes: (*b.bytes).clone(),
+        }
+    }
+}
+
+impl Into<space::buffer::BufferAddressSpace> for BufferAddressSpace {
+    fn into(self) -> space::buffer::BufferAddressSpace {
+        space::buffer::BufferAddressSpace::new(self.bytes, &self.label)
+    }
+}
+
+impl From<&space::gdb::GdbAddressSpace> for GdbAddressSpace {
+    fn from(g: &space::gdb::GdbAddressSpace) -> GdbAddressSpace {
+        GdbAddressSpace {
+            target: match &g.target {
+                space::gdb::Target::Tcp(addr) => GdbTarget::Tcp(addr.clone()),
+                space::gdb::Target::Unix(path) => GdbTarget::Unix(path.clone()),
+            },
+            label: g.label.clone(),
+        }
+    }
+}
+
+impl From<&space::concat::ConcatAddressSpace> for ConcatAddressSpace {
+    fn from(c: &space::concat::ConcatAddressSpace) -> ConcatAddressSpace {
+        ConcatAddressSpace {
+            label: c.label.clone(),
+            pieces: c.pieces().iter().map(|p| ConcatPiece {
+                space: Space::from(&*p.space),
+                size: p.size,
+            }).collect(),
+        }
+    }
+}
+
+impl Into<space::concat::ConcatAddressSpace> for ConcatAddressSpace {
+    fn into(self) -> space::concat::ConcatAddressSpace {
+        space::concat::ConcatAddressSpace::new(self.pieces.into_iter().map(|p| {
+            let space: space::AddressSpace = p.space.into();
+            (sync::Arc::new(space), p.size)
+        }).collect(), &self.label)
+    }
+}
+
+impl Into<space::gdb::GdbAddressSpace> for GdbAddressSpace {
+    fn into(self) -> space::gdb::GdbAddressSpace {
+        space::gdb::GdbAddressSpace::new(match self.target {
+            GdbTarget::Tcp(addr) => space::gdb::Target::Tcp(addr),
+            GdbTarget::Unix(path) => space::gdb::Target::Unix(path),
+        }, &self.label)
+    }
+}
+
 /*
  * /===============================\   
  * | Serialize/deserialize impls.  |   
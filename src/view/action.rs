@@ -1,7 +1,14 @@
+pub mod datapath;
 pub mod debug;
+pub mod edit;
+pub mod export;
+pub mod import_patch;
 pub mod tree;
 pub mod listing;
 pub mod new_project;
+pub mod new_project_from_gdb;
+pub mod new_project_from_process;
+pub mod save_in_place;
 pub mod save_project;
 pub mod settings;
 pub mod open_project;
@@ -0,0 +1,158 @@
+use std::rc;
+use std::sync;
+
+use gtk::prelude::*;
+use gtk::gio;
+use gtk::glib;
+
+use crate::catch_panic;
+use crate::model::addr;
+use crate::model::datapath;
+use crate::model::document;
+use crate::view::error;
+use crate::view::window;
+use crate::view::window::ErrorReporter;
+
+/// The datapath editor's actions. The ones that edit a single filter take its index, since the editor can act on rows
+/// other than the selected one.
+struct DatapathActions {
+    document_host: sync::Arc<document::DocumentHost>,
+    window: rc::Weak<window::CharmWindow>,
+}
+
+pub fn add_actions(window_context: &window::WindowContext) {
+    let actions = rc::Rc::new(DatapathActions {
+        document_host: window_context.project.document_host.clone(),
+        window: window_context.window.clone(),
+    });
+
+    add_filter_action(window_context, &actions, "datapath.toggle_filter", |document, index| {
+        let Some(filter) = document.datapath.get(index) else { return Ok(None) };
+        check_not_resizing(filter)?;
+        Ok(Some(document.replace_filter(index, filter.toggled())))
+    });
+
+    add_filter_action(window_context, &actions, "datapath.remove_filter", |document, index| {
+        let Some(filter) = document.datapath.get(index) else { return Ok(None) };
+        check_not_resizing(filter)?;
+        Ok(Some(document.remove_filter(index)))
+    });
+
+    add_filter_action(window_context, &actions, "datapath.raise_filter", |document, index| {
+        Ok((index + 1 < document.datapath.len()).then(|| document.reorder_filter(index, index + 1)))
+    });
+
+    add_filter_action(window_context, &actions, "datapath.lower_filter", |document, index| {
+        Ok((index > 0 && index < document.datapath.len()).then(|| document.reorder_filter(index, index - 1)))
+    });
+
+    let action = gio::SimpleAction::new("datapath.compact", None);
+    action.connect_activate(activation_handler(&actions, |actions, _| {
+        if let Err(e) = actions.change(actions.document_host.get().compact_datapath()) {
+            actions.report_error(e);
+        }
+    }));
+    action.set_enabled(true);
+    window_context.action_group.add_action(&action);
+
+    let action = gio::SimpleAction::new("datapath.set_filter_address", Some(glib::VariantTy::new("(ts)").unwrap()));
+    action.connect_activate(activation_handler(&actions, move |actions, parameter| {
+        if let Some((index, address)) = parameter.and_then(|parameter| parameter.get::<(u64, String)>()) {
+            if let Err(e) = actions.set_address(index as usize, address) {
+                actions.report_error(e);
+            }
+        }
+    }));
+    action.set_enabled(true);
+    window_context.action_group.add_action(&action);
+}
+
+fn activation_handler<F: Fn(&DatapathActions, Option<&glib::Variant>) + 'static>(actions: &rc::Rc<DatapathActions>, cb: F) -> impl Fn(&gio::SimpleAction, Option<&glib::Variant>) {
+    let actions = actions.clone();
+
+    move |_, parameter| catch_panic! {
+        cb(&actions, parameter);
+    }
+}
+
+/// Adds an action that makes a change to the filter at the index it's given. `change` returns None if there's nothing
+/// to do, like when a filter is already at the top and gets moved up.
+fn add_filter_action<F>(window_context: &window::WindowContext, actions: &rc::Rc<DatapathActions>, name: &str, change: F) where
+    F: Fn(&document::Document, usize) -> Result<Option<document::change::Change>, error::Error> + 'static {
+    let action = gio::SimpleAction::new(name, Some(glib::VariantTy::UINT64));
+    action.connect_activate(activation_handler(actions, move |actions, parameter| {
+        if let Some(index) = parameter.and_then(|parameter| parameter.get::<u64>()) {
+            let document = actions.document_host.get();
+
+            if let Err(e) = change(&document, index as usize).and_then(|change| change.map_or(Ok(()), |change| actions.change(change))) {
+                actions.report_error(e);
+            }
+        }
+    }));
+    action.set_enabled(true);
+    window_context.action_group.add_action(&action);
+}
+
+/// Insertions and deletions were made along with a change to the size of the structure, so taking them out of the
+/// datapath (or putting them back) would leave the two out of step. Undo is the way to get rid of those.
+fn check_not_resizing(filter: &datapath::Filter) -> Result<(), error::Error> {
+    if filter.resizes_structure() {
+        return Err(error::Error {
+            while_attempting: error::Action::EditDatapath,
+            trouble: error::Trouble::Other("Inserted and deleted bytes can't be removed or disabled here, since the structure was resized around them. Use undo instead.".to_string()),
+            level: error::Level::Error,
+            is_bug: false,
+        });
+    }
+
+    Ok(())
+}
+
+impl DatapathActions {
+    fn change(&self, change: document::change::Change) -> Result<(), error::Error> {
+        self.document_host.change(change).map_err(|(error, attempted_version)| error::Error {
+            while_attempting: error::Action::EditDatapath,
+            trouble: error::Trouble::DocumentUpdateFailure {
+                error,
+                attempted_version,
+            },
+            level: error::Level::Error,
+            is_bug: false,
+        }).map(|_| {})
+    }
+
+    fn set_address(&self, index: usize, text: String) -> Result<(), error::Error> {
+        let document = self.document_host.get();
+        let Some(filter) = document.datapath.get(index) else { return Ok(()) };
+
+        let address = addr::Address::parse(text.as_str()).map_err(|error| error::Error {
+            while_attempting: error::Action::EditDatapath,
+            trouble: error::Trouble::AddressParseFailed {
+                error,
+                address: text,
+            },
+            level: error::Level::Error,
+            is_bug: false,
+        })?;
+
+        if address.bit != 0 {
+            return Err(error::Error {
+                while_attempting: error::Action::EditDatapath,
+                trouble: error::Trouble::Other("Filters can only be moved to byte-aligned addresses.".to_string()),
+                level: error::Level::Error,
+                is_bug: false,
+            });
+        }
+
+        /* the editor doesn't let you edit addresses that can't be changed */
+        let Some(filter) = filter.relocated(address.byte) else { return Ok(()) };
+
+        self.change(document.replace_filter(index, filter))
+    }
+
+    fn report_error(&self, error: error::Error) {
+        if let Some(window) = self.window.upgrade() {
+            window.report_error(error);
+        }
+    }
+}
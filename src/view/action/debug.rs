@@ -1,2 +1,3 @@
+pub mod cache_statistics;
 pub mod reopen_current_project;
 pub mod revert_document;
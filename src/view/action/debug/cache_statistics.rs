@@ -0,0 +1,125 @@
+use std::cell;
+use std::fmt::Write;
+use std::rc;
+use std::sync;
+
+use gtk::prelude::*;
+use gtk::glib;
+use gtk::glib::clone;
+
+use crate::catch_panic;
+use crate::model::datapath;
+use crate::model::document;
+use crate::view::helpers;
+use crate::view::window;
+
+/// How often the counters get refreshed while the panel is showing.
+const REFRESH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+struct CacheStatisticsAction {
+    document_host: sync::Arc<document::DocumentHost>,
+
+    dialog: gtk::ApplicationWindow,
+    label: gtk::Label,
+
+    refresh: cell::RefCell<Option<glib::SourceId>>,
+}
+
+pub fn add_action(window_context: &window::WindowContext) {
+    let label = gtk::Label::builder()
+        .xalign(0.0)
+        .yalign(0.0)
+        .selectable(true)
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(10)
+        .margin_end(10)
+        .css_classes(["monospace"])
+        .build();
+
+    let dialog = gtk::ApplicationWindow::builder()
+        .application(&window_context.window.upgrade().unwrap().application.application)
+        .child(&label)
+        .resizable(true)
+        .title("Cache Statistics")
+        .transient_for(&window_context.window.upgrade().unwrap().window)
+        .hide_on_close(true)
+        .destroy_with_parent(true)
+        .build();
+
+    let action = rc::Rc::new(CacheStatisticsAction {
+        document_host: window_context.project.document_host.clone(),
+
+        dialog: dialog.clone(),
+        label,
+
+        refresh: cell::RefCell::new(None),
+    });
+
+    /* no point in refreshing counters nobody can see */
+    dialog.connect_hide(clone!(#[weak] action, move |_| catch_panic! {
+        action.stop_refreshing();
+    }));
+
+    window_context.action_group.add_action(&helpers::create_simple_action_strong(action, "debug.cache_statistics", |action| action.activate()));
+}
+
+impl CacheStatisticsAction {
+    fn activate(self: &rc::Rc<Self>) {
+        self.update();
+
+        if self.refresh.borrow().is_none() {
+            *self.refresh.borrow_mut() = Some(glib::timeout_add_local(REFRESH_INTERVAL, clone!(#[weak(rename_to = action)] self, #[upgrade_or] glib::ControlFlow::Break, move || catch_panic! {
+                @default(glib::ControlFlow::Break);
+                action.update();
+                glib::ControlFlow::Continue
+            })));
+        }
+
+        self.dialog.present();
+    }
+
+    fn stop_refreshing(&self) {
+        if let Some(source) = self.refresh.borrow_mut().take() {
+            source.remove();
+        }
+    }
+
+    fn update(&self) {
+        let document = self.document_host.get();
+        let mut text = String::new();
+
+        for filter in document.datapath.iter() {
+            let datapath::Filter::LoadSpace(lsf) = filter else { continue };
+            let statistics = lsf.cache_statistics();
+            let lookups = statistics.hits + statistics.misses;
+
+            /* writing to a String can't fail */
+            let _ = writeln!(text, "{}", filter.human_details());
+            let _ = writeln!(text, "  geometry:      {} x 0x{:x} bytes", lsf.cache_block_count(), lsf.cache_block_size());
+            let _ = write!(text, "  hits:          {}", statistics.hits);
+            if lookups > 0 {
+                let _ = write!(text, " ({:.1}%)", 100.0 * statistics.hits as f64 / lookups as f64);
+            }
+            let _ = writeln!(text);
+            let _ = writeln!(text, "  misses:        {}", statistics.misses);
+            let _ = writeln!(text, "  evictions:     {}", statistics.evictions);
+            let _ = writeln!(text, "  cancellations: {}", statistics.cancellations);
+            let _ = writeln!(text, "  pending:       {}", statistics.pending);
+            let _ = writeln!(text, "  bytes fetched: 0x{:x}", statistics.bytes_fetched);
+        }
+
+        if text.is_empty() {
+            text.push_str("No address spaces are loaded.");
+        }
+
+        self.label.set_text(text.trim_end());
+    }
+}
+
+impl Drop for CacheStatisticsAction {
+    fn drop(&mut self) {
+        self.stop_refreshing();
+        self.dialog.destroy();
+    }
+}
@@ -0,0 +1,82 @@
+use std::rc;
+
+use gtk::prelude::*;
+use gtk::glib::clone;
+
+use crate::view::error;
+use crate::view::helpers;
+use crate::view::project;
+use crate::view::window;
+use crate::view::window::ErrorReporter;
+
+struct UndoAction {
+    project: rc::Rc<project::Project>,
+    window: rc::Weak<window::CharmWindow>,
+    
+    subscriber: once_cell::unsync::OnceCell<helpers::AsyncSubscriber>,
+}
+
+pub fn add_actions(window_context: &window::WindowContext) {
+    let document = window_context.project.document_host.get();
+    
+    let action_impl = rc::Rc::new(UndoAction {
+        project: window_context.project.clone(),
+        window: window_context.window.clone(),
+        subscriber: Default::default(),
+    });
+
+    let undo_action = helpers::create_simple_action_strong(action_impl.clone(), "undo", |action| action.activate(false));
+    let redo_action = helpers::create_simple_action_strong(action_impl.clone(), "redo", |action| action.activate(true));
+
+    {
+        let mut history = action_impl.project.undo_history.borrow_mut();
+        history.sync(&document);
+        undo_action.set_enabled(history.can_undo());
+        redo_action.set_enabled(history.can_redo());
+    }
+    
+    action_impl.subscriber.set(
+        helpers::subscribe_to_updates(
+            rc::Rc::downgrade(&action_impl),
+            action_impl.project.document_host.clone(),
+            document,
+            clone!(#[weak] undo_action, #[weak] redo_action, move |action_impl, new_document| {
+                let mut history = action_impl.project.undo_history.borrow_mut();
+                history.sync(new_document);
+                undo_action.set_enabled(history.can_undo());
+                redo_action.set_enabled(history.can_redo());
+            })
+        )
+    ).unwrap();
+
+    window_context.action_group.add_action(&undo_action);
+    window_context.action_group.add_action(&redo_action);
+}
+
+impl UndoAction {
+    fn activate(&self, redo: bool) {
+        let result = {
+            let mut history = self.project.undo_history.borrow_mut();
+            
+            if redo {
+                history.redo(&self.project.document_host)
+            } else {
+                history.undo(&self.project.document_host)
+            }
+        };
+
+        if let Err((error, attempted_version)) = result {
+            if let Some(window) = self.window.upgrade() {
+                window.report_error(error::Error {
+                    while_attempting: if redo { error::Action::Redo } else { error::Action::Undo },
+                    trouble: error::Trouble::DocumentUpdateFailure {
+                        error,
+                        attempted_version,
+                    },
+                    level: error::Level::Error,
+                    is_bug: false,
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use std::cell;
+use std::io::Write;
+use std::path;
+use std::rc;
+use std::sync;
+
+use gtk::prelude::*;
+use gtk::glib;
+use gtk::glib::clone;
+
+use crate::catch_panic;
+use crate::model::datapath;
+use crate::model::datapath::export;
+use crate::model::datapath::patch;
+use crate::model::document;
+use crate::view::error;
+use crate::view::helpers;
+use crate::view::window;
+use crate::view::window::ErrorReporter;
+
+/// How often the progress bar gets updated while an export is running.
+const REFRESH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+#[derive(Clone, Copy)]
+enum Kind {
+    /// The datapath's output, byte for byte.
+    Data,
+    /// The difference between the datapath's output and the data underneath it.
+    Patch,
+}
+
+/// Whatever stopped an export, from either kind.
+enum Failure {
+    Data(export::ExportError),
+    Patch(patch::PatchError),
+}
+
+struct ExportAction {
+    kind: Kind,
+    window: rc::Weak<window::CharmWindow>,
+    document_host: sync::Arc<document::DocumentHost>,
+    dialog: gtk::FileChooserNative,
+
+    running: cell::RefCell<Option<RunningExport>>,
+}
+
+struct RunningExport {
+    progress: sync::Arc<export::Progress>,
+    dialog: gtk::ApplicationWindow,
+    refresh: glib::SourceId,
+}
+
+pub fn add_actions(window_context: &window::WindowContext) {
+    add_action(window_context, Kind::Data, "export_data");
+    add_action(window_context, Kind::Patch, "export_patch");
+}
+
+fn add_action(window_context: &window::WindowContext, kind: Kind, name: &str) {
+    let window = window_context.window.upgrade().unwrap();
+
+    let dialog = gtk::FileChooserNative::builder()
+        .accept_label("Export")
+        .cancel_label("Cancel")
+        .title(kind.title())
+        .modal(true)
+        .transient_for(&window.window)
+        .action(gtk::FileChooserAction::Save)
+        .select_multiple(false)
+        .create_folders(true)
+        .build();
+
+    let action = rc::Rc::new(ExportAction {
+        kind,
+        window: window_context.window.clone(),
+        document_host: window_context.project.document_host.clone(),
+        dialog,
+
+        running: cell::RefCell::new(None),
+    });
+
+    action.dialog.connect_response(clone!(#[weak] action, move |_dialog, response_type| catch_panic! {
+        action.respond(response_type);
+    }));
+
+    window_context.action_group.add_action(&helpers::create_simple_action_strong(action, name, |action| action.activate()));
+}
+
+impl Kind {
+    fn title(&self) -> &'static str {
+        match self {
+            Kind::Data => "Charm: Export Data",
+            Kind::Patch => "Charm: Export Patch",
+        }
+    }
+
+    fn action(&self) -> error::Action {
+        match self {
+            Kind::Data => error::Action::ExportData,
+            Kind::Patch => error::Action::ExportPatch,
+        }
+    }
+}
+
+impl Failure {
+    fn into_trouble(self) -> Option<error::Trouble> {
+        match self {
+            Failure::Data(export::ExportError::Cancelled) | Failure::Patch(patch::PatchError::Fetch(export::ExportError::Cancelled)) => None,
+            Failure::Data(error) => Some(error::Trouble::ExportFailed(error)),
+            Failure::Patch(error) => Some(error::Trouble::PatchFailed(error)),
+        }
+    }
+}
+
+impl From<export::ExportError> for Failure {
+    fn from(e: export::ExportError) -> Failure {
+        Failure::Data(e)
+    }
+}
+
+impl From<patch::PatchError> for Failure {
+    fn from(e: patch::PatchError) -> Failure {
+        Failure::Patch(e)
+    }
+}
+
+impl From<std::io::Error> for Failure {
+    fn from(e: std::io::Error) -> Failure {
+        Failure::Data(export::ExportError::Io(e))
+    }
+}
+
+impl ExportAction {
+    fn activate(&self) {
+        /* only one export at a time */
+        if let Some(running) = &*self.running.borrow() {
+            running.dialog.present();
+            return;
+        }
+
+        if let Kind::Patch = self.kind {
+            /* find out whether the patch can be made at all before asking where to put it */
+            match patch::choose_format(&self.document_host.get().datapath) {
+                Ok(format) => self.dialog.set_current_name(&format!("patch.{}", format.extension())),
+                Err(error) => {
+                    if let Some(window) = self.window.upgrade() {
+                        window.report_error(error::Error {
+                            while_attempting: self.kind.action(),
+                            trouble: error::Trouble::PatchFailed(error),
+                            level: error::Level::Error,
+                            is_bug: false,
+                        });
+                    }
+                    return;
+                },
+            }
+        }
+
+        self.dialog.show();
+    }
+
+    fn respond(self: &rc::Rc<Self>, response_type: gtk::ResponseType) {
+        self.dialog.hide();
+
+        match response_type {
+            gtk::ResponseType::Accept => {
+                if let Some(path) = self.dialog.file().and_then(|file| file.path()) {
+                    self.start(path);
+                }
+            },
+            _ => {} /* we were cancelled, ignore */
+        }
+    }
+
+    fn start(self: &rc::Rc<Self>, path: path::PathBuf) {
+        let window = match self.window.upgrade() {
+            Some(w) => w,
+            None => return,
+        };
+
+        let document = self.document_host.get();
+        let datapath = document.datapath.clone();
+        let size = document.root.size.round_up().bytes;
+        let progress = sync::Arc::new(export::Progress::default());
+
+        let label = gtk::Label::builder()
+            .label(format!("Exporting to {}", path.display()))
+            .xalign(0.0)
+            .build();
+
+        let progress_bar = gtk::ProgressBar::builder()
+            .show_text(true)
+            .build();
+
+        let cancel_button = gtk::Button::with_label("Cancel");
+        cancel_button.set_halign(gtk::Align::End);
+
+        let content = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(10)
+            .margin_top(10)
+            .margin_bottom(10)
+            .margin_start(10)
+            .margin_end(10)
+            .width_request(400)
+            .build();
+        content.append(&label);
+        content.append(&progress_bar);
+        content.append(&cancel_button);
+
+        let dialog = gtk::ApplicationWindow::builder()
+            .application(&window.application.application)
+            .child(&content)
+            .resizable(false)
+            .deletable(false)
+            .modal(true)
+            .title(self.kind.title())
+            .transient_for(&window.window)
+            .destroy_with_parent(true)
+            .build();
+
+        cancel_button.connect_clicked(clone!(#[strong] progress, move |button| catch_panic! {
+            button.set_sensitive(false);
+            progress.cancel();
+        }));
+
+        let refresh = glib::timeout_add_local(REFRESH_INTERVAL, clone!(#[strong] progress, #[weak] progress_bar, #[upgrade_or] glib::ControlFlow::Break, move || catch_panic! {
+            @default(glib::ControlFlow::Break);
+            let bytes_written = progress.bytes_written();
+            progress_bar.set_fraction(if size == 0 { 1.0 } else { bytes_written as f64 / size as f64 });
+            progress_bar.set_text(Some(&format!("0x{:x} / 0x{:x} bytes", bytes_written, size)));
+            glib::ControlFlow::Continue
+        }));
+
+        let kind = self.kind;
+        let handle = window.application.rt.handle().clone();
+        let task = window.application.rt.spawn_blocking(clone!(#[strong] progress, move || {
+            run_export(kind, &handle, &datapath, size, &path, &progress)
+        }));
+
+        dialog.present();
+
+        *self.running.borrow_mut() = Some(RunningExport {
+            progress,
+            dialog,
+            refresh,
+        });
+
+        /* This isn't kept in an AsyncSubscriber because it's the one that tears down the RunningExport. If we go away
+         * first, Drop cancels the export and this finds nothing left to tell. */
+        let action = rc::Rc::downgrade(self);
+        glib::MainContext::default().spawn_local(async move {
+            let result = task.await;
+
+            if let Some(action) = action.upgrade() {
+                catch_panic! {
+                    action.finish(result);
+                }
+            }
+        });
+    }
+
+    fn finish(&self, result: Result<Result<(), Failure>, tokio::task::JoinError>) {
+        if let Some(running) = self.running.borrow_mut().take() {
+            running.refresh.remove();
+            running.dialog.destroy();
+        }
+
+        let trouble = match result {
+            Ok(Ok(())) => return,
+            Ok(Err(failure)) => match failure.into_trouble() {
+                Some(trouble) => trouble,
+                None => return,
+            },
+            Err(error) => error::Trouble::Other(format!("Export task failed: {}", error)),
+        };
+
+        if let Some(window) = self.window.upgrade() {
+            window.report_error(error::Error {
+                while_attempting: self.kind.action(),
+                is_bug: matches!(trouble, error::Trouble::Other(_)),
+                trouble,
+                level: error::Level::Error,
+            });
+        }
+    }
+}
+
+impl Drop for ExportAction {
+    fn drop(&mut self) {
+        if let Some(running) = self.running.get_mut().take() {
+            running.progress.cancel();
+            running.refresh.remove();
+            running.dialog.destroy();
+        }
+
+        self.dialog.destroy();
+    }
+}
+
+/// Runs on a blocking thread. Writes to a file next to the destination first and only moves it into place once the
+/// whole thing has been written, so a failed or cancelled export doesn't leave a truncated file where the user asked
+/// for the output.
+fn run_export(kind: Kind, handle: &tokio::runtime::Handle, datapath: &datapath::DataPath, size: u64, path: &path::Path, progress: &export::Progress) -> Result<(), Failure> {
+    let mut partial_path = path.as_os_str().to_owned();
+    partial_path.push(".partial");
+    let partial_path = path::PathBuf::from(partial_path);
+
+    let result = match kind {
+        Kind::Data => write_export(handle, datapath, size, &partial_path, progress),
+        Kind::Patch => write_patch(handle, datapath, size, &partial_path, progress),
+    }.and_then(|()| {
+        std::fs::rename(&partial_path, path).map_err(Failure::from)
+    });
+
+    if result.is_err() {
+        /* nothing useful to do if this fails too */
+        let _ = std::fs::remove_file(&partial_path);
+    }
+
+    result
+}
+
+fn write_export(handle: &tokio::runtime::Handle, datapath: &datapath::DataPath, size: u64, path: &path::Path, progress: &export::Progress) -> Result<(), Failure> {
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+
+    handle.block_on(export::export(datapath, size, &mut out, progress))?;
+
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+    Ok(())
+}
+
+fn write_patch(handle: &tokio::runtime::Handle, datapath: &datapath::DataPath, size: u64, path: &path::Path, progress: &export::Progress) -> Result<(), Failure> {
+    let patch = handle.block_on(patch::create(datapath, size, progress))?;
+
+    let file = std::fs::File::create(path)?;
+    (&file).write_all(&patch.bytes)?;
+    file.sync_all()?;
+
+    Ok(())
+}
@@ -0,0 +1,189 @@
+use std::cell;
+use std::rc;
+use std::sync;
+use std::vec;
+
+use gtk::prelude::*;
+use gtk::glib;
+use gtk::glib::clone;
+
+use crate::catch_panic;
+use crate::model::datapath;
+use crate::model::datapath::export;
+use crate::model::datapath::patch;
+use crate::model::document;
+use crate::view::error;
+use crate::view::helpers;
+use crate::view::window;
+use crate::view::window::ErrorReporter;
+
+struct ImportPatchAction {
+    window: rc::Weak<window::CharmWindow>,
+    document_host: sync::Arc<document::DocumentHost>,
+    dialog: gtk::FileChooserNative,
+
+    busy: cell::Cell<bool>,
+}
+
+enum Failure {
+    Read(std::io::Error),
+    Import(patch::ImportError),
+}
+
+pub fn add_action(window_context: &window::WindowContext) {
+    let window = window_context.window.upgrade().unwrap();
+
+    let patches = gtk::FileFilter::new();
+    patches.set_name(Some("Patches"));
+    patches.add_pattern("*.ips");
+    patches.add_pattern("*.bps");
+    patches.add_pattern("*.txt");
+
+    let everything = gtk::FileFilter::new();
+    everything.set_name(Some("All Files"));
+    everything.add_pattern("*");
+
+    let dialog = gtk::FileChooserNative::builder()
+        .accept_label("Import")
+        .cancel_label("Cancel")
+        .title("Charm: Import Patch")
+        .modal(true)
+        .transient_for(&window.window)
+        .action(gtk::FileChooserAction::Open)
+        .select_multiple(false)
+        .build();
+    dialog.add_filter(&patches);
+    dialog.add_filter(&everything);
+
+    let action = rc::Rc::new(ImportPatchAction {
+        window: window_context.window.clone(),
+        document_host: window_context.project.document_host.clone(),
+        dialog,
+
+        busy: cell::Cell::new(false),
+    });
+
+    action.dialog.connect_response(clone!(#[weak] action, move |_dialog, response_type| catch_panic! {
+        action.respond(response_type);
+    }));
+
+    window_context.action_group.add_action(&helpers::create_simple_action_strong(action, "import_patch", |action| action.activate()));
+}
+
+impl ImportPatchAction {
+    fn activate(&self) {
+        if !self.busy.get() {
+            self.dialog.show();
+        }
+    }
+
+    fn respond(self: &rc::Rc<Self>, response_type: gtk::ResponseType) {
+        self.dialog.hide();
+
+        match response_type {
+            gtk::ResponseType::Accept => {
+                if let Some(path) = self.dialog.file().and_then(|file| file.path()) {
+                    self.start(path);
+                }
+            },
+            _ => {} /* we were cancelled, ignore */
+        }
+    }
+
+    fn start(self: &rc::Rc<Self>, path: std::path::PathBuf) {
+        let window = match self.window.upgrade() {
+            Some(w) => w,
+            None => return,
+        };
+
+        let document = self.document_host.get();
+        let datapath = document.datapath.clone();
+
+        self.busy.set(true);
+
+        /* BPS patches need to read the document's data, which can take a while */
+        let handle = window.application.rt.handle().clone();
+        let task = window.application.rt.spawn_blocking(move || {
+            let bytes = std::fs::read(&path).map_err(Failure::Read)?;
+            handle.block_on(patch::import(&bytes, &datapath, &export::Progress::default())).map_err(Failure::Import)
+        });
+
+        let action = rc::Rc::downgrade(self);
+        glib::MainContext::default().spawn_local(async move {
+            let result = task.await;
+
+            if let Some(action) = action.upgrade() {
+                catch_panic! {
+                    action.finish(result);
+                }
+            }
+        });
+    }
+
+    fn finish(&self, result: Result<Result<vec::Vec<datapath::Filter>, Failure>, tokio::task::JoinError>) {
+        self.busy.set(false);
+
+        let Some(window) = self.window.upgrade() else { return };
+
+        let filters = match result {
+            Ok(Ok(filters)) => filters,
+            Ok(Err(failure)) => {
+                window.report_error(error::Error {
+                    while_attempting: error::Action::ImportPatch,
+                    trouble: match failure {
+                        Failure::Read(error) => error::Trouble::StdIoError(error),
+                        Failure::Import(error) => error::Trouble::PatchImportFailed(error),
+                    },
+                    level: error::Level::Error,
+                    is_bug: false,
+                });
+                return;
+            },
+            Err(error) => {
+                window.report_error(error::Error {
+                    while_attempting: error::Action::ImportPatch,
+                    trouble: error::Trouble::Other(format!("Import task failed: {}", error)),
+                    level: error::Level::Error,
+                    is_bug: true,
+                });
+                return;
+            },
+        };
+
+        /* The filters are in order of increasing address, so each insertion or deletion only shifts things that come
+         * after it and haven't been applied yet. */
+        for filter in filters {
+            let document = self.document_host.get();
+
+            let address = filter.human_affects_addr();
+            let change = match document.import_filter(filter) {
+                Ok(change) => change,
+                Err(error) => {
+                    window.report_error(error::Error {
+                        while_attempting: error::Action::ImportPatch,
+                        trouble: error::Trouble::Other(match error {
+                            document::EditDataError::PastEnd => format!("The patch edits data at 0x{:x}, which is past the end of the document.", address),
+                            document::EditDataError::ContainsNodes => format!("The patch deletes bytes at 0x{:x} that contain structure nodes. Delete them first.", address),
+                        }),
+                        level: error::Level::Error,
+                        is_bug: false,
+                    });
+                    return;
+                },
+            };
+
+            if let Err((error, attempted_version)) = self.document_host.change(change) {
+                window.report_error(error::Error {
+                    while_attempting: error::Action::ImportPatch,
+                    trouble: error::Trouble::DocumentUpdateFailure {
+                        error,
+                        attempted_version,
+                    },
+                    level: error::Level::Error,
+                    is_bug: false,
+                });
+                return;
+            }
+        }
+    }
+}
@@ -1,3 +1,5 @@
+pub mod delete_selected_bytes;
 pub mod delete_selected_nodes;
 pub mod insert_node;
 pub mod goto;
+pub mod mode;
@@ -0,0 +1,131 @@
+use std::cell;
+use std::rc;
+use std::sync;
+
+use crate::catch_panic;
+use crate::model::addr;
+use crate::model::document;
+use crate::model::selection;
+use crate::view::error;
+use crate::view::helpers;
+use crate::view::window;
+use crate::view::window::ErrorReporter;
+
+use gtk::prelude::*;
+use gtk::gio;
+
+struct DeleteSelectedBytesAction {
+    document_host: sync::Arc<document::DocumentHost>,
+    selection_host: sync::Arc<selection::listing::Host>,
+    selection: cell::RefCell<sync::Arc<selection::ListingSelection>>,
+    subscriber: helpers::AsyncSubscriber,
+    action: gio::SimpleAction,
+}
+
+pub fn add_action(window_context: &window::WindowContext) {
+    let gio_action = gio::SimpleAction::new("delete_selected_bytes", None);
+    let action_impl = DeleteSelectedBytesAction::new(window_context, gio_action.clone());
+    let window = window_context.window.clone();
+    action_impl.update_is_enabled();
+
+    gio_action.connect_activate(move |_, _| catch_panic! {
+        let Some(window) = window.upgrade() else { return };
+
+        if let Err(e) = action_impl.activate() {
+            window.report_error(e);
+        }
+    });
+
+    window_context.action_group.add_action(&gio_action);
+}
+
+/// The absolute extent that the selection covers, if it's something we can delete. Structure selections that include
+/// nodes are left to delete_selected_nodes.
+fn selected_extent(selection: &selection::ListingSelection) -> Option<addr::Extent> {
+    let extent = match &selection.mode {
+        selection::listing::Mode::Address(extent) => *extent,
+        selection::listing::Mode::Structure(selection::listing::StructureMode::Range(range)) if range.begin.1 == range.end.1 => {
+            range.extent().rebase(selection.document.lookup_node(&range.path).1)
+        },
+
+        _ => return None,
+    };
+
+    if extent.is_empty() {
+        None
+    } else {
+        Some(extent)
+    }
+}
+
+impl DeleteSelectedBytesAction {
+    fn new(window_context: &window::WindowContext, action: gio::SimpleAction) -> rc::Rc<Self> {
+        let document_host = window_context.project.document_host.clone();
+        let selection_host = window_context.listing_selection_host.clone();
+        let selection = selection_host.get();
+
+        rc::Rc::new_cyclic(|weak: &rc::Weak<Self>| Self {
+            subscriber: helpers::subscribe_to_updates(weak.clone(), selection_host.clone(), selection.clone(), |action, new_sel| {
+                action.selection_updated(new_sel);
+            }),
+
+            document_host,
+            selection_host,
+            selection: cell::RefCell::new(selection),
+            action,
+        })
+    }
+
+    fn selection_updated(&self, new_sel: &sync::Arc<selection::ListingSelection>) {
+        *self.selection.borrow_mut() = new_sel.clone();
+        self.update_is_enabled();
+    }
+
+    fn update_is_enabled(&self) {
+        self.action.set_enabled(selected_extent(&self.selection.borrow()).is_some());
+    }
+
+    fn change(&self) -> Result<document::change::Change, error::Error> {
+        let selection = self.selection.borrow();
+
+        let extent = selected_extent(&selection).ok_or_else(|| error::Error {
+            while_attempting: error::Action::DeleteBytesInListing,
+            trouble: error::Trouble::UnsupportedListingSelectionMode {
+                selection: selection.clone()
+            },
+            level: error::Level::Error,
+            is_bug: false,
+        })?;
+
+        if extent.begin.bit != 0 || extent.end.bit != 0 {
+            return Err(error::Error {
+                while_attempting: error::Action::DeleteBytesInListing,
+                trouble: error::Trouble::Other("The selection doesn't start and end on byte boundaries.".to_string()),
+                level: error::Level::Error,
+                is_bug: false,
+            });
+        }
+
+        selection.document.delete_extent(extent).map_err(|error| error::Error {
+            while_attempting: error::Action::DeleteBytesInListing,
+            trouble: error::Trouble::Other(match error {
+                document::EditDataError::PastEnd => "The selection goes past the end of the document.",
+                document::EditDataError::ContainsNodes => "The selection contains structure nodes. Delete them first.",
+            }.to_string()),
+            level: error::Level::Error,
+            is_bug: false,
+        })
+    }
+
+    fn activate(&self) -> Result<(), error::Error> {
+        self.document_host.change(self.change()?).map_err(|(error, attempted_version)| error::Error {
+            while_attempting: error::Action::DeleteBytesInListing,
+            trouble: error::Trouble::DocumentUpdateFailure {
+                error,
+                attempted_version
+            },
+            level: error::Level::Error,
+            is_bug: false,
+        }).map(|_| {})
+    }
+}
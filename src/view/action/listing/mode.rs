@@ -0,0 +1,46 @@
+use gtk::prelude::*;
+use gtk::glib;
+use gtk::gio;
+
+use crate::catch_panic;
+use crate::view::listing::facet::cursor::Mode;
+use crate::view::window;
+
+pub fn add_actions(window_context: &window::WindowContext) {
+    let lw = window_context.lw.clone();
+    
+    let action = gio::SimpleAction::new_stateful("mode", Some(glib::VariantTy::STRING), &"command".to_variant());
+    action.connect_activate(|action, parameter| catch_panic! {
+        action.change_state(parameter.unwrap());
+    });
+    action.connect_change_state(move |action, state| catch_panic! {
+        let mode = match state.and_then(|state| state.str()) {
+            Some("command") => Mode::Command,
+            Some("entry") => Mode::Entry,
+            _ => return,
+        };
+
+        lw.set_mode(mode);
+        action.set_state(state.unwrap());
+    });
+    action.set_enabled(true);
+
+    window_context.action_group.add_action(&action);
+
+    let lw = window_context.lw.clone();
+    
+    let action = gio::SimpleAction::new_stateful("insert_mode", None, &false.to_variant());
+    action.connect_activate(|action, _| catch_panic! {
+        let insert = action.state().and_then(|state| state.get::<bool>()).unwrap_or(false);
+        action.change_state(&(!insert).to_variant());
+    });
+    action.connect_change_state(move |action, state| catch_panic! {
+        let Some(insert) = state.and_then(|state| state.get::<bool>()) else { return };
+
+        lw.set_insert(insert);
+        action.set_state(&insert.to_variant());
+    });
+    action.set_enabled(true);
+
+    window_context.action_group.add_action(&action);
+}
@@ -11,6 +11,8 @@ use crate::catch_panic;
 use crate::model::document;
 use crate::model::document::structure;
 use crate::model::space;
+use crate::model::space::AddressSpaceExt;
+use crate::view::config;
 use crate::view::error;
 use crate::view::helpers;
 use crate::view::project;
@@ -20,6 +22,7 @@ use crate::view::window::ErrorReporter;
 struct NewProjectAction {
     window: rc::Weak<window::CharmWindow>,
     file_chooser: gtk::FileChooserNative,
+    concat_chooser: gtk::FileChooserNative,
 }
 
 pub fn add_actions(window: &rc::Rc<window::CharmWindow>) {
@@ -33,17 +36,33 @@ pub fn add_actions(window: &rc::Rc<window::CharmWindow>) {
         .create_folders(true)
         .build();
 
+    let concat_dialog = gtk::FileChooserNative::builder()
+        .accept_label("Open")
+        .cancel_label("Cancel")
+        .title("Charm: Open Files to Concatenate")
+        .modal(true)
+        .transient_for(&window.window)
+        .action(gtk::FileChooserAction::Open)
+        .select_multiple(true)
+        .build();
+
     let action = rc::Rc::new(NewProjectAction {
         window: rc::Rc::downgrade(window),
-        file_chooser: dialog
+        file_chooser: dialog,
+        concat_chooser: concat_dialog,
     });
 
     action.file_chooser.connect_response(clone!(#[weak] action, move |_dialog, response_type| catch_panic! {
         action.file_chooser_response(response_type);
     }));
 
+    action.concat_chooser.connect_response(clone!(#[weak] action, move |_dialog, response_type| catch_panic! {
+        action.concat_chooser_response(response_type);
+    }));
+
     window.window.add_action(&helpers::create_simple_action_strong(action.clone(), "new_project.empty", |action| action.activate_empty()));
     window.window.add_action(&helpers::create_simple_action_strong(action.clone(), "new_project.from_file", |action| action.activate_from_file()));
+    window.window.add_action(&helpers::create_simple_action_strong(action.clone(), "new_project.from_files_concatenated", |action| action.activate_from_files_concatenated()));
 }
 
 enum NewProjectError {
@@ -83,19 +102,41 @@ impl NewProjectAction {
         self.file_chooser.show();
     }
 
-    fn new_project_from_file(file: &gio::File) -> Result<document::Document, NewProjectError> {
+    fn activate_from_files_concatenated(&self) {
+        self.concat_chooser.show();
+    }
+
+    /// Returns the space along with its display name and size.
+    fn open_file_space(file: &gio::File) -> Result<(space::AddressSpace, glib::GString, u64), NewProjectError> {
         if !file.is_native() {
             return Err(NewProjectError::NonNativeFile);
         }
         
         let attributes = file.query_info("standard::display-name,standard::size", gio::FileQueryInfoFlags::NONE, gio::Cancellable::NONE)?;
         let dn = attributes.attribute_as_string("standard::display-name").ok_or(NewProjectError::MissingDisplayName)?;
-        let size = attributes.size();
+        let space: space::AddressSpace = if config::INSTANCE.borrow().map_files {
+            let mas = space::mmap::MmapAddressSpace::new(file.path().unwrap(), &dn);
+            mas.try_open()?;
+            mas.into()
+        } else {
+            let fas = space::file::FileAddressSpace::new(file.path().unwrap(), &dn);
+            fas.try_open()?;
+            fas.into()
+        };
+
+        /* trust the space over gio, in case the file changed in between */
+        let size = match space.size() {
+            Some(size) => size,
+            None => u64::try_from(attributes.size())?,
+        };
+
+        Ok((space, dn, size))
+    }
 
-        let fas = space::file::FileAddressSpace::new(file.path().unwrap(), &dn);
-        fas.try_open()?;
+    fn new_project_from_file(file: &gio::File) -> Result<document::Document, NewProjectError> {
+        let (space, dn, size) = Self::open_file_space(file)?;
         
-        let space = std::sync::Arc::new(fas.into());
+        let space = std::sync::Arc::new(space);
 
         let root = sync::Arc::new(structure::Node {
             props: structure::Properties {
@@ -105,7 +146,7 @@ impl NewProjectAction {
                 content_display: structure::ContentDisplay::default(),
                 locked: true,
             },
-            size: u64::try_from(size)?.into(),
+            size: size.into(),
             children: vec::Vec::new(),
         });
 
@@ -113,7 +154,65 @@ impl NewProjectAction {
             .load_space(space)
             .build())
     }
+
+    fn new_project_from_files_concatenated(files: &[gio::File]) -> Result<document::Document, NewProjectError> {
+        let mut pieces = vec::Vec::new();
+        let mut names = vec::Vec::new();
+
+        for file in files {
+            let (space, dn, size) = Self::open_file_space(file)?;
+            pieces.push((sync::Arc::new(space), size));
+            names.push(dn.to_string());
+        }
+
+        let label = names.join(" + ");
+        let cas = space::concat::ConcatAddressSpace::new(pieces, &label);
+
+        /* give each file its own node so it's obvious where the boundaries are */
+        let children = cas.pieces().iter().zip(names).map(|(piece, name)| structure::Childhood::new(sync::Arc::new(structure::Node {
+            props: structure::Properties {
+                name,
+                title_display: structure::TitleDisplay::default(),
+                children_display: structure::ChildrenDisplay::default(),
+                content_display: structure::ContentDisplay::default(),
+                locked: false,
+            },
+            size: piece.size.into(),
+            children: vec::Vec::new(),
+        }), piece.offset.into())).collect();
+
+        let root = sync::Arc::new(structure::Node {
+            props: structure::Properties {
+                name: label,
+                title_display: structure::TitleDisplay::default(),
+                children_display: structure::ChildrenDisplay::default(),
+                content_display: structure::ContentDisplay::default(),
+                locked: true,
+            },
+            size: cas.size().unwrap_or(0).into(),
+            children,
+        });
+
+        Ok(document::Builder::new(root)
+            .load_space(sync::Arc::new(cas.into()))
+            .build())
+    }
     
+    fn report_error(window: &window::CharmWindow, e: NewProjectError) {
+        window.report_error(error::Error {
+            while_attempting: error::Action::NewProjectFromFile,
+            trouble: match e {
+                NewProjectError::GlibError(e) => error::Trouble::GlibIoError(e),
+                NewProjectError::IoError(e) => error::Trouble::StdIoError(e),
+                NewProjectError::MissingDisplayName => error::Trouble::Other("Unable to retrieve display name for file".to_string()),
+                NewProjectError::NonNativeFile => error::Trouble::Other("Unable to create project from non-native file".to_string()),
+                NewProjectError::InvalidSize(_) => error::Trouble::Other("Unable to get size of file".to_string()),
+            },
+            level: error::Level::Error,
+            is_bug: false,
+        });
+    }
+
     fn file_chooser_response(&self, response_type: gtk::ResponseType) {
         self.file_chooser.hide();
 
@@ -126,22 +225,35 @@ impl NewProjectAction {
 
                     match Self::new_project_from_file(&file.downcast().expect("file chooser files should be gio::File instances")) {
                         Ok(doc) => window.open_project(project::Project::new_unsaved(doc), false, true),
-                        Err(e) => window.report_error(error::Error {
-                            while_attempting: error::Action::NewProjectFromFile,
-                            trouble: match e {
-                                NewProjectError::GlibError(e) => error::Trouble::GlibIoError(e),
-                                NewProjectError::IoError(e) => error::Trouble::StdIoError(e),
-                                NewProjectError::MissingDisplayName => error::Trouble::Other("Unable to retrieve display name for file".to_string()),
-                                NewProjectError::NonNativeFile => error::Trouble::Other("Unable to create project from non-native file".to_string()),
-                                NewProjectError::InvalidSize(_) => error::Trouble::Other("Unable to get size of file".to_string()),
-                            },
-                            level: error::Level::Error,
-                            is_bug: false,
-                        }),
+                        Err(e) => Self::report_error(&window, e),
                     };
                 }
             },
             _ => {} /* we were cancelled, ignore */
         }
     }
+
+    fn concat_chooser_response(&self, response_type: gtk::ResponseType) {
+        self.concat_chooser.hide();
+
+        let Some(window) = self.window.upgrade() else { return };
+
+        if response_type != gtk::ResponseType::Accept {
+            /* we were cancelled, ignore */
+            return;
+        }
+
+        let files: vec::Vec<gio::File> = self.concat_chooser.files().iter::<gio::File>()
+            .map(|file| file.expect("list model should not be modified during iteration"))
+            .collect();
+
+        if files.is_empty() {
+            return;
+        }
+
+        match Self::new_project_from_files_concatenated(&files) {
+            Ok(doc) => window.open_project(project::Project::new_unsaved(doc), false, true),
+            Err(e) => Self::report_error(&window, e),
+        }
+    }
 }
@@ -0,0 +1,155 @@
+use gtk::prelude::*;
+use gtk::glib;
+use gtk::glib::clone;
+
+use std::cell;
+use std::rc;
+use std::sync;
+use std::vec;
+
+use crate::catch_panic;
+use crate::model::addr;
+use crate::model::document;
+use crate::model::document::structure;
+use crate::model::space;
+use crate::model::space::gdb;
+use crate::view::error;
+use crate::view::helpers;
+use crate::view::project;
+use crate::view::window;
+use crate::view::window::ErrorReporter;
+
+struct NewProjectFromGdbAction {
+    window: rc::Weak<window::CharmWindow>,
+
+    dialog: gtk::ApplicationWindow,
+    entry: gtk::Entry,
+
+    busy: cell::Cell<bool>,
+}
+
+pub fn add_action(window: &rc::Rc<window::CharmWindow>) {
+    let builder = gtk::Builder::from_string(include_str!("gdb-target.ui"));
+
+    let dialog = gtk::ApplicationWindow::builder()
+        .application(&window.application.application)
+        .child(&builder.object::<gtk::Widget>("toplevel").unwrap())
+        .resizable(false)
+        .title("Charm: Connect to GDB Target")
+        .transient_for(&window.window)
+        .modal(true)
+        .hide_on_close(true)
+        .destroy_with_parent(true)
+        .default_widget(&builder.object::<gtk::Widget>("connect_button").unwrap())
+        .build();
+
+    let action = rc::Rc::new(NewProjectFromGdbAction {
+        window: rc::Rc::downgrade(window),
+        dialog,
+        entry: builder.object("entry").unwrap(),
+
+        busy: cell::Cell::new(false),
+    });
+
+    action.entry.connect_activate(clone!(#[weak] action, move |_| catch_panic! {
+        action.connect();
+    }));
+
+    helpers::bind_simple_action(&action, &action.dialog, "cancel", |action| {
+        action.dialog.hide();
+    });
+
+    helpers::bind_simple_action(&action, &action.dialog, "connect", |action| {
+        action.connect();
+    });
+
+    window.window.add_action(&helpers::create_simple_action_strong(action, "new_project.from_gdb", |action| action.activate()));
+}
+
+impl NewProjectFromGdbAction {
+    fn activate(&self) {
+        if self.busy.get() {
+            return;
+        }
+
+        self.entry.grab_focus();
+        self.dialog.present();
+    }
+
+    fn connect(self: &rc::Rc<Self>) {
+        let text = self.entry.text();
+        if text.is_empty() {
+            return;
+        }
+
+        self.dialog.hide();
+
+        let Some(window) = self.window.upgrade() else { return };
+        let target = gdb::Target::parse(text.as_str());
+
+        self.busy.set(true);
+
+        /* connecting to a board that isn't there takes a while to fail */
+        let task = window.application.rt.spawn_blocking(clone!(#[strong] target, move || new_project_from_gdb(target)));
+
+        let action = rc::Rc::downgrade(self);
+        glib::MainContext::default().spawn_local(async move {
+            let result = task.await;
+
+            if let Some(action) = action.upgrade() {
+                catch_panic! {
+                    action.finish(target, result);
+                }
+            }
+        });
+    }
+
+    fn finish(&self, target: gdb::Target, result: Result<Result<document::Document, std::io::Error>, tokio::task::JoinError>) {
+        self.busy.set(false);
+
+        let Some(window) = self.window.upgrade() else { return };
+
+        match result {
+            Ok(Ok(doc)) => window.open_project(project::Project::new_unsaved(doc), false, true),
+            Ok(Err(e)) => window.report_error(error::Error {
+                while_attempting: error::Action::NewProjectFromGdb,
+                trouble: error::Trouble::OpenAddressSpaceError {
+                    error: e,
+                    path: std::path::PathBuf::from(target.to_string()),
+                },
+                level: error::Level::Error,
+                is_bug: false,
+            }),
+            Err(error) => window.report_error(error::Error {
+                while_attempting: error::Action::NewProjectFromGdb,
+                trouble: error::Trouble::Other(format!("Connection task failed: {}", error)),
+                level: error::Level::Error,
+                is_bug: true,
+            }),
+        }
+    }
+}
+
+fn new_project_from_gdb(target: gdb::Target) -> Result<document::Document, std::io::Error> {
+    let label = format!("gdb {}", target);
+
+    let gas = gdb::GdbAddressSpace::new(target, &label);
+    gas.try_open()?;
+
+    /* we have no idea how big the target's address space is */
+    let root = sync::Arc::new(structure::Node {
+        props: structure::Properties {
+            name: label,
+            title_display: structure::TitleDisplay::Major,
+            children_display: structure::ChildrenDisplay::Full,
+            content_display: structure::ContentDisplay::default(),
+            locked: true,
+        },
+        size: addr::unit::MAX,
+        children: vec::Vec::new(),
+    });
+
+    Ok(document::Builder::new(root)
+        .load_space(sync::Arc::new(space::AddressSpace::from(gas)))
+        .build())
+}
@@ -0,0 +1,200 @@
+use gtk::prelude::*;
+use gtk::glib;
+use gtk::glib::clone;
+
+use std::cell;
+use std::rc;
+use std::sync;
+use std::vec;
+
+use crate::catch_panic;
+use crate::model::addr;
+use crate::model::document;
+use crate::model::document::structure;
+use crate::model::space;
+use crate::model::space::AddressSpaceExt;
+use crate::model::space::process;
+use crate::view::error;
+use crate::view::helpers;
+use crate::view::project;
+use crate::view::window;
+use crate::view::window::ErrorReporter;
+
+struct NewProjectFromProcessAction {
+    window: rc::Weak<window::CharmWindow>,
+
+    dialog: gtk::ApplicationWindow,
+    entry: gtk::SearchEntry,
+    list: gtk::ListBox,
+    populate_mappings: gtk::CheckButton,
+
+    processes: cell::RefCell<vec::Vec<process::ProcessInfo>>,
+    /// The processes that are currently shown in the list, in the same order as the rows.
+    shown: cell::RefCell<vec::Vec<process::ProcessInfo>>,
+}
+
+pub fn add_action(window: &rc::Rc<window::CharmWindow>) {
+    let builder = gtk::Builder::from_string(include_str!("process-picker.ui"));
+
+    let list: gtk::ListBox = builder.object("list").unwrap();
+
+    let dialog = gtk::ApplicationWindow::builder()
+        .application(&window.application.application)
+        .child(&builder.object::<gtk::Widget>("toplevel").unwrap())
+        .resizable(true)
+        .title("Charm: Attach to Process")
+        .transient_for(&window.window)
+        .modal(true)
+        .hide_on_close(true)
+        .destroy_with_parent(true)
+        .default_widget(&builder.object::<gtk::Widget>("attach_button").unwrap())
+        .build();
+
+    let action = rc::Rc::new(NewProjectFromProcessAction {
+        window: rc::Rc::downgrade(window),
+        dialog,
+        entry: builder.object("entry").unwrap(),
+        list,
+        populate_mappings: builder.object("populate_mappings").unwrap(),
+
+        processes: cell::RefCell::new(vec::Vec::new()),
+        shown: cell::RefCell::new(vec::Vec::new()),
+    });
+
+    action.entry.connect_search_changed(clone!(#[weak] action, move |_| catch_panic! {
+        action.refresh_list();
+    }));
+
+    action.entry.connect_activate(clone!(#[weak] action, move |_| catch_panic! {
+        action.attach();
+    }));
+
+    action.list.connect_row_activated(clone!(#[weak] action, move |_, _| catch_panic! {
+        action.attach();
+    }));
+
+    helpers::bind_simple_action(&action, &action.dialog, "cancel", |action| {
+        action.dialog.hide();
+    });
+
+    helpers::bind_simple_action(&action, &action.dialog, "attach", |action| {
+        action.attach();
+    });
+
+    window.window.add_action(&helpers::create_simple_action_strong(action, "new_project.from_process", |action| action.activate()));
+}
+
+impl NewProjectFromProcessAction {
+    fn activate(&self) {
+        match process::list_processes() {
+            Ok(processes) => *self.processes.borrow_mut() = processes,
+            Err(e) => {
+                self.report_error(error::Trouble::StdIoError(e));
+                return;
+            },
+        }
+
+        self.entry.set_text("");
+        self.refresh_list();
+        self.entry.grab_focus();
+
+        self.dialog.present();
+    }
+
+    fn refresh_list(&self) {
+        let filter = self.entry.text().to_lowercase();
+
+        let mut shown = self.shown.borrow_mut();
+        *shown = self.processes.borrow().iter().filter(|p| {
+            filter.is_empty() || p.name.to_lowercase().contains(&filter) || p.pid.to_string().starts_with(&filter)
+        }).cloned().collect();
+
+        while let Some(row) = self.list.row_at_index(0) {
+            self.list.remove(&row);
+        }
+
+        for p in shown.iter() {
+            self.list.append(&gtk::Label::builder()
+                             .label(format!("{:>7}  {}", p.pid, p.name))
+                             .halign(gtk::Align::Start)
+                             .css_classes(["monospace"])
+                             .build());
+        }
+
+        if let Some(row) = self.list.row_at_index(0) {
+            self.list.select_row(Some(&row));
+        }
+    }
+
+    fn attach(&self) {
+        let Some(index) = self.list.selected_row().map(|row| row.index()) else { return };
+        let Some(info) = usize::try_from(index).ok().and_then(|i| self.shown.borrow().get(i).cloned()) else { return };
+
+        self.dialog.hide();
+
+        let Some(window) = self.window.upgrade() else { return };
+
+        match new_project_from_process(&info, self.populate_mappings.is_active()) {
+            Ok(doc) => window.open_project(project::Project::new_unsaved(doc), false, true),
+            Err(e) => self.report_error(error::Trouble::OpenAddressSpaceError {
+                error: e,
+                path: std::path::PathBuf::from(format!("/proc/{}/mem", info.pid)),
+            }),
+        }
+    }
+
+    fn report_error(&self, trouble: error::Trouble) {
+        let Some(window) = self.window.upgrade() else { return };
+
+        window.report_error(error::Error {
+            while_attempting: error::Action::NewProjectFromProcess,
+            trouble,
+            level: error::Level::Error,
+            is_bug: false,
+        });
+    }
+}
+
+fn mapping_node(mapping: &process::Mapping) -> structure::Childhood {
+    structure::Childhood::new(sync::Arc::new(structure::Node {
+        props: structure::Properties {
+            name: format!("{} {}", mapping.pathname.as_deref().unwrap_or("[anonymous]"), mapping.permissions()),
+            title_display: structure::TitleDisplay::default(),
+            children_display: structure::ChildrenDisplay::default(),
+            content_display: structure::ContentDisplay::default(),
+            locked: false,
+        },
+        size: (mapping.end - mapping.start).into(),
+        children: vec::Vec::new(),
+    }), mapping.start.into())
+}
+
+fn new_project_from_process(info: &process::ProcessInfo, populate_mappings: bool) -> Result<document::Document, std::io::Error> {
+    let label = format!("{} ({})", info.name, info.pid);
+
+    let pas = process::ProcessAddressSpace::new(info.pid, &label);
+    pas.try_open()?;
+
+    let mappings = pas.mappings()?;
+    let size = pas.size().unwrap_or(0);
+
+    let root = sync::Arc::new(structure::Node {
+        props: structure::Properties {
+            name: label,
+            title_display: structure::TitleDisplay::Major,
+            children_display: structure::ChildrenDisplay::Full,
+            content_display: structure::ContentDisplay::default(),
+            locked: true,
+        },
+        size: addr::Size::from(size),
+        children: if populate_mappings {
+            mappings.iter().map(mapping_node).collect()
+        } else {
+            vec::Vec::new()
+        },
+    });
+
+    Ok(document::Builder::new(root)
+        .load_space(sync::Arc::new(space::AddressSpace::from(pas)))
+        .build())
+}
@@ -76,21 +76,8 @@ fn try_open_project(window: &rc::Rc<window::CharmWindow>, project_file: gio::Fil
 
     /* Open any FileAddressSpaces that don't try to get opened during deserialization. */
     for filter in &document.datapath {
-        match filter {
-            datapath::Filter::LoadSpace(lsf) => match &**lsf.space() {
-                space::AddressSpace::File(f) => match f.try_open() {
-                    Ok(_) => {},
-                    Err(e) => window.report_error(error::Error {
-                        while_attempting: error::Action::OpenProject,
-                        trouble: error::Trouble::OpenAddressSpaceError {
-                            error: e,
-                            path: f.path.clone(),
-                        },
-                        level: error::Level::Error,
-                        is_bug: false,
-                    }),
-                },
-            },
+        match filter.inner() {
+            datapath::Filter::LoadSpace(lsf) => open_space(window, lsf.space()),
             _ => {}
         }
     }
@@ -100,6 +87,39 @@ fn try_open_project(window: &rc::Rc<window::CharmWindow>, project_file: gio::Fil
     Ok(())
 }
 
+fn open_space(window: &rc::Rc<window::CharmWindow>, space: &space::AddressSpace) {
+    let (result, path) = match space {
+        space::AddressSpace::File(f) => (f.try_open().map(|_| ()), f.path.clone()),
+        space::AddressSpace::Mmap(f) => (f.try_open().map(|_| ()), f.path.clone()),
+        /* the process probably isn't around anymore, or has a different pid */
+        space::AddressSpace::Process(p) => (p.try_open().map(|_| ()), std::path::PathBuf::from(format!("/proc/{}/mem", p.pid))),
+        space::AddressSpace::Buffer(_) => return,
+        /* connecting blocks for a while if the target isn't there, so leave it to the first fetch */
+        space::AddressSpace::Gdb(g) => {
+            g.open_deferred();
+            return;
+        },
+        space::AddressSpace::Concat(c) => {
+            for piece in c.pieces() {
+                open_space(window, &piece.space);
+            }
+            return;
+        },
+    };
+
+    if let Err(e) = result {
+        window.report_error(error::Error {
+            while_attempting: error::Action::OpenProject,
+            trouble: error::Trouble::OpenAddressSpaceError {
+                error: e,
+                path,
+            },
+            level: error::Level::Error,
+            is_bug: false,
+        });
+    }
+}
+
 impl OpenProjectAction {
     fn activate(&self) {
         self.dialog.show();
@@ -0,0 +1,123 @@
+use std::cell;
+use std::rc;
+use std::sync;
+
+use gtk::prelude::*;
+use gtk::glib;
+
+use crate::catch_panic;
+use crate::model::datapath::write_back;
+use crate::model::document;
+use crate::view::error;
+use crate::view::helpers;
+use crate::view::window;
+use crate::view::window::ErrorReporter;
+
+struct SaveInPlaceAction {
+    window: rc::Weak<window::CharmWindow>,
+    document_host: sync::Arc<document::DocumentHost>,
+
+    /* so a second save doesn't race the first one for the backup name */
+    busy: cell::Cell<bool>,
+}
+
+pub fn add_action(window_context: &window::WindowContext) {
+    let action = rc::Rc::new(SaveInPlaceAction {
+        window: window_context.window.clone(),
+        document_host: window_context.project.document_host.clone(),
+
+        busy: cell::Cell::new(false),
+    });
+
+    window_context.action_group.add_action(&helpers::create_simple_action_strong(action, "save_in_place", |action| action.activate()));
+}
+
+impl SaveInPlaceAction {
+    fn activate(self: &rc::Rc<Self>) {
+        let window = match self.window.upgrade() {
+            Some(w) => w,
+            None => return,
+        };
+
+        if self.busy.get() {
+            return;
+        }
+
+        let document = self.document_host.get();
+
+        let plan = match write_back::Plan::new(&document.datapath) {
+            Ok(plan) => plan,
+            Err(error) => {
+                window.report_error(error::Error {
+                    while_attempting: error::Action::SaveInPlace,
+                    trouble: error::Trouble::WriteBackFailed { error, backup: None },
+                    level: error::Level::Error,
+                    is_bug: false,
+                });
+                return;
+            },
+        };
+
+        if plan.is_empty() {
+            return;
+        }
+
+        /* Made against the document the plan came from, so that if anything else touches the datapath while we're
+         * writing, this fails instead of throwing those edits away. */
+        let change = document.replace_datapath(plan.collapsed());
+        let backup = write_back::backup_path_for(plan.path());
+
+        self.busy.set(true);
+
+        let task = window.application.rt.spawn_blocking({
+            let backup = backup.clone();
+            move || plan.execute(&backup)
+        });
+
+        let action = rc::Rc::downgrade(self);
+        glib::MainContext::default().spawn_local(async move {
+            let result = task.await;
+
+            if let Some(action) = action.upgrade() {
+                catch_panic! {
+                    action.finish(result, change, backup);
+                }
+            }
+        });
+    }
+
+    fn finish(&self, result: Result<Result<(), write_back::WriteBackError>, tokio::task::JoinError>, change: document::change::Change, backup: std::path::PathBuf) {
+        self.busy.set(false);
+
+        let error = match result {
+            Ok(Ok(())) => match self.document_host.change(change) {
+                Ok(_) => return,
+                Err((error, attempted_version)) => error::Error {
+                    while_attempting: error::Action::CollapseDatapath,
+                    trouble: error::Trouble::DocumentUpdateFailure {
+                        error,
+                        attempted_version,
+                    },
+                    level: error::Level::Warning,
+                    is_bug: false,
+                },
+            },
+            Ok(Err(error)) => error::Error {
+                while_attempting: error::Action::SaveInPlace,
+                trouble: error::Trouble::WriteBackFailed { error, backup: Some(backup) },
+                level: error::Level::Error,
+                is_bug: false,
+            },
+            Err(error) => error::Error {
+                while_attempting: error::Action::SaveInPlace,
+                trouble: error::Trouble::Other(format!("Save task failed: {}", error)),
+                level: error::Level::Error,
+                is_bug: true,
+            },
+        };
+
+        if let Some(window) = self.window.upgrade() {
+            window.report_error(error);
+        }
+    }
+}
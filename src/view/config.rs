@@ -222,6 +222,14 @@ macro_rules! declare_config {
 
 declare_config![Config {
     file_access_delay: u64 = 0, /* milliseconds */
+    map_files: bool = false, /* open new projects with a memory-mapped address space */
+
+    #[bind("lock-snapshots")]
+    lock_snapshots: bool = false, /* don't reload files that change on disk */
+    
+    cache_block_size: u64 = 0x1000, /* bytes, for new projects */
+    cache_block_count: usize = 1024, /* for new projects */
+    cache_max_in_flight: usize = 8, /* fetches, past which we stop prefetching */
     
     lookahead: usize = 20, /* lines */
 
@@ -265,6 +273,12 @@ declare_config![Config {
     #[bind("color-placeholder")]
     placeholder_color: Color = color!("141414ff"),
 
+    #[bind("color-error")]
+    error_color: Color = color!("c01c28ff", "f66151ff"),
+
+    #[bind("color-unmapped")]
+    unmapped_color: Color = color!("9a9996ff", "5e5c64ff"),
+
     #[bind("color-selection")]
     selection_color: Color = color!("8888ff60"),
 
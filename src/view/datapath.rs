@@ -1,12 +1,17 @@
 use std::sync;
 
+use crate::catch_panic;
+use crate::model::addr;
 use crate::model::datapath;
 use crate::model::document;
 use crate::view::helpers;
 
 use gtk::prelude::*;
+use gtk::gio;
 use gtk::glib;
+use gtk::glib::clone;
 
+/* Columns: type, address, size, description, enabled, index into the datapath, whether the address can be edited. */
 pub fn create_model(document_host: sync::Arc<document::DocumentHost>) -> (gtk::TreeModel, helpers::AsyncSubscriber) {
     let document = document_host.get();
 
@@ -15,10 +20,13 @@ pub fn create_model(document_host: sync::Arc<document::DocumentHost>) -> (gtk::T
         glib::types::Type::STRING,
         glib::types::Type::STRING,
         glib::types::Type::STRING,
+        glib::types::Type::BOOL,
+        glib::types::Type::U64,
+        glib::types::Type::BOOL,
     ]);
 
     update_store(&store, &document);
-    
+
     let subscriber = helpers::subscribe_to_updates(store.downgrade(), document_host, document, move |store, new_document| {
         update_store(&store, &new_document);
     });
@@ -26,22 +34,166 @@ pub fn create_model(document_host: sync::Arc<document::DocumentHost>) -> (gtk::T
     (store.upcast(), subscriber)
 }
 
+/// Sets up the columns and context menu of the datapath editor. Edits go through the `ctx.datapath.*` actions.
+pub fn build_columns(tree_view: &gtk::TreeView) {
+    {
+        let crt = gtk::CellRendererToggle::new();
+        crt.connect_toggled(clone!(#[weak] tree_view, move |_, path| catch_panic! {
+            if let Some(index) = filter_index(&tree_view, &path) {
+                let _ = tree_view.activate_action("ctx.datapath.toggle_filter", Some(&index.to_variant()));
+            }
+        }));
+        let c = gtk::TreeViewColumn::new();
+        c.pack_start(&crt, false);
+        c.add_attribute(&crt, "active", 4);
+        c.set_title("Enabled");
+        tree_view.append_column(&c);
+    }
+    {
+        let crt = gtk::CellRendererText::new();
+        let c = gtk::TreeViewColumn::new();
+        c.pack_start(&crt, true);
+        c.add_attribute(&crt, "text", 0);
+        c.set_title("Type");
+        tree_view.append_column(&c);
+    }
+    {
+        let crt = gtk::CellRendererText::new();
+        crt.connect_edited(clone!(#[weak] tree_view, move |_, path, text| catch_panic! {
+            if let Some(index) = filter_index(&tree_view, &path) {
+                let _ = tree_view.activate_action("ctx.datapath.set_filter_address", Some(&(index, text.to_string()).to_variant()));
+            }
+        }));
+        let c = gtk::TreeViewColumn::new();
+        c.pack_start(&crt, true);
+        c.add_attribute(&crt, "text", 1);
+        c.add_attribute(&crt, "editable", 6);
+        c.set_title("Address");
+        tree_view.append_column(&c);
+    }
+    {
+        let crt = gtk::CellRendererText::new();
+        let c = gtk::TreeViewColumn::new();
+        c.pack_start(&crt, true);
+        c.add_attribute(&crt, "text", 2);
+        c.set_title("Size");
+        tree_view.append_column(&c);
+    }
+    {
+        let crt = gtk::CellRendererText::new();
+        let c = gtk::TreeViewColumn::new();
+        c.pack_start(&crt, true);
+        c.add_attribute(&crt, "text", 3);
+        c.set_title("Description");
+        tree_view.append_column(&c);
+    }
+
+    let popover = gtk::PopoverMenu::from_model(gio::MenuModel::NONE);
+    popover.set_parent(tree_view);
+
+    let gesture = gtk::GestureClick::new();
+    gesture.connect_pressed(clone!(#[weak] tree_view, move |gesture, n_press, x, y| catch_panic! {
+        let seq = gesture.current_sequence();
+        let event = gesture.last_event(seq.as_ref()).unwrap();
+
+        if n_press != 1 {
+            return;
+        }
+
+        if !event.triggers_context_menu() {
+            return;
+        }
+
+        let (bx, by) = tree_view.convert_widget_to_bin_window_coords(x as i32, y as i32);
+        let Some((Some(path), _, _, _)) = tree_view.path_at_pos(bx, by) else { return };
+        let Some(model) = tree_view.model() else { return };
+        let Some(iter) = model.iter(&path) else { return };
+
+        gesture.set_state(gtk::EventSequenceState::Claimed);
+
+        popover.set_menu_model(Some(&row_menu(model.get::<u64>(&iter, 5), model.get::<bool>(&iter, 4))));
+        popover.set_pointing_to(Some(&gtk::gdk::Rectangle::new(x as i32, y as i32, 1, 1)));
+        popover.popup();
+    }));
+    gesture.set_exclusive(true);
+    gesture.set_button(0);
+    tree_view.add_controller(gesture);
+}
+
+/// The index into the datapath of the filter shown in a row.
+pub fn filter_index(tree_view: &gtk::TreeView, path: &gtk::TreePath) -> Option<u64> {
+    let model = tree_view.model()?;
+    let iter = model.iter(path)?;
+    Some(model.get::<u64>(&iter, 5))
+}
+
+/// The bytes that the filter at `index` has an effect on, for highlighting in the listing. Filters that go on forever
+/// are cut off at the end of the document.
+pub fn filter_extent(document: &document::Document, index: usize) -> Option<addr::Extent> {
+    let filter = document.datapath.get(index)?;
+    let begin = filter.human_affects_addr();
+    let end = match filter.human_affects_size() {
+        Some(size) => begin.saturating_add(size),
+        None => std::cmp::max(begin, document.root.size.bytes),
+    };
+
+    Some(addr::Extent::between(begin, end))
+}
+
+/* The list shows the top of the datapath first, so "up" is towards the end of the datapath. */
+fn row_menu(index: u64, enabled: bool) -> gio::Menu {
+    let menu = gio::Menu::new();
+
+    for (label, action) in [
+        (if enabled { "Disable" } else { "Enable" }, "ctx.datapath.toggle_filter"),
+        ("Move Up", "ctx.datapath.raise_filter"),
+        ("Move Down", "ctx.datapath.lower_filter"),
+        ("Remove", "ctx.datapath.remove_filter"),
+    ] {
+        let item = gio::MenuItem::new(Some(label), None);
+        item.set_action_and_target_value(Some(action), Some(&index.to_variant()));
+        menu.append_item(&item);
+    }
+
+    menu
+}
+
+fn label(filter: &datapath::Filter) -> &'static str {
+    match filter {
+        datapath::Filter::LoadSpace(_) => "Load Address Space",
+        datapath::Filter::Overwrite(_) => "Overwrite Bytes",
+        datapath::Filter::Move(_) => "Move Bytes",
+        datapath::Filter::Insert(_) => "Insert Bytes",
+        datapath::Filter::Transform(_) => "Transform Bytes",
+        datapath::Filter::Decompress(_) => "Decompress",
+        datapath::Filter::Delete(_) => "Delete Bytes",
+        datapath::Filter::Disabled(f) => label(&f.filter),
+    }
+}
+
 fn update_store(store: &gtk::ListStore, document: &sync::Arc<document::Document>) {
-    store.clear();
-    
-    // TODO: only adjust difference
-    for filter in document.datapath.iter().rev() {
-        let i = store.append();
+    /* Rows are reused instead of rebuilt so that the selection and scroll position survive edits to the datapath. */
+    let mut row = store.iter_first();
+
+    for (index, filter) in document.datapath.iter().enumerate().rev() {
+        let i = row.take().unwrap_or_else(|| store.append());
         store.set(&i, &[
-            (0, match filter {
-                datapath::Filter::LoadSpace(_) => &"Load Address Space",
-                datapath::Filter::Overwrite(_) => &"Overwrite Bytes",
-                datapath::Filter::Move(_) => &"Move Bytes",
-                datapath::Filter::Insert(_) => &"Insert Bytes",
-            }),
+            (0, &label(filter)),
             (1, &format!("0x{:x}", filter.human_affects_addr())),
             (2, &filter.human_affects_size().map(|size| format!("0x{:x}", size)).unwrap_or_else(|| "Infinite".to_string())),
-            (3, &filter.human_details())
+            (3, &filter.human_details()),
+            (4, &filter.is_enabled()),
+            (5, &(index as u64)),
+            (6, &filter.relocated(filter.human_affects_addr()).is_some()),
         ]);
+
+        if store.iter_next(&i) {
+            row = Some(i);
+        }
+    }
+
+    /* the datapath got shorter */
+    if let Some(i) = row {
+        while store.remove(&i) {}
     }
 }
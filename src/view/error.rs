@@ -3,6 +3,7 @@ use std::fmt::Write;
 use std::sync;
 
 use crate::model;
+use crate::model::datapath;
 use crate::model::document;
 use crate::model::selection;
 use crate::serialization;
@@ -17,6 +18,7 @@ pub enum Action {
     /* User-facing actions */
     DeleteNode,
     DeleteNodesInListing,
+    DeleteBytesInListing,
     DestructureNode,
     InsertNodeParseOffset,
     InsertNodeParseSize,
@@ -24,6 +26,9 @@ pub enum Action {
     NestNodesInListing,
     Nest, /* in tree view */
     EditProperties,
+    EnterData,
+    Undo,
+    Redo,
 
     ModifyTreeSelection,
     RubberBandSelection,
@@ -31,7 +36,17 @@ pub enum Action {
     SaveProject,
     OpenProject,
     NewProjectFromFile,
+    NewProjectFromProcess,
+    NewProjectFromGdb,
     SaveRecoveredDocument,
+    WatchBackingFiles,
+    ExportData,
+    ExportPatch,
+    ImportPatch,
+    SaveInPlace,
+    CollapseDatapath,
+    EditDatapath,
+    SelectFilterInListing,
 }
 
 pub enum Trouble {
@@ -64,6 +79,18 @@ pub enum Trouble {
         selection: sync::Arc<selection::ListingSelection>,
     },
     NoNodesInSelection,
+    BackingFilesChanged {
+        labels: Vec<String>,
+        reloaded: bool,
+    },
+    ExportFailed(datapath::export::ExportError),
+    PatchFailed(datapath::patch::PatchError),
+    PatchImportFailed(datapath::patch::ImportError),
+    WriteBackFailed {
+        error: datapath::write_back::WriteBackError,
+        /* None if we didn't get far enough to make one */
+        backup: Option<std::path::PathBuf>,
+    },
     
     Other(String),
 }
@@ -89,6 +116,7 @@ impl Error {
 
             Action::DeleteNode => "Failed to delete node.",
             Action::DeleteNodesInListing => "Failed to delete nodes.",
+            Action::DeleteBytesInListing => "Failed to delete bytes.",
             Action::DestructureNode => "Failed to destructure node.",
             Action::InsertNodeParseOffset => "Failed to parse offset.",
             Action::InsertNodeParseSize => "Failed to parse size.",
@@ -96,6 +124,9 @@ impl Error {
             Action::NestNodesInListing => "Failed to nest nodes.",
             Action::Nest => "Failed to nest nodes.",
             Action::EditProperties => "Failed to edit node properties.",
+            Action::EnterData => "Failed to edit data.",
+            Action::Undo => "Failed to undo.",
+            Action::Redo => "Failed to redo.",
 
             Action::ModifyTreeSelection => "Failed to modify tree selection.",
             Action::RubberBandSelection => "Failed to rubber-band select.",
@@ -103,7 +134,17 @@ impl Error {
             Action::SaveProject => "Failed to save project.",
             Action::OpenProject => "Failed to open project.",
             Action::NewProjectFromFile => "Failed to create new project from file.",
+            Action::NewProjectFromProcess => "Failed to create new project from process.",
+            Action::NewProjectFromGdb => "Failed to create new project from GDB target.",
             Action::SaveRecoveredDocument => "Failed to save recovered document.",
+            Action::WatchBackingFiles => "A file changed on disk.",
+            Action::ExportData => "Failed to export data.",
+            Action::ExportPatch => "Failed to export patch.",
+            Action::ImportPatch => "Failed to import patch.",
+            Action::SaveInPlace => "Failed to save edits to file.",
+            Action::CollapseDatapath => "Saved edits to file, but failed to simplify the datapath.",
+            Action::EditDatapath => "Failed to edit datapath.",
+            Action::SelectFilterInListing => "Failed to select the filter's bytes in the listing.",
         }.to_string()
     }
 
@@ -131,6 +172,8 @@ impl Error {
                             document::change::UpdateError::NotYetImplemented => write!(msg, "This type of change can't automatically update itself to newer versions of the document yet because it hasn't been implemented.")?,
                             document::change::UpdateError::NodeDeleted => write!(msg, "A node referenced by this change was been deleted.")?,
                             document::change::UpdateError::RangeSplit => write!(msg, "The range of nodes this change was meant to affect got split up.")?,
+                            document::change::UpdateError::FilterRemoved => write!(msg, "A datapath filter referenced by this change was removed.")?,
+                            document::change::UpdateError::FilterReplaced => write!(msg, "A datapath filter referenced by this change was modified.")?,
                         };
                         write!(msg, "\n")?;
                         if let Some(incompatible_change) = &incompatible_change {
@@ -208,6 +251,41 @@ impl Error {
                 write!(msg, "No nodes in current selection.\n")?
             },
 
+            Trouble::BackingFilesChanged { labels, reloaded } => {
+                for label in labels {
+                    write!(msg, "'{}' was modified or replaced by another program.\n", label)?;
+                }
+
+                if *reloaded {
+                    write!(msg, "The new contents have been loaded.\n")?
+                } else {
+                    write!(msg, "Snapshots are locked, so the data that was already read is still being shown. Data that hadn't been read yet may come from the new contents.\n")?
+                }
+            },
+
+            Trouble::ExportFailed(error) => {
+                write!(msg, "{}\n", error)?;
+                write!(msg, "The destination file was left untouched.\n")?
+            },
+
+            Trouble::PatchFailed(error) => {
+                write!(msg, "{}\n", error)?
+            },
+
+            Trouble::PatchImportFailed(error) => {
+                write!(msg, "{}\n", error)?;
+                write!(msg, "Nothing was changed.\n")?
+            },
+
+            Trouble::WriteBackFailed { error, backup } => {
+                write!(msg, "{}\n", error)?;
+
+                match (error, backup) {
+                    (datapath::write_back::WriteBackError::Io(_), Some(backup)) => write!(msg, "The file's original contents were backed up to {}\n", backup.display())?,
+                    _ => write!(msg, "The file was left untouched.\n")?,
+                }
+            },
+
             Trouble::Other(error) => {
                 write!(msg, "{}\n", error)?
             },            
@@ -315,6 +393,68 @@ fn write_document_change_detail(msg: &mut String, document: &document::Document,
             write!(msg, "Delete children under {}\n", SafePathDescription::new(document, &range.parent))?;
             write!(msg, "Indices: {}-{} (inclusive)\n", range.first, range.last)?;
         },
+        document::change::ChangeType::StackFilter { filter } => {
+            write!(msg, "Stack filter onto datapath\n")?;
+            write!(msg, "Filter: {:?}\n", filter)?;
+        },
+        document::change::ChangeType::ReplaceTopFilter { remove, filter } => {
+            write!(msg, "Replace top filter on datapath\n")?;
+            write!(msg, "Remove: {}\n", remove)?;
+            write!(msg, "Filter: {:?}\n", filter)?;
+        },
+        document::change::ChangeType::InsertData { path, offset, bytes } => {
+            write!(msg, "Insert data into {}\n", SafePathDescription::new(document, path))?;
+            write!(msg, "Offset: {}\n", offset)?;
+            write!(msg, "Bytes: {:?}\n", bytes)?;
+        },
+        document::change::ChangeType::RemoveInsertedData { path, offset, length, filter } => {
+            write!(msg, "Remove inserted data from {}\n", SafePathDescription::new(document, path))?;
+            write!(msg, "Offset: {}\n", offset)?;
+            write!(msg, "Length: {:#x}\n", length)?;
+            write!(msg, "Filter: {:?}\n", filter)?;
+        },
+        document::change::ChangeType::DeleteData { path, offset, length } => {
+            write!(msg, "Delete data from {}\n", SafePathDescription::new(document, path))?;
+            write!(msg, "Offset: {}\n", offset)?;
+            write!(msg, "Length: {:#x}\n", length)?;
+        },
+        document::change::ChangeType::RestoreDeletedData { path, offset, length, filter } => {
+            write!(msg, "Restore deleted data to {}\n", SafePathDescription::new(document, path))?;
+            write!(msg, "Offset: {}\n", offset)?;
+            write!(msg, "Length: {:#x}\n", length)?;
+            write!(msg, "Filter: {:?}\n", filter)?;
+        },
+        document::change::ChangeType::AddFilter { index, filter } => {
+            write!(msg, "Add filter to datapath\n")?;
+            write!(msg, "Index: {}\n", index)?;
+            write!(msg, "Filter: {:?}\n", filter)?;
+        },
+        document::change::ChangeType::RemoveFilter { index } => {
+            write!(msg, "Remove filter from datapath\n")?;
+            write!(msg, "Index: {}\n", index)?;
+        },
+        document::change::ChangeType::ReplaceFilter { index, filter } => {
+            write!(msg, "Replace filter in datapath\n")?;
+            write!(msg, "Index: {}\n", index)?;
+            write!(msg, "Filter: {:?}\n", filter)?;
+        },
+        document::change::ChangeType::ReorderFilter { from, to } => {
+            write!(msg, "Move filter in datapath\n")?;
+            write!(msg, "From: {}\n", from)?;
+            write!(msg, "To: {}\n", to)?;
+        },
+        document::change::ChangeType::ReplaceDatapath { datapath } => {
+            write!(msg, "Replace datapath\n")?;
+            for filter in datapath.iter() {
+                write!(msg, "Filter: {:?}\n", filter)?;
+            }
+        },
+        document::change::ChangeType::RewriteDatapath { datapath } => {
+            write!(msg, "Rewrite datapath\n")?;
+            for filter in datapath.iter() {
+                write!(msg, "Filter: {:?}\n", filter)?;
+            }
+        },
     };
 
     Ok(())
@@ -402,6 +402,41 @@ impl StructureListModel {
                 Some((range.first as u32, count_removed as u32, 0))
             },
             change::ChangeType::DeleteRange { .. } => None,
+
+            /* Datapath changes don't affect the hierarchy. */
+            change::ChangeType::StackFilter { .. } => None,
+            change::ChangeType::ReplaceTopFilter { .. } => None,
+            change::ChangeType::AddFilter { .. } => None,
+            change::ChangeType::RemoveFilter { .. } => None,
+            change::ChangeType::ReplaceFilter { .. } => None,
+            change::ChangeType::ReorderFilter { .. } => None,
+            change::ChangeType::ReplaceDatapath { .. } => None,
+            change::ChangeType::RewriteDatapath { .. } => None,
+
+            /* Inserting or removing data can shift any of our children around. */
+            change::ChangeType::InsertData { .. }
+                | change::ChangeType::RemoveInsertedData { .. }
+                | change::ChangeType::DeleteData { .. }
+                | change::ChangeType::RestoreDeletedData { .. } => {
+                let document_host = i.document_host.clone();
+
+                for (index, (child_item, childhood)) in i.children.iter().zip(new_node.children.iter()).enumerate() {
+                    let mut path = i.path.clone();
+                    path.push(index);
+
+                    child_item.stage(NodeInfo {
+                        path,
+                        node: childhood.node.clone(),
+                        props: childhood.node.props.clone(),
+                        offset: childhood.offset,
+                        address: addr + childhood.offset.to_size(),
+                        document: new_doc.clone(),
+                        document_host: document_host.clone(),
+                    });
+                }
+
+                None
+            },
         };
 
         /* Fixup children's paths and node pointers */
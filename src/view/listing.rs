@@ -2,11 +2,13 @@ use std::future;
 use std::pin;
 use std::rc;
 use std::sync;
+use std::string;
 use std::task;
 
 use crate::catch_panic;
 use crate::util;
 use crate::model::addr;
+use crate::model::datapath;
 use crate::model::datapath::DataPathExt;
 use crate::model::document;
 use crate::model::document::structure;
@@ -283,6 +285,7 @@ impl ListingWidget {
         let context_menu = gio::Menu::new();
         context_menu.append(Some("Create node..."), Some("ctx.insert_node"));
         context_menu.append(Some("Delete selected nodes"), Some("ctx.delete_selected_nodes"));
+        context_menu.append(Some("Delete selected bytes"), Some("ctx.delete_selected_bytes"));
         context_menu.freeze();
         
         let mut interior = Interior {
@@ -377,9 +380,17 @@ impl ListingWidget {
         let ec_key = gtk::EventControllerKey::new();
         ec_key.connect_key_pressed(clone!(#[weak(rename_to=lw)] self, #[upgrade_or] glib::Propagation::Proceed, move |_eck, keyval, keycode, modifier| catch_panic! {
             @default(glib::Propagation::Proceed);
+
+            /* This goes through the action so its state stays in sync. Needs to happen without holding the interior lock. */
+            if keyval == gdk::Key::Escape && lw.mode() != facet::cursor::Mode::Command {
+                let _ = lw.activate_action("ctx.mode", Some(&"command".to_variant()));
+                return glib::Propagation::Stop;
+            }
             
             lw.imp().interior.get().unwrap().write().key_pressed(&lw, keyval, keycode, modifier)
         }));
+        /* Need to see keys before our shortcuts do so that hex digits can be entered in entry mode. */
+        ec_key.set_propagation_phase(gtk::PropagationPhase::Capture);
         self.add_controller(ec_key);
 
         /* Register scroll event controller */
@@ -414,6 +425,20 @@ impl ListingWidget {
         }));
         self.add_controller(ec_motion);
 
+        /* Explain where bytes came from, or why they couldn't be read */
+        self.set_has_tooltip(true);
+        self.connect_query_tooltip(|lw, x, y, _keyboard, tooltip| catch_panic! {
+            @default(false);
+            
+            match lw.imp().interior.get().unwrap().read().describe_byte_at(x as f64, y as f64) {
+                Some(description) => {
+                    tooltip.set_text(Some(&description));
+                    true
+                },
+                None => false,
+            }
+        });
+
         /* Context menu */
         let ec_context_menu = gtk::GestureClick::new();
         ec_context_menu.connect_pressed(clone!(#[weak(rename_to=lw)] self, move |gesture, _n_press, x, y| catch_panic! {
@@ -466,6 +491,20 @@ impl ListingWidget {
         self.imp().init(interior);
     }
 
+    pub fn mode(&self) -> facet::cursor::Mode {
+        self.imp().interior.get().unwrap().read().cursor.mode
+    }
+
+    pub fn set_mode(&self, mode: facet::cursor::Mode) {
+        self.imp().interior.get().unwrap().write().cursor.change_mode(mode);
+        self.queue_draw();
+    }
+
+    pub fn set_insert(&self, insert: bool) {
+        self.imp().interior.get().unwrap().write().cursor.change_insert(insert);
+        self.queue_draw();
+    }
+    
     pub fn set_cursor_hidden(&self, cursor_hidden: bool) {
         self.imp().interior.get().unwrap().write().cursor.hidden = cursor_hidden;
     }
@@ -497,6 +536,19 @@ impl ListingWidget {
         self.imp().interior.get().unwrap().write().animate(self, frame_clock)
     }
     
+    /// Throws away the data every line has fetched and fetches it again, for when the data behind the document changed
+    /// without the document itself changing (e.g. the file we loaded got rewritten).
+    pub fn refetch(&self) {
+        let mut interior = self.imp().interior.get().unwrap().write();
+
+        for line in interior.window.line_views.iter_mut() {
+            line.invalidate_data();
+        }
+
+        interior.request_work();
+        self.queue_draw();
+    }
+
     pub fn bonk(&self) {
         self.imp().interior.get().unwrap().write().cursor.bonk();
     }
@@ -608,6 +660,24 @@ impl RenderDetail {
             addr_pane_width,
         }
     }
+
+    /// Bytes that couldn't be read get their own color, depending on why.
+    fn byte_color(&self, record: &datapath::ByteRecord) -> &gdk::RGBA {
+        match record.error {
+            Some(datapath::ByteError::Io { .. } | datapath::ByteError::Decompression(_)) => self.config.error_color.rgba(),
+            Some(datapath::ByteError::Unmapped | datapath::ByteError::EndOfSpace) => self.config.unmapped_color.rgba(),
+            None => self.config.text_color.rgba(),
+        }
+    }
+}
+
+/// What to draw in place of each digit (or character) of a byte that couldn't be read.
+fn error_glyph(error: datapath::ByteError) -> gsc::Entry {
+    match error {
+        datapath::ByteError::Io { .. } | datapath::ByteError::Decompression(_) => gsc::Entry::PrintableAscii(b'!'),
+        datapath::ByteError::Unmapped => gsc::Entry::PrintableAscii(b'-'),
+        datapath::ByteError::EndOfSpace => gsc::Entry::Space,
+    }
 }
 
 impl Interior {
@@ -699,6 +769,10 @@ impl Interior {
 
         work_needed = self.document.datapath.poll(cx) || work_needed;
         
+        if let Some(hint) = self.prefetch_hint() {
+            self.document.datapath.prefetch(&hint, cx);
+        }
+        
         for line in self.window.line_views.iter_mut() {
             work_needed = line.work(&self.document, cx) || work_needed;
 
@@ -720,6 +794,31 @@ impl Interior {
         self.work_incomplete = work_needed;
     }
 
+    /// Works out which part of the datapath the window covers, so the datapath can start fetching whatever we're going
+    /// to scroll into next.
+    fn prefetch_hint(&self) -> Option<datapath::PrefetchHint> {
+        let (begin, end) = self.window.line_views.iter()
+            .flat_map(|line| line.iter_tokens())
+            .map(|token| token.absolute_extent())
+            .filter(|extent| !extent.is_empty())
+            .map(|extent| extent.round_out())
+            .fold(None, |range, (addr, size)| match range {
+                None => Some((addr, addr + size)),
+                Some((begin, end)) => Some((std::cmp::min(begin, addr), std::cmp::max(end, addr + size))),
+            })?;
+
+        /* The window already reaches `lookahead` lines past what's on screen, so read ahead by about that much more. */
+        let lines = std::cmp::max(self.window.line_views.len(), 1) as u64;
+        let distance = (end - begin) / lines * self.scroll.get_lookahead() as u64;
+        
+        Some(datapath::PrefetchHint {
+            addr: begin,
+            size: end - begin,
+            direction: self.scroll.get_direction(),
+            distance,
+        })
+    }
+
     fn collect_events(&mut self, widget: &ListingWidget) {
         let _circumstances = crashreport::circumstances([
             crashreport::Circumstance::InWindow(self.charm_window_id),
@@ -797,8 +896,33 @@ impl Interior {
             (gdk::Key::Page_Up,   false, false) => { self.scroll.page_up(&self.window); glib::Propagation::Stop },
             (gdk::Key::Page_Down, false, false) => { self.scroll.page_down(&self.window); glib::Propagation::Stop },
             
-            //_ => self.cursor_transaction_fallible(|c| c.entry(&document_host, ek), facet::scroll::EnsureCursorInViewDirection::Any),
-            _ => glib::Propagation::Proceed,
+            _ => {
+                let document_host = self.document_host.clone();
+                let key = cursor::key::Key::new(keyval, modifier);
+                let mut entry_error = None;
+                
+                let r = self.cursor_transaction_fallible(|c| match c.entry(&document_host, &key) {
+                    Ok(consumed) => consumed,
+                    Err(e) => {
+                        entry_error = Some(e);
+                        true
+                    },
+                }, facet::scroll::EnsureCursorInViewDirection::Any);
+
+                if let (Some((error, attempted_version)), Some(window)) = (entry_error, self.charm_window.upgrade()) {
+                    window.report_error(error::Error {
+                        while_attempting: error::Action::EnterData,
+                        trouble: error::Trouble::DocumentUpdateFailure {
+                            error,
+                            attempted_version,
+                        },
+                        level: error::Level::Error,
+                        is_bug: false,
+                    });
+                }
+
+                r
+            },
         };
 
         self.collect_events(widget);
@@ -977,6 +1101,34 @@ impl Interior {
         let line = self.window.line_views.get(lineno)?;
         line.pick(x, y)
     }
+
+    /// Describes which filters the byte at the given coordinates came through and, if it couldn't be read, why.
+    fn describe_byte_at(&self, x: f64, y: f64) -> Option<string::String> {
+        let (path, offset) = match self.pick(x, y)? {
+            pick::Triplet {
+                middle: (path, pick::Part::Hexdump { offset, .. }),
+                ..
+            } => (path, offset),
+            
+            _ => return None,
+        };
+
+        let (_, node_addr) = self.document.lookup_node(&path);
+
+        /* The byte is on screen, so it should be in the cache already, but just in case it isn't, fetching needs the
+         * runtime. */
+        let _guard = self.runtime.enter();
+        let addr = (node_addr + offset.to_size()).byte;
+        let mut record = [datapath::ByteRecord::default()];
+        self.document.datapath.fetch(datapath::ByteRecordRange::new(addr, &mut record), &mut task::Context::from_waker(futures::task::noop_waker_ref()));
+
+        let provenance = datapath::provenance::describe(&self.document.datapath, &self.document.datapath.provenance(addr));
+
+        Some(match record[0].error {
+            Some(error) => format!("{}\n\n{}", error.describe(), provenance),
+            None => provenance,
+        })
+    }
 }
 
 impl future::Future for ListingWidgetWorkFuture {
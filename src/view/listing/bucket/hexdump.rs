@@ -192,9 +192,13 @@ impl bucket::Bucket for HexdumpBucket {
                         let nybble = if low_nybble { byte_record.value & 0xf } else { byte_record.value >> 4 };
                         let has_cursor = hex_cursor.map_or(false, |hxc| sync::Arc::ptr_eq(&hxc.token.common.node, &self.node) && hxc.extent.begin + hxc.offset == offset && hxc.low_nybble == low_nybble);
                         
-                        let digit = if pending { gsc::Entry::Space } else { gsc::Entry::Digit(nybble) };
+                        let digit = match byte_record.error {
+                            _ if pending => gsc::Entry::Space,
+                            Some(error) => listing::error_glyph(error),
+                            None => gsc::Entry::Digit(nybble),
+                        };
 
-                        ctx.render.gsc_mono.begin(digit, ctx.render.config.text_color.rgba(), &mut octet_point)
+                        ctx.render.gsc_mono.begin(digit, ctx.render.byte_color(&byte_record), &mut octet_point)
                             .selected(selected, ctx.render.config.selection_color.rgba())
                             .cursor(has_cursor, ctx.cursor, ctx.render.config.cursor_fg_color.rgba(), ctx.render.config.cursor_bg_color.rgba())
                             .placeholder(pending, ctx.render.config.placeholder_color.rgba())
@@ -231,11 +235,15 @@ impl bucket::Bucket for HexdumpBucket {
                             let selected = selection.includes(byte_extent.begin);
                             let pending = byte_record.pending || !byte_record.loaded;
                     
-                            let digit = if pending { gsc::Entry::Space } else { gsc::Entry::PrintableAscii(byte_record.value) };
+                            let digit = match byte_record.error {
+                                _ if pending => gsc::Entry::Space,
+                                Some(error) => listing::error_glyph(error),
+                                None => gsc::Entry::PrintableAscii(byte_record.value),
+                            };
 
                             let mut char_point = graphene::Point::new(x + space_width * i as f32, lh);
                         
-                            ctx.render.gsc_mono.begin(digit, ctx.render.config.text_color.rgba(), &mut char_point)
+                            ctx.render.gsc_mono.begin(digit, ctx.render.byte_color(&byte_record), &mut char_point)
                                 .selected(selected, ctx.render.config.selection_color.rgba())
                                 .placeholder(pending, ctx.render.config.placeholder_color.rgba())
                                 .render(ctx.snapshot);
@@ -149,25 +149,25 @@ impl CursorView {
         self.cursor.goto(document, path, offset, hint)
     }
     
-    /*
-    pub fn entry(&mut self, document_host: &document::DocumentHost, key: &gdk::EventKey) -> bool {
-        match match self.mode {
-            Mode::Command => return false,
+    /// Returns Ok(true) if the key was consumed by data entry, or Ok(false) if it should be handled by something else.
+    pub fn entry(&mut self, document_host: &document::DocumentHost, key: &cursor::key::Key) -> Result<bool, (document::change::ApplyError, sync::Arc<document::Document>)> {
+        let result = match self.mode {
+            Mode::Command => return Ok(false),
             Mode::Entry => {
-                self.events.want_draw();
-                self.cursor.enter_standard(document_host, self.insert, &cursor::key::Key::from(key))
+                self.ev_draw.want();
+                self.cursor.enter_standard(document_host, self.insert, key)
             },
-            Mode::TextEntry => {
-                self.events.want_draw();
-                self.cursor.enter_utf8(document_host, self.insert, &cursor::key::Key::from(key))
-            },
-        } {
-            Ok(cursor::MovementResult::Ok) => { self.blink(); true },
-            Err(cursor::EntryError::KeyNotRecognized) => { self.blink(); false },
-            _ => { self.bonk(); true }
+            // TODO: text entry
+            Mode::TextEntry => return Ok(false),
+        };
+        
+        match result {
+            Ok(cursor::MovementResult::Ok) => { self.blink(); Ok(true) },
+            Err(cursor::EntryError::KeyNotRecognized) => { self.blink(); Ok(false) },
+            Err(cursor::EntryError::DocumentUpdateFailure { error, attempted_version }) => { self.bonk(); Err((error, attempted_version)) },
+            _ => { self.bonk(); Ok(true) }
         }
-}
-    */
+    }
 }
 
 impl facet::Facet for CursorView {
@@ -6,6 +6,7 @@ use crate::model::document;
 use crate::model::document::structure;
 use crate::model::listing::window;
 use crate::model::listing::window::LineView;
+use crate::model::space;
 use crate::view::listing;
 use crate::view::listing::facet;
 use crate::view::listing::helpers;
@@ -27,6 +28,7 @@ pub struct Scroller {
     bonked_bottom: bool,
     cursor_spring: bool,
     cursor_direction: EnsureCursorInViewDirection,
+    direction: space::cache::Direction,
 }
 
 pub enum ScrollUnit {
@@ -79,6 +81,7 @@ impl Scroller {
             bonked_bottom: false,
             cursor_spring: false,
             cursor_direction: EnsureCursorInViewDirection::Any,
+            direction: space::cache::Direction::Forward,
         }
     }
 
@@ -93,6 +96,11 @@ impl Scroller {
     pub fn get_lookahead(&self) -> usize {
         self.config.lookahead
     }
+
+    /// Which way the window last moved.
+    pub fn get_direction(&self) -> space::cache::Direction {
+        self.direction
+    }
     
     pub fn scroll_wheel_impulse(&mut self, delta: f64, kinetic_allowed: bool) {
         if delta < 0.0 && self.bonked_top {
@@ -154,6 +162,7 @@ impl Scroller {
                 if window.scroll_down() {
                     self.position-= 1.0;
                     amt-= 1;
+                    self.direction = space::cache::Direction::Forward;
                     
                     /* request work on behalf of the new lines that need to load their content. */
                     self.ev_work.want();
@@ -174,6 +183,7 @@ impl Scroller {
                 if window.scroll_up() {
                     self.position+= 1.0;
                     amt-= 1;
+                    self.direction = space::cache::Direction::Backward;
 
                     /* request work on behalf of the new lines that need to load their content. */
                     self.ev_work.want();
@@ -187,6 +187,11 @@ impl Line {
         self.render_node = None;
     }
 
+    /// Forces the line to fetch its data again the next time it works, even if the document hasn't changed.
+    pub fn invalidate_data(&mut self) {
+        self.current_document = None;
+    }
+
     pub fn render(&mut self, cursor: &facet::cursor::CursorView, selection: &selection::listing::Mode, render: &listing::RenderDetail) -> Option<gsk::RenderNode> {
         /* check if the cursor is on any of the tokens on this line */
         let has_cursor = self.ty.contains_cursor(&cursor.cursor);
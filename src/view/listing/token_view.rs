@@ -164,15 +164,18 @@ impl TokenView {
                     let byte_record = self.data_cache.get(i as usize).copied().unwrap_or_default();
                     let pending = byte_record.pending || !byte_record.loaded;
 
-                    let digit_hi = if pending { gsc::Entry::Space } else { gsc::Entry::Digit((byte_record.value & 0xf0) >> 4) };
-                    let digit_lo = if pending { gsc::Entry::Space } else { gsc::Entry::Digit( byte_record.value & 0x0f      ) };
+                    let (digit_hi, digit_lo) = match byte_record.error {
+                        _ if pending => (gsc::Entry::Space, gsc::Entry::Space),
+                        Some(error) => (listing::error_glyph(error), listing::error_glyph(error)),
+                        None => (gsc::Entry::Digit((byte_record.value & 0xf0) >> 4), gsc::Entry::Digit(byte_record.value & 0x0f)),
+                    };
                     
                     let byte_extent = addr::Extent::sized(i.into(), addr::unit::BYTE).intersection(token.extent);
                     let selected = byte_extent.map_or(false, |be| selection.includes(be.begin));
                     
                     render.gsc_mono.begin_iter([
                         digit_hi, digit_lo
-                    ].into_iter(), render.config.text_color.rgba(), &mut pos)
+                    ].into_iter(), render.byte_color(&byte_record), &mut pos)
                         .selected(selected, render.config.selection_color.rgba())
                         .placeholder(pending, render.config.placeholder_color.rgba())
                     // TODO: cursor for hexstring
@@ -18,6 +18,7 @@ pub mod listing;
 pub mod project;
 pub mod props_editor;
 pub mod selection;
+pub mod watcher;
 
 use std::cell;
 use std::rc;
@@ -74,7 +75,10 @@ impl CharmApplication {
         app.application.set_accels_for_action("win.open", &["<Ctrl>O"]);
         app.application.set_accels_for_action("win.save_project", &["<Ctrl>S"]);
         app.application.set_accels_for_action("win.cancel", &["Escape"]);
-        app.application.set_accels_for_action("ctx.debug.revert_document(uint32 1)", &["<Ctrl>Z"]);
+        app.application.set_accels_for_action("ctx.undo", &["<Ctrl>Z"]);
+        app.application.set_accels_for_action("ctx.redo", &["<Ctrl><Shift>Z", "<Ctrl>Y"]);
+        app.application.set_accels_for_action("ctx.mode::entry", &["<Ctrl>E"]);
+        app.application.set_accels_for_action("ctx.insert_mode", &["<Ctrl>I"]);
 
         /* css */
         let css = gtk::CssProvider::new();
@@ -1,7 +1,9 @@
 use std::cell;
 use std::sync;
 
+use crate::model::datapath;
 use crate::model::document;
+use crate::model::document::undo;
 use crate::model::versioned::Versioned;
 use crate::serialization;
 
@@ -13,6 +15,7 @@ pub struct Project {
     pub document_host: sync::Arc<document::DocumentHost>,
     pub save_file: cell::RefCell<Option<gio::File>>,
     pub last_save: cell::RefCell<Option<sync::Arc<document::Document>>>,
+    pub undo_history: cell::RefCell<undo::History>,
 }
 
 pub enum SaveProjectError {
@@ -35,22 +38,26 @@ impl From<serialization::SerializationError> for SaveProjectError {
 impl Project {
     pub fn new_unsaved(doc: document::Document) -> Self {
         let document_host = sync::Arc::new(document::DocumentHost::new(doc));
+        let undo_history = undo::History::new(document_host.get());
 
         Project {
             document_host,
             save_file: cell::RefCell::new(None),
             last_save: cell::RefCell::new(None),
+            undo_history: cell::RefCell::new(undo_history),
         }
     }
 
     pub fn new_from_save(doc: document::Document, file: gio::File) -> Self {
         let document_host = sync::Arc::new(document::DocumentHost::new(doc));
         let last_save = document_host.get();
+        let undo_history = undo::History::new(last_save.clone());
         
         Project {
             document_host,
             save_file: cell::RefCell::new(Some(file)),
             last_save: cell::RefCell::new(Some(last_save)),
+            undo_history: cell::RefCell::new(undo_history),
         }
     }
 
@@ -63,8 +70,13 @@ impl Project {
     
     pub fn try_save_to(&self, file: gio::File) -> Result<(), SaveProjectError> {
         let document = self.document_host.get();
+
+        /* Undo history doesn't get saved, so there's no reason to keep every little edit around in the file. The open
+         * document keeps its datapath as it is, since undoing edits expects to find their filters. */
+        let mut compacted = (*document).clone();
+        compacted.datapath = datapath::compact::compact(&document.datapath);
         
-        let bytes = serialization::serialize_project(&*document)?;
+        let bytes = serialization::serialize_project(&compacted)?;
 
         file.replace_contents(&bytes[..], None, false, gio::FileCreateFlags::REPLACE_DESTINATION, gio::Cancellable::NONE)?;
 
@@ -89,10 +101,14 @@ impl Project {
             }
         }
 
+        let document_host = sync::Arc::new(document::DocumentHost::new((**document).clone()));
+        let undo_history = undo::History::new(document_host.get());
+        
         Project {
-            document_host: sync::Arc::new(document::DocumentHost::new((**document).clone())),
+            document_host,
             save_file: self.save_file.clone(),
             last_save: self.last_save.clone(),
+            undo_history: cell::RefCell::new(undo_history),
         }
     }
 
@@ -0,0 +1,176 @@
+use std::cell;
+use std::rc;
+use std::sync;
+use std::vec;
+
+use gtk::gio;
+use gtk::glib;
+use gtk::glib::clone;
+use gtk::prelude::*;
+
+use crate::catch_panic;
+use crate::model::datapath;
+use crate::model::document;
+use crate::model::space;
+use crate::model::space::AddressSpaceExt;
+use crate::view::config;
+use crate::view::error;
+use crate::view::helpers;
+use crate::view::listing;
+use crate::view::window;
+use crate::view::window::ErrorReporter;
+
+/// How long to wait for a file to stop changing before we go look at it. Programs tend to write files in several steps,
+/// and we don't want to reload (and bother the user) for each one.
+const SETTLE_TIME: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Watches the files behind the address spaces in a document's datapath. When one of them gets modified or replaced,
+/// the space reopens it (unless snapshots are locked), the listing fetches its data again, and the user is told.
+pub struct FileWatcher {
+    interior: rc::Rc<Interior>,
+    _document_subscriber: helpers::AsyncSubscriber,
+}
+
+struct Interior {
+    window: rc::Weak<window::CharmWindow>,
+    lw: listing::ListingWidget,
+    document: cell::RefCell<sync::Arc<document::Document>>,
+
+    /* GIO watches with inotify where it can and falls back to polling where it can't, so we don't have to. */
+    monitors: cell::RefCell<vec::Vec<(std::path::PathBuf, gio::FileMonitor)>>,
+    pending_check: cell::RefCell<Option<glib::SourceId>>,
+}
+
+impl FileWatcher {
+    pub fn new(window: &rc::Rc<window::CharmWindow>, document_host: sync::Arc<document::DocumentHost>, lw: &listing::ListingWidget) -> FileWatcher {
+        let document = document_host.get();
+
+        let interior = rc::Rc::new(Interior {
+            window: rc::Rc::downgrade(window),
+            lw: lw.clone(),
+            document: cell::RefCell::new(document.clone()),
+
+            monitors: Default::default(),
+            pending_check: Default::default(),
+        });
+
+        interior.update_monitors();
+
+        let document_subscriber = helpers::subscribe_to_updates(rc::Rc::downgrade(&interior), document_host, document, |interior, new_document| {
+            *interior.document.borrow_mut() = new_document.clone();
+            interior.update_monitors();
+        });
+
+        FileWatcher {
+            interior,
+            _document_subscriber: document_subscriber,
+        }
+    }
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        for (_, monitor) in self.interior.monitors.borrow_mut().drain(..) {
+            monitor.cancel();
+        }
+
+        if let Some(source) = self.interior.pending_check.borrow_mut().take() {
+            source.remove();
+        }
+    }
+}
+
+/// Every distinct address space loaded anywhere in the datapath.
+fn spaces(datapath: &datapath::DataPath) -> vec::Vec<sync::Arc<space::AddressSpace>> {
+    let mut spaces: vec::Vec<sync::Arc<space::AddressSpace>> = vec::Vec::new();
+
+    for filter in datapath.iter() {
+        if let datapath::Filter::LoadSpace(lsf) = filter.inner() {
+            if !spaces.iter().any(|space| sync::Arc::ptr_eq(space, lsf.space())) {
+                spaces.push(lsf.space().clone());
+            }
+        }
+    }
+
+    spaces
+}
+
+impl Interior {
+    /// Starts watching any files that were added to the datapath and stops watching ones that were removed.
+    fn update_monitors(self: &rc::Rc<Self>) {
+        let document = self.document.borrow().clone();
+        let spaces = spaces(&document.datapath);
+
+        let mut paths: vec::Vec<std::path::PathBuf> = spaces.iter().flat_map(|space| space.backing_files()).map(|path| path.to_path_buf()).collect();
+        paths.sort();
+        paths.dedup();
+
+        let mut monitors = self.monitors.borrow_mut();
+
+        monitors.retain(|(path, monitor)| {
+            let keep = paths.contains(path);
+            if !keep {
+                monitor.cancel();
+            }
+            keep
+        });
+
+        for path in paths {
+            if monitors.iter().any(|(watched, _)| *watched == path) {
+                continue;
+            }
+
+            /* not being able to watch a file isn't worth bothering the user about */
+            let Ok(monitor) = gio::File::for_path(&path).monitor_file(gio::FileMonitorFlags::NONE, gio::Cancellable::NONE) else { continue };
+
+            monitor.connect_changed(clone!(#[weak(rename_to = interior)] self, move |_, _, _, event| catch_panic! {
+                if event != gio::FileMonitorEvent::AttributeChanged {
+                    interior.schedule_check();
+                }
+            }));
+
+            monitors.push((path, monitor));
+        }
+    }
+
+    fn schedule_check(self: &rc::Rc<Self>) {
+        if let Some(source) = self.pending_check.borrow_mut().take() {
+            source.remove();
+        }
+
+        *self.pending_check.borrow_mut() = Some(glib::timeout_add_local_once(SETTLE_TIME, clone!(#[weak(rename_to = interior)] self, move || catch_panic! {
+            interior.pending_check.borrow_mut().take();
+            interior.check();
+        })));
+    }
+
+    fn check(&self) {
+        let document = self.document.borrow().clone();
+        let reload = !config::INSTANCE.borrow().lock_snapshots;
+
+        let labels: vec::Vec<String> = spaces(&document.datapath).into_iter()
+            .filter(|space| space.check_for_changes(reload))
+            .map(|space| space.get_label().to_string())
+            .collect();
+
+        if labels.is_empty() {
+            return;
+        }
+
+        if reload {
+            self.lw.refetch();
+        }
+
+        if let Some(window) = self.window.upgrade() {
+            window.report_error(error::Error {
+                while_attempting: error::Action::WatchBackingFiles,
+                trouble: error::Trouble::BackingFilesChanged {
+                    labels,
+                    reloaded: reload,
+                },
+                level: if reload { error::Level::Informational } else { error::Level::Warning },
+                is_bug: false,
+            });
+        }
+    }
+}
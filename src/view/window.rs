@@ -19,6 +19,7 @@ use crate::view::hierarchy;
 use crate::view::selection;
 use crate::view::project;
 use crate::view::props_editor;
+use crate::view::watcher;
 
 use gtk::gio;
 use gtk::glib;
@@ -61,6 +62,7 @@ pub struct WindowContext {
     document_subscriber_for_debug_revert_menu_update: helpers::AsyncSubscriber,
     document_subscriber_for_title_update: helpers::AsyncSubscriber,
     datapath_subscriber: helpers::AsyncSubscriber,
+    file_watcher: watcher::FileWatcher,
 }
 
 static NEXT_WINDOW_ID: sync::atomic::AtomicU64 = sync::atomic::AtomicU64::new(1);
@@ -94,30 +96,48 @@ impl CharmWindow {
                     let new_project_menu = gio::Menu::new();
                     new_project_menu.append(Some("Empty"), Some("win.new_project.empty"));
                     new_project_menu.append(Some("From File..."), Some("win.new_project.from_file"));
+                    new_project_menu.append(Some("From Concatenated Files..."), Some("win.new_project.from_files_concatenated"));
+                    new_project_menu.append(Some("From Process..."), Some("win.new_project.from_process"));
+                    new_project_menu.append(Some("From GDB Target..."), Some("win.new_project.from_gdb"));
                     new_project_menu.freeze();
                     file_menu.append_submenu(Some("New Project"), &new_project_menu);
                 }
                 file_menu.append(Some("Open Project..."), Some("win.open_project"));
                 file_menu.append(Some("Save Project"), Some("win.save_project"));
                 file_menu.append(Some("Save Project As..."), Some("win.save_project_as"));
+                file_menu.append(Some("Export Data..."), Some("ctx.export_data"));
+                file_menu.append(Some("Export Patch..."), Some("ctx.export_patch"));
+                file_menu.append(Some("Import Patch..."), Some("ctx.import_patch"));
+                file_menu.append(Some("Save Edits to File"), Some("ctx.save_in_place"));
                 file_menu.freeze();
                 menu_bar.append_submenu(Some("File"), &file_menu);
             }
             {
                 let edit_menu = gio::Menu::new();
-                edit_menu.append(Some("Goto..."), Some("ctx.goto"));
-                edit_menu.append(Some("Settings..."), Some("win.settings"));
-                /*
+                {
+                    let history_menu = gio::Menu::new();
+                    history_menu.append(Some("Undo"), Some("ctx.undo"));
+                    history_menu.append(Some("Redo"), Some("ctx.redo"));
+                    history_menu.freeze();
+                    edit_menu.append_section(None, &history_menu);
+                }
+                {
+                    let misc_menu = gio::Menu::new();
+                    misc_menu.append(Some("Goto..."), Some("ctx.goto"));
+                    misc_menu.append(Some("Compact Datapath"), Some("ctx.datapath.compact"));
+                    misc_menu.append(Some("Settings..."), Some("win.settings"));
+                    misc_menu.freeze();
+                    edit_menu.append_section(None, &misc_menu);
+                }
                 {
                     let mode_menu = gio::Menu::new();
-                    mode_menu.append(Some("Command mode"), Some("listing.mode::command"));
-                    mode_menu.append(Some("Hex entry"), Some("listing.mode::entry"));
-                    mode_menu.append(Some("Text entry (UTF8)"), Some("listing.mode::utf8"));
-                    mode_menu.append(Some("Insert"), Some("listing.insert_mode"));
-                    edit_menu.append_section(Some("Edit Mode"), &mode_menu);
+                    mode_menu.append(Some("Command mode"), Some("ctx.mode::command"));
+                    mode_menu.append(Some("Hex entry"), Some("ctx.mode::entry"));
+                    /* TODO: text entry (UTF8) */
+                    mode_menu.append(Some("Insert"), Some("ctx.insert_mode"));
                     mode_menu.freeze();
-            }
-                */
+                    edit_menu.append_section(Some("Edit Mode"), &mode_menu);
+                }
                 edit_menu.freeze();
                 menu_bar.append_submenu(Some("Edit"), &edit_menu);
             }
@@ -147,6 +167,7 @@ impl CharmWindow {
                 let debug_menu = gio::Menu::new();
                 debug_menu.append(Some("Reset UI"), Some("win.debug.reopen_current_project"));
                 debug_menu.append_submenu(Some("Revert document"), &debug_revert_menu);
+                debug_menu.append(Some("Cache statistics"), Some("ctx.debug.cache_statistics"));
                 debug_menu.append(Some("Crash"), Some("app.crash"));
                 debug_menu.freeze();
                 menu_bar.append_submenu(Some("Debug"), &debug_menu);
@@ -158,40 +179,7 @@ impl CharmWindow {
         }
 
         let datapath_editor: gtk::TreeView = builder.object("datapath_editor").unwrap();
-        {
-            {
-                let crt = gtk::CellRendererText::new();
-                let c = gtk::TreeViewColumn::new();
-                c.pack_start(&crt, true);
-                c.add_attribute(&crt, "text", 0);
-                c.set_title("Type");
-                datapath_editor.append_column(&c);
-            }
-            {
-                let crt = gtk::CellRendererText::new();
-                let c = gtk::TreeViewColumn::new();
-                c.pack_start(&crt, true);
-                c.add_attribute(&crt, "text", 1);
-                c.set_title("Address");
-                datapath_editor.append_column(&c);
-            }
-            {
-                let crt = gtk::CellRendererText::new();
-                let c = gtk::TreeViewColumn::new();
-                c.pack_start(&crt, true);
-                c.add_attribute(&crt, "text", 2);
-                c.set_title("Size");
-                datapath_editor.append_column(&c);
-            }
-            {
-                let crt = gtk::CellRendererText::new();
-                let c = gtk::TreeViewColumn::new();
-                c.pack_start(&crt, true);
-                c.add_attribute(&crt, "text", 3);
-                c.set_title("Description");
-                datapath_editor.append_column(&c);
-            }
-        }
+        view::datapath::build_columns(&datapath_editor);
 
         let hierarchy_editor: gtk::ColumnView = builder.object("hierarchy_editor").unwrap();
         {
@@ -313,9 +301,45 @@ impl CharmWindow {
             ctx.lw.goto(&info.document, &info.path, addr::unit::NULL, cursor::PlacementHint::Title);
         }));
         
+        w.datapath_editor.selection().connect_changed(clone!(#[weak] w, move |selection| catch_panic! {
+            /* this fires while the context is being swapped out and the model taken away */
+            let Ok(guard) = w.context.try_borrow() else { return };
+            let Some(ctx) = guard.as_ref() else { return };
+            let Some((model, iter)) = selection.selected() else { return };
+
+            let document = ctx.project.document_host.get();
+            let Some(extent) = view::datapath::filter_extent(&document, model.get::<u64>(&iter, 5) as usize) else { return };
+
+            if let Err((error, attempted_version)) = ctx.listing_selection_host.change(selection_model::listing::Change::AssignAddress(extent)) {
+                w.report_error(error::Error {
+                    while_attempting: error::Action::SelectFilterInListing,
+                    trouble: error::Trouble::ListingSelectionUpdateFailure {
+                        error,
+                        attempted_version,
+                    },
+                    level: error::Level::Warning,
+                    is_bug: true,
+                });
+            }
+        }));
+
+        w.datapath_editor.connect_row_activated(clone!(#[weak] w, move |tv, path, _| catch_panic! {
+            let guard = w.context.borrow();
+            let Some(ctx) = guard.as_ref() else { return };
+            let Some(index) = view::datapath::filter_index(tv, path) else { return };
+
+            let document = ctx.project.document_host.get();
+            let Some(extent) = view::datapath::filter_extent(&document, index as usize) else { return };
+            let Some((path, offset)) = document.insertion_point(extent.begin) else { return };
+
+            ctx.lw.goto(&document, &path, offset, cursor::PlacementHint::Unused);
+        }));
+
         /* window actions */
 
         action::new_project::add_actions(&w);
+        action::new_project_from_process::add_action(&w);
+        action::new_project_from_gdb::add_action(&w);
         action::save_project::add_actions(&w);
         action::open_project::add_action(&w);
         action::debug::reopen_current_project::add_action(&w);
@@ -511,6 +535,7 @@ impl WindowContext {
         
         let (datapath_model, datapath_subscriber) = view::datapath::create_model(document_host.clone());
         let tree_selection_model = selection::TreeSelectionModel::new(window.clone() as rc::Rc<dyn ErrorReporter>, tree_selection_host.clone(), document_host.clone());
+        let file_watcher = watcher::FileWatcher::new(window, document_host.clone(), &lw);
         
         let wc = WindowContext {
             window: rc::Rc::downgrade(window),
@@ -529,8 +554,11 @@ impl WindowContext {
             document_subscriber_for_debug_revert_menu_update,
             document_subscriber_for_title_update,
             datapath_subscriber,
+            file_watcher,
         };
 
+        action::datapath::add_actions(&wc);
+        action::listing::delete_selected_bytes::add_action(&wc);
         action::listing::delete_selected_nodes::add_action(&wc);
         action::listing::insert_node::add_actions(&wc);
         action::listing::insert_node::add_insert_fixed_size_node_at_cursor_action(&wc, "byte", 1);
@@ -538,9 +566,15 @@ impl WindowContext {
         action::listing::insert_node::add_insert_fixed_size_node_at_cursor_action(&wc, "dword", 4);
         action::listing::insert_node::add_insert_fixed_size_node_at_cursor_action(&wc, "qword", 8);
         action::listing::goto::add_action(&wc);
+        action::listing::mode::add_actions(&wc);
         action::tree::delete_node::add_action(&wc);
         action::tree::nest::add_action(&wc);
         action::tree::destructure::add_action(&wc);
+        action::edit::undo::add_actions(&wc);
+        action::export::add_actions(&wc);
+        action::import_patch::add_action(&wc);
+        action::save_in_place::add_action(&wc);
+        action::debug::cache_statistics::add_action(&wc);
         action::debug::revert_document::add_action(&wc);
         
         wc